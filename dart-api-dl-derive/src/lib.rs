@@ -0,0 +1,406 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Derive macros for `xayn-dart-api-dl`, use them through its `derive` feature.
+#![deny(
+    clippy::pedantic,
+    noop_method_call,
+    rust_2018_idioms,
+    rust_2021_compatibility,
+    unused_qualifications
+)]
+#![warn(missing_docs, unreachable_pub)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Literal, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input,
+    punctuated::Punctuated,
+    token::Comma,
+    Data,
+    DeriveInput,
+    Error,
+    Fields,
+    Type,
+};
+
+/// The maximum number of fields, limited by the tuples supported by `decode_tuple`.
+const MAX_FIELDS: usize = 8;
+
+/// Derives `DartMessage` and `DartSource` for a struct with named fields.
+///
+/// The struct must have 1 to 8 fields. The message is encoded as array of the
+/// fields in declaration order. The supported field types are:
+///
+/// | rust       | dart           |
+/// |------------|----------------|
+/// | `bool`     | `bool`         |
+/// | `i64`      | `int`          |
+/// | `f64`      | `double`       |
+/// | `String`   | `String`       |
+/// | `Vec<u8>`  | `Uint8List`    |
+/// | `Vec<i64>` | `List<int>`    |
+/// | `Vec<f64>` | `List<double>` |
+#[proc_macro_derive(DartMessage)]
+pub fn derive_dart_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `TryFrom<Struct> for CObject` for a struct with named fields.
+///
+/// The struct is encoded as array of the fields in declaration order, each
+/// field is encoded with `IntoCObjectElement`, e.g. `None` is encoded as null.
+/// Encoding fails with a `NulError` if a string contains a nul byte, as dart
+/// strings are passed nul terminated.
+/// The struct itself implements `IntoCObjectElement` too, so it can be nested
+/// in other structs.
+#[proc_macro_derive(IntoCObject)]
+pub fn derive_into_cobject(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_into_cobject(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `TryFrom<WithRuntime<'_, '_>>` for a struct with named fields.
+///
+/// This decodes the encoding of `#[derive(IntoCObject)]`, each field is decoded
+/// with `FromCObjectElement`, e.g. null is decoded as `None`. Decoding fails with
+/// a `DecodeError` if the array has the wrong length or if an element has the
+/// wrong type. The struct itself implements `FromCObjectElement` too, so it can
+/// be nested in other structs.
+#[proc_macro_derive(FromCObject)]
+pub fn derive_from_cobject(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_cobject(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// A supported field of a message.
+struct Field {
+    name: Ident,
+    kind: FieldKind,
+}
+
+#[derive(Clone, Copy)]
+enum FieldKind {
+    Bool,
+    Int,
+    Double,
+    String,
+    Bytes,
+    IntList,
+    DoubleList,
+}
+
+impl FieldKind {
+    fn from_type(ty: &Type) -> Option<Self> {
+        let ty = quote!(#ty).to_string().replace(' ', "");
+        Some(match ty.as_str() {
+            "bool" => FieldKind::Bool,
+            "i64" => FieldKind::Int,
+            "f64" => FieldKind::Double,
+            "String" => FieldKind::String,
+            "Vec<u8>" => FieldKind::Bytes,
+            "Vec<i64>" => FieldKind::IntList,
+            "Vec<f64>" => FieldKind::DoubleList,
+            _ => return None,
+        })
+    }
+
+    fn dart_type(self) -> &'static str {
+        match self {
+            FieldKind::Bool => "bool",
+            FieldKind::Int => "int",
+            FieldKind::Double => "double",
+            FieldKind::String => "String",
+            FieldKind::Bytes => "Uint8List",
+            FieldKind::IntList => "List<int>",
+            FieldKind::DoubleList => "List<double>",
+        }
+    }
+
+    fn dart_decode(self, value: &str) -> String {
+        match self {
+            FieldKind::IntList => format!("({value} as List<Object?>).cast<int>()"),
+            FieldKind::DoubleList => format!("({value} as List<Object?>).cast<double>()"),
+            _ => format!("{value} as {}", self.dart_type()),
+        }
+    }
+}
+
+/// Returns the fields of a non-generic struct with named fields.
+fn named_fields<'a>(
+    input: &'a DeriveInput,
+    derive: &str,
+) -> syn::Result<&'a Punctuated<syn::Field, Comma>> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    format!("{derive} requires a struct with named fields"),
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                format!("{derive} can only be derived for structs"),
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            format!("{derive} can't be derived for generic structs"),
+        ));
+    }
+    Ok(fields)
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let fields = named_fields(input, "DartMessage")?;
+    if fields.is_empty() || fields.len() > MAX_FIELDS {
+        return Err(Error::new_spanned(
+            &input.ident,
+            format!("DartMessage requires 1 to {MAX_FIELDS} fields"),
+        ));
+    }
+    fields
+        .iter()
+        .map(|field| {
+            let kind = FieldKind::from_type(&field.ty).ok_or_else(|| {
+                Error::new_spanned(&field.ty, "unsupported DartMessage field type")
+            })?;
+            Ok(Field {
+                name: field.ident.clone().unwrap(),
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// Returns the names of the fields in declaration order.
+fn field_names(input: &DeriveInput, derive: &str) -> syn::Result<Vec<Ident>> {
+    Ok(named_fields(input, derive)?
+        .iter()
+        .map(|field| field.ident.clone().unwrap())
+        .collect())
+}
+
+fn expand_into_cobject(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let names = field_names(input, "IntoCObject")?;
+    let ident = &input.ident;
+    Ok(quote! {
+        impl ::std::convert::TryFrom<#ident> for ::xayn_dart_api_dl::cobject::CObject {
+            type Error = ::std::ffi::NulError;
+
+            fn try_from(value: #ident) -> ::std::result::Result<Self, Self::Error> {
+                ::std::result::Result::Ok(::xayn_dart_api_dl::cobject::CObject::array(::std::vec![
+                    #(::std::boxed::Box::new(
+                        ::xayn_dart_api_dl::cobject::IntoCObjectElement::into_cobject_element(
+                            value.#names,
+                        )?,
+                    ),)*
+                ]))
+            }
+        }
+
+        impl ::xayn_dart_api_dl::cobject::IntoCObjectElement for #ident {
+            fn into_cobject_element(
+                self,
+            ) -> ::std::result::Result<::xayn_dart_api_dl::cobject::CObject, ::std::ffi::NulError> {
+                ::std::convert::TryFrom::try_from(self)
+            }
+        }
+    })
+}
+
+fn expand_from_cobject(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let names = field_names(input, "FromCObject")?;
+    let ident = &input.ident;
+    let len = names.len();
+    let indices = (0..len).map(Literal::usize_unsuffixed);
+    let expected = format!("an array encoding `{ident}`");
+    Ok(quote! {
+        impl ::std::convert::TryFrom<::xayn_dart_api_dl::cobject::WithRuntime<'_, '_>> for #ident {
+            type Error = ::xayn_dart_api_dl::cobject::DecodeError;
+
+            fn try_from(
+                ::xayn_dart_api_dl::cobject::WithRuntime(obj, rt): ::xayn_dart_api_dl::cobject::WithRuntime<'_, '_>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let array = obj
+                    .require_array(rt)
+                    .map_err(::xayn_dart_api_dl::cobject::DecodeError::NotAnArray)?;
+                if array.len() != #len {
+                    return ::std::result::Result::Err(
+                        ::xayn_dart_api_dl::cobject::DecodeError::WrongLength {
+                            expected: #len,
+                            found: array.len(),
+                        },
+                    );
+                }
+                ::std::result::Result::Ok(Self {
+                    #(#names: ::xayn_dart_api_dl::cobject::decode_array_element(array, #indices, rt)?,)*
+                })
+            }
+        }
+
+        impl ::xayn_dart_api_dl::cobject::FromCObjectElement for #ident {
+            const EXPECTED: &'static str = #expected;
+
+            fn from_cobject_element(
+                obj: &::xayn_dart_api_dl::cobject::CObjectMut<'_>,
+                rt: ::xayn_dart_api_dl::DartRuntime,
+            ) -> ::std::option::Option<Self> {
+                ::std::convert::TryFrom::try_from(::xayn_dart_api_dl::cobject::WithRuntime(obj, rt))
+                    .ok()
+            }
+        }
+    })
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = fields(input)?;
+    let ident = &input.ident;
+    let names = fields.iter().map(|field| &field.name).collect::<Vec<_>>();
+    let dart_source = dart_source(&ident.to_string(), &fields);
+    Ok(quote! {
+        impl ::xayn_dart_api_dl::cobject::DartMessage for #ident {
+            fn encode(
+                self,
+            ) -> ::std::result::Result<::xayn_dart_api_dl::cobject::CObject, ::std::ffi::NulError> {
+                ::std::result::Result::Ok(::xayn_dart_api_dl::cobject::CObject::array(::std::vec![
+                    #(::std::boxed::Box::new(
+                        ::xayn_dart_api_dl::cobject::IntoCObjectElement::into_cobject_element(
+                            self.#names,
+                        )?,
+                    ),)*
+                ]))
+            }
+
+            fn decode(
+                obj: &::xayn_dart_api_dl::cobject::CObjectMut<'_>,
+                rt: ::xayn_dart_api_dl::DartRuntime,
+            ) -> ::std::result::Result<Self, ::xayn_dart_api_dl::cobject::DecodeError> {
+                let (#(#names,)*) = obj.decode_tuple(rt)?;
+                ::std::result::Result::Ok(Self { #(#names,)* })
+            }
+        }
+
+        impl ::xayn_dart_api_dl::cobject::DartSource for #ident {
+            const DART_SOURCE: &'static str = #dart_source;
+        }
+    })
+}
+
+/// Generates a dart class with the same fields and matching `encode`/`decode` functions.
+fn dart_source(name: &str, fields: &[Field]) -> String {
+    let declarations = fields
+        .iter()
+        .map(|field| format!("  final {} {};\n", field.kind.dart_type(), field.name))
+        .collect::<Vec<_>>()
+        .concat();
+    let names = fields
+        .iter()
+        .map(|field| field.name.to_string())
+        .collect::<Vec<_>>();
+    let params = names
+        .iter()
+        .map(|name| format!("this.{name}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let names = names.join(", ");
+    let len = fields.len();
+    let args = fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| field.kind.dart_decode(&format!("list[{idx}]")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "class {name} {{\n\
+         {declarations}\n  \
+         {name}({params});\n\n  \
+         List<Object?> encode() => [{names}];\n\n  \
+         static {name} decode(Object? message) {{\n    \
+         final list = message as List<Object?>;\n    \
+         if (list.length != {len}) {{\n      \
+         throw ArgumentError('Expected {len} fields, found ${{list.length}}.');\n    \
+         }}\n    \
+         return {name}({args});\n  \
+         }}\n\
+         }}\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn test_dart_source() {
+        let input: DeriveInput = parse_quote! {
+            struct Sample {
+                id: i64,
+                name: String,
+                payload: Vec<u8>,
+                scores: Vec<f64>,
+            }
+        };
+        let source = dart_source("Sample", &fields(&input).unwrap());
+        assert!(source.contains("  final Uint8List payload;\n"));
+        assert!(source.contains("  List<Object?> encode() => [id, name, payload, scores];\n"));
+        assert!(source.contains(
+            "  static Sample decode(Object? message) {\n    \
+             final list = message as List<Object?>;\n    \
+             if (list.length != 4) {\n"
+        ));
+        assert!(source.contains(
+            "    return Sample(list[0] as int, list[1] as String, list[2] as Uint8List, \
+             (list[3] as List<Object?>).cast<double>());\n"
+        ));
+    }
+
+    #[test]
+    fn test_from_cobject_requires_named_fields() {
+        let input: DeriveInput = parse_quote! {
+            struct Sample(i64, Option<String>);
+        };
+        let err = expand_from_cobject(&input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "FromCObject requires a struct with named fields"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_field_type() {
+        let input: DeriveInput = parse_quote! {
+            struct Sample {
+                id: u32,
+            }
+        };
+        assert!(fields(&input).is_err());
+    }
+}
@@ -72,6 +72,8 @@
 //!
 //! The version of the Dart VM's API DL **can not be looked up, the functionality
 //! is missing.** Currently we are at API DL version 2.0 so it doesn't matter.
+//! `DartRuntime::dl_minor_version_at_least()` of `xayn-dart-api-dl` checks the
+//! function slots added in later minor versions instead.
 // FIXME: But Dart 2.15 will bump it to 2.1 (hopefully) in a (potentially) non-detectable way.
 //        If it's not fixed by dart we could somewhat work around it, but I hope we don't need to.
 //        Also versions are accessible from dart, so higher level bindings
@@ -29,12 +29,39 @@
 //!   such we need to handle resource cleanup, like
 //!   freeing allocated string.
 
+mod aggregate;
+mod coerce;
+mod dart_source;
+#[cfg(feature = "serde")]
+mod de;
+mod decode;
+mod diff;
+mod dsl;
 mod owned;
 mod reference;
 mod rust_values;
+#[cfg(feature = "serde")]
+mod ser;
+mod tagged;
 mod type_enums;
+mod value;
+mod versioned;
 
+pub use aggregate::*;
+pub use coerce::*;
+#[cfg(feature = "derive")]
+pub use dart_api_dl_derive::{DartMessage, FromCObject, IntoCObject};
+pub use dart_source::*;
+#[cfg(feature = "serde")]
+pub use de::*;
+pub use decode::*;
+pub use diff::*;
+pub use dsl::*;
 pub use owned::*;
 pub use reference::*;
 pub use rust_values::*;
+#[cfg(feature = "serde")]
+pub use ser::*;
+pub use tagged::*;
 pub use type_enums::*;
+pub use value::*;
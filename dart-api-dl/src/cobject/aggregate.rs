@@ -0,0 +1,99 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::DartRuntime;
+
+use super::{CObject, CObjectMut, UnknownCObjectType};
+
+/// Collects copies of received messages into a single array [`CObject`].
+///
+/// This allows e.g. a handler to coalesce a burst of messages and process or
+/// forward them together.
+#[derive(Default)]
+pub struct CObjectAggregator {
+    // boxed as required by `CObject::array()`
+    #[allow(clippy::vec_box)]
+    elements: Vec<Box<CObject>>,
+}
+
+impl CObjectAggregator {
+    /// Creates an empty aggregator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of added messages.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns `true` if no messages were added.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Appends a deep copy of the message.
+    ///
+    /// Typed data is copied into new external typed data.
+    ///
+    /// # Errors
+    ///
+    /// If the message is (or contains objects) of an unknown type, in which
+    /// case nothing is appended.
+    pub fn add(
+        &mut self,
+        rt: DartRuntime,
+        message: &CObjectMut<'_>,
+    ) -> Result<(), UnknownCObjectType> {
+        self.elements.push(Box::new(message.deep_copy(rt.into())?));
+        Ok(())
+    }
+
+    /// Returns an array of all added messages in the order they were added.
+    pub fn finish(self) -> CObject {
+        CObject::array(self.elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cobject::{TypedData, TypedDataRef};
+
+    use super::*;
+
+    #[test]
+    fn test_aggregate_messages() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut aggregator = CObjectAggregator::new();
+        for mut message in [
+            CObject::int32(1),
+            CObject::string("two").unwrap(),
+            CObject::external_typed_data(TypedData::Uint16(vec![3, 3, 3])),
+        ] {
+            aggregator.add(rt, &message.as_mut()).unwrap();
+        }
+        assert_eq!(aggregator.len(), 3);
+
+        let mut aggregate = aggregator.finish();
+        let aggregate = aggregate.as_mut();
+        let aggregate = aggregate.as_array(rt).unwrap();
+        assert_eq!(aggregate.len(), 3);
+        assert_eq!(aggregate[0].as_int32(rt), Some(1));
+        assert_eq!(aggregate[1].as_string(rt), Some("two"));
+        assert!(matches!(
+            aggregate[2].as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint16(&[3, 3, 3])), _))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display};
+
+use thiserror::Error;
+
+use crate::DartRuntime;
+
+use super::{CObjectMut, CObjectType, CObjectValuesRef, TypedDataRef};
+
+/// A kind of value a [`CoercionChain`] tries to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoercionKind {
+    /// Null.
+    Null,
+    /// A bool.
+    Bool,
+    /// An int of any width.
+    Int,
+    /// A double.
+    Double,
+    /// A string.
+    String,
+    /// An array.
+    Array,
+    /// Typed data of a known type.
+    TypedData,
+}
+
+impl Display for CoercionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CoercionKind::Null => "null",
+            CoercionKind::Bool => "a bool",
+            CoercionKind::Int => "an int",
+            CoercionKind::Double => "a double",
+            CoercionKind::String => "a string",
+            CoercionKind::Array => "an array",
+            CoercionKind::TypedData => "typed data",
+        })
+    }
+}
+
+/// The first matching coercion of a [`CoercionChain`].
+#[derive(Debug)]
+pub enum Coerced<'a> {
+    /// The object is null.
+    Null,
+    /// The object is a bool.
+    Bool(bool),
+    /// The object is an int.
+    Int(i64),
+    /// The object is a double.
+    Double(f64),
+    /// The object is a string.
+    String(&'a str),
+    /// The object is an array.
+    Array(&'a [CObjectMut<'a>]),
+    /// The object is typed data of a known type.
+    TypedData(TypedDataRef<'a>),
+}
+
+/// None of the coercions of a [`CoercionChain`] matched.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub struct CoercionChainFailed {
+    /// The attempted coercions in order.
+    pub attempted: Vec<CoercionKind>,
+    /// The found type, `None` if it's not known by this library.
+    pub found: Option<CObjectType>,
+}
+
+impl Display for CoercionChainFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Expected ")?;
+        for (idx, kind) in self.attempted.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" or ")?;
+            }
+            write!(f, "{kind}")?;
+        }
+        match self.found {
+            Some(found) => write!(f, ", found {found:?}."),
+            None => f.write_str(", found an unknown type."),
+        }
+    }
+}
+
+/// Tries to read an object as one of multiple kinds, see [`CObjectMut::coerce()`].
+#[must_use]
+pub struct CoercionChain<'a> {
+    obj: &'a CObjectMut<'a>,
+    rt: DartRuntime,
+    attempted: Vec<CoercionKind>,
+}
+
+macro_rules! chain_methods {
+    ($($(#[$attr:meta])* $name:ident => $kind:ident;)*) => ($(
+        $(#[$attr])*
+        pub fn $name(self) -> Self {
+            self.or(CoercionKind::$kind)
+        }
+    )*);
+}
+
+impl<'a> CoercionChain<'a> {
+    /// Appends a coercion to the chain.
+    pub fn or(mut self, kind: CoercionKind) -> Self {
+        self.attempted.push(kind);
+        self
+    }
+
+    chain_methods! {
+        /// Appends a coercion to null.
+        or_null => Null;
+        /// Appends a coercion to a bool.
+        or_bool => Bool;
+        /// Appends a coercion to an int.
+        or_int => Int;
+        /// Appends a coercion to a double.
+        or_double => Double;
+        /// Appends a coercion to a string.
+        or_string => String;
+        /// Appends a coercion to an array.
+        or_array => Array;
+        /// Appends a coercion to typed data.
+        or_typed_data => TypedData;
+    }
+
+    /// Returns the first matching coercion.
+    ///
+    /// # Errors
+    ///
+    /// If none of the coercions matches.
+    pub fn finish(self) -> Result<Coerced<'a>, CoercionChainFailed> {
+        let coerced = self
+            .obj
+            .value_ref(self.rt)
+            .ok()
+            .and_then(|value| self.attempted.iter().find_map(|kind| coerce(&value, *kind)));
+        coerced.ok_or_else(|| CoercionChainFailed {
+            attempted: self.attempted,
+            found: self.obj.r#type().ok(),
+        })
+    }
+}
+
+fn coerce<'a>(value: &CObjectValuesRef<'a>, kind: CoercionKind) -> Option<Coerced<'a>> {
+    Some(match (kind, value) {
+        (CoercionKind::Null, CObjectValuesRef::Null) => Coerced::Null,
+        (CoercionKind::Bool, CObjectValuesRef::Bool(value)) => Coerced::Bool(*value),
+        (CoercionKind::Int, CObjectValuesRef::Int32(value)) => Coerced::Int((*value).into()),
+        (CoercionKind::Int, CObjectValuesRef::Int64(value)) => Coerced::Int(*value),
+        (CoercionKind::Double, CObjectValuesRef::Double(value)) => Coerced::Double(*value),
+        (CoercionKind::String, CObjectValuesRef::String(value)) => Coerced::String(value),
+        (CoercionKind::Array, CObjectValuesRef::Array(array)) => Coerced::Array(array),
+        (CoercionKind::TypedData, CObjectValuesRef::TypedData { data: Ok(data), .. }) => {
+            Coerced::TypedData(*data)
+        }
+        _ => return None,
+    })
+}
+
+impl CObjectMut<'_> {
+    /// Starts a chain of coercions which are tried in order.
+    ///
+    /// This is useful for polymorphic fields, failed coercions of the chain are
+    /// not reported as coercion failures.
+    ///
+    /// ```
+    /// # use xayn_dart_api_dl::{cobject::{CObjectMut, Coerced, CoercionChainFailed}, DartRuntime};
+    /// fn id(rt: DartRuntime, field: &CObjectMut<'_>) -> Result<String, CoercionChainFailed> {
+    ///     Ok(match field.coerce(rt).or_int().or_string().finish()? {
+    ///         Coerced::Int(id) => id.to_string(),
+    ///         Coerced::String(id) => id.to_owned(),
+    ///         _ => unreachable!(),
+    ///     })
+    /// }
+    /// ```
+    pub fn coerce(&self, rt: DartRuntime) -> CoercionChain<'_> {
+        CoercionChain {
+            obj: self,
+            rt,
+            attempted: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cobject::CObject;
+
+    use super::*;
+
+    #[test]
+    fn test_coerce_int_or_string() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut field = CObject::string("id-7").unwrap();
+        let field = field.as_mut();
+        assert!(matches!(
+            field.coerce(rt).or_int().or_string().finish(),
+            Ok(Coerced::String("id-7"))
+        ));
+
+        let mut field = CObject::int32(7);
+        let field = field.as_mut();
+        assert!(matches!(
+            field.coerce(rt).or_int().or_string().finish(),
+            Ok(Coerced::Int(7))
+        ));
+    }
+
+    #[test]
+    fn test_coerce_lists_attempted_kinds() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut field = CObject::double(1.5);
+        let field = field.as_mut();
+        let error = field
+            .coerce(rt)
+            .or_int()
+            .or_string()
+            .or_null()
+            .finish()
+            .unwrap_err();
+        assert_eq!(
+            error.attempted,
+            [CoercionKind::Int, CoercionKind::String, CoercionKind::Null]
+        );
+        assert_eq!(
+            error.to_string(),
+            "Expected an int or a string or null, found Double."
+        );
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fs, io, path::Path};
+
+/// A message type with a matching dart class.
+///
+/// With the `derive` feature this is derived together with [`DartMessage`],
+/// the class has the same fields and `encode`/`decode` functions compatible
+/// with the rust side.
+///
+/// ```
+/// # #[cfg(feature = "derive")] {
+/// use xayn_dart_api_dl::cobject::{dart_file, DartMessage, DartSource};
+///
+/// #[derive(DartMessage)]
+/// struct Thumbnail {
+///     id: i64,
+///     png: Vec<u8>,
+/// }
+///
+/// let file = dart_file(&[Thumbnail::DART_SOURCE]);
+/// assert!(file.contains("static Thumbnail decode(Object? message) {"));
+/// # }
+/// ```
+///
+/// [`DartMessage`]: crate::cobject::DartMessage
+pub trait DartSource {
+    /// The source code of the dart class.
+    const DART_SOURCE: &'static str;
+}
+
+/// Returns the content of a dart file containing the given classes.
+///
+/// Use it with the [`DartSource::DART_SOURCE`] of the messages, e.g. in a test
+/// which checks that the generated file is up to date.
+pub fn dart_file(sources: &[&str]) -> String {
+    let mut file = String::from(
+        "// Generated from the rust message types, do not edit.\n\nimport 'dart:typed_data';\n",
+    );
+    for source in sources {
+        file.push('\n');
+        file.push_str(source);
+    }
+    file
+}
+
+/// Writes a dart file containing the given classes, see [`dart_file()`].
+///
+/// The file is only written if its content changed.
+///
+/// # Errors
+///
+/// If reading or writing the file failed.
+pub fn write_dart_file(path: impl AsRef<Path>, sources: &[&str]) -> io::Result<()> {
+    let path = path.as_ref();
+    let content = dart_file(sources);
+    match fs::read_to_string(path) {
+        Ok(old) if old == content => Ok(()),
+        Ok(_) => fs::write(path, content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => fs::write(path, content),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,458 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    ffi::NulError,
+    fmt::{self, Display},
+    slice,
+};
+
+use serde::de::{
+    self,
+    value::{SeqDeserializer, StrDeserializer},
+    Deserialize,
+    DeserializeSeed,
+    Deserializer,
+    IntoDeserializer,
+    Visitor,
+};
+use thiserror::Error;
+
+use crate::DartRuntime;
+
+use super::{CObject, CObjectMut, CObjectType, CObjectValuesRef, TypedData};
+
+/// Deserializing a [`CObjectMut`] failed.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The object (or an element of it) can't be deserialized.
+    ///
+    /// This is the case for send ports, capabilities and unknown types.
+    #[error("Can't deserialize {0:?}.")]
+    Unsupported(Option<CObjectType>),
+    /// The object doesn't match the deserialized type.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// A [`Deserializer`] over a received message.
+///
+/// - null is deserialized as unit or `None`
+/// - bools, ints, doubles and strings are deserialized as scalars
+/// - arrays are deserialized as sequences, which includes structs and tuples
+/// - typed data is deserialized as (native endian) bytes
+/// - strings can be deserialized as unit enum variants
+///
+/// Send ports and capabilities can't be deserialized.
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use xayn_dart_api_dl::{cobject::{CObjectDeserializer, CObjectMut, DeserializeError}, DartRuntime};
+/// #[derive(Deserialize)]
+/// struct Add {
+///     a: i64,
+///     b: i64,
+/// }
+///
+/// fn decode(rt: DartRuntime, msg: &CObjectMut<'_>) -> Result<i64, DeserializeError> {
+///     let add = Add::deserialize(CObjectDeserializer::new(rt, msg))?;
+///     Ok(add.a + add.b)
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct CObjectDeserializer<'de> {
+    rt: DartRuntime,
+    obj: &'de CObjectMut<'de>,
+}
+
+impl<'de> CObjectDeserializer<'de> {
+    /// Creates a deserializer for the object.
+    pub fn new(rt: DartRuntime, obj: &'de CObjectMut<'de>) -> Self {
+        Self { rt, obj }
+    }
+
+    fn value(self) -> Result<CObjectValuesRef<'de>, DeserializeError> {
+        self.obj
+            .value_ref(self.rt)
+            .map_err(|_| DeserializeError::Unsupported(None))
+    }
+
+    fn visit_array<V>(
+        self,
+        array: &'de [CObjectMut<'de>],
+        visitor: V,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        let mut access = ArrayAccess {
+            rt: self.rt,
+            elements: array.iter(),
+        };
+        let value = visitor.visit_seq(&mut access)?;
+        let remaining = access.elements.len();
+        if remaining == 0 {
+            Ok(value)
+        } else {
+            Err(de::Error::invalid_length(
+                array.len(),
+                &format!("{} elements", array.len() - remaining).as_str(),
+            ))
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for CObjectDeserializer<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value()? {
+            CObjectValuesRef::Null => visitor.visit_unit(),
+            CObjectValuesRef::Bool(value) => visitor.visit_bool(value),
+            CObjectValuesRef::Int32(value) => visitor.visit_i32(value),
+            CObjectValuesRef::Int64(value) => visitor.visit_i64(value),
+            CObjectValuesRef::Double(value) => visitor.visit_f64(value),
+            CObjectValuesRef::String(value) => visitor.visit_borrowed_str(value),
+            CObjectValuesRef::Array(array) => self.visit_array(array, visitor),
+            CObjectValuesRef::TypedData { data: Ok(data), .. } => {
+                visitor.visit_borrowed_bytes(data.as_bytes())
+            }
+            CObjectValuesRef::TypedData { data: Err(_), .. } => {
+                Err(DeserializeError::Unsupported(Some(CObjectType::TypedData)))
+            }
+            CObjectValuesRef::SendPort(_) => {
+                Err(DeserializeError::Unsupported(Some(CObjectType::SendPort)))
+            }
+            CObjectValuesRef::Capability(_) => {
+                Err(DeserializeError::Unsupported(Some(CObjectType::Capability)))
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let CObjectValuesRef::Null = self.value()? {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // allows deserializing typed data into e.g. a `Vec<u8>`
+        if let CObjectValuesRef::TypedData { data: Ok(data), .. } = self.value()? {
+            let mut bytes = SeqDeserializer::new(data.as_bytes().iter().copied());
+            let value = visitor.visit_seq(&mut bytes)?;
+            bytes.end()?;
+            Ok(value)
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let CObjectValuesRef::String(variant) = self.value()? {
+            let variant: StrDeserializer<'_, DeserializeError> = variant.into_deserializer();
+            visitor.visit_enum(variant)
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct ArrayAccess<'de> {
+    rt: DartRuntime,
+    elements: slice::Iter<'de, CObjectMut<'de>>,
+}
+
+impl<'de> de::SeqAccess<'de> for ArrayAccess<'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.elements
+            .next()
+            .map(|obj| seed.deserialize(CObjectDeserializer::new(self.rt, obj)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+/// Deserializing a [`CObject`] failed.
+///
+/// It's wrapped in the error of the used deserializer.
+#[derive(Debug, Error)]
+pub enum CObjectDeserializeError {
+    /// A string contains a nul byte.
+    #[error(transparent)]
+    Nul(#[from] NulError),
+    /// The data can't be represented as object, e.g. maps or ints out of the `i64` range.
+    #[error("Can't create an object from {0}.")]
+    Unsupported(String),
+    /// The data doesn't match the deserialized type.
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl de::Error for CObjectDeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// Creates an object from structured data.
+///
+/// - unit and `None` are deserialized as null
+/// - bools, ints, floats and strings are deserialized as bool, int64, double and string
+/// - sequences are deserialized as arrays
+/// - bytes are deserialized as `Uint8` typed data
+///
+/// Other data, e.g. maps, results in a [`CObjectDeserializeError`].
+impl<'de> Deserialize<'de> for CObject {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CObjectVisitor)
+    }
+}
+
+struct CObjectVisitor;
+
+impl CObjectVisitor {
+    fn unsupported<E: de::Error>(what: impl Display) -> E {
+        E::custom(CObjectDeserializeError::Unsupported(what.to_string()))
+    }
+}
+
+impl<'de> Visitor<'de> for CObjectVisitor {
+    type Value = CObject;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("null, a bool, an int, a float, a string, bytes or a sequence")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<CObject, E> {
+        Ok(CObject::null())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<CObject, E> {
+        Ok(CObject::null())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<CObject, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CObject::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<CObject, E> {
+        Ok(CObject::bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<CObject, E> {
+        Ok(CObject::int64(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<CObject, E> {
+        i64::try_from(value)
+            .map(CObject::int64)
+            .map_err(|_| Self::unsupported(format_args!("the int {value}")))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<CObject, E> {
+        Ok(CObject::double(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<CObject, E> {
+        CObject::string(value).map_err(|error| E::custom(CObjectDeserializeError::from(error)))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<CObject, E> {
+        Ok(CObject::typed_data(TypedData::Uint8(value.to_vec())))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<CObject, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut array = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(element) = seq.next_element::<CObject>()? {
+            array.push(Box::new(element));
+        }
+        Ok(CObject::array(array))
+    }
+
+    fn visit_map<A>(self, _map: A) -> Result<CObject, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        Err(Self::unsupported("a map"))
+    }
+
+    fn visit_enum<A>(self, _data: A) -> Result<CObject, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        Err(Self::unsupported("an enum"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dart_api_dl_sys::ILLEGAL_PORT;
+    use serde::Deserialize;
+
+    use crate::cobject::{CObject, TypedData};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Op {
+        Add,
+        Sub,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Command<'a> {
+        op: Op,
+        name: &'a str,
+        operands: Vec<i32>,
+        scale: Option<f64>,
+        payload: Vec<u8>,
+        verbose: bool,
+    }
+
+    #[test]
+    fn test_deserialize_struct_from_array() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::string("sub").unwrap()),
+            Box::new(CObject::string("calc").unwrap()),
+            Box::new(CObject::array(vec![
+                Box::new(CObject::int32(7)),
+                Box::new(CObject::int64(-2)),
+            ])),
+            Box::new(CObject::null()),
+            Box::new(CObject::typed_data(TypedData::Uint8(vec![1, 2, 3]))),
+            Box::new(CObject::bool(true)),
+        ]);
+        let msg = msg.as_mut();
+        let command = Command::deserialize(CObjectDeserializer::new(rt, &msg)).unwrap();
+        assert_eq!(
+            command,
+            Command {
+                op: Op::Sub,
+                name: "calc",
+                operands: vec![7, -2],
+                scale: None,
+                payload: vec![1, 2, 3],
+                verbose: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cobject_round_trips_through_json() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let json = serde_json::json!([null, true, -3, 1.5, "hy", [[], ["nested", 0]]]);
+        let mut obj = CObject::deserialize(&json).unwrap();
+        let obj = obj.as_mut();
+        assert_eq!(obj.as_array(rt).unwrap()[2].as_int64(rt), Some(-3));
+        assert_eq!(serde_json::to_value(&obj).unwrap(), json);
+    }
+
+    #[test]
+    fn test_cobject_deserialize_errors() {
+        let error = |json: &str| {
+            serde_json::from_str::<CObject>(json)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert!(error(r#"["a\u0000b"]"#).contains("nul byte"));
+        assert!(error(r#"{"a": 1}"#).contains("Can't create an object from a map."));
+        assert!(error(&u64::MAX.to_string()).contains("Can't create an object from the int"));
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut port = CObject::raw_send_port(ILLEGAL_PORT, ILLEGAL_PORT);
+        let port = port.as_mut();
+        assert_eq!(
+            <Option<i64>>::deserialize(CObjectDeserializer::new(rt, &port)),
+            Err(DeserializeError::Unsupported(Some(CObjectType::SendPort)))
+        );
+
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::int32(1)),
+            Box::new(CObject::int32(2)),
+            Box::new(CObject::int32(3)),
+        ]);
+        let msg = msg.as_mut();
+        assert!(<(i32, i32)>::deserialize(CObjectDeserializer::new(rt, &msg)).is_err());
+        assert!(<(i32, bool, i32)>::deserialize(CObjectDeserializer::new(rt, &msg)).is_err());
+        assert_eq!(
+            <(i32, i32, u8)>::deserialize(CObjectDeserializer::new(rt, &msg)),
+            Ok((1, 2, 3))
+        );
+    }
+}
@@ -0,0 +1,751 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::NulError;
+
+use thiserror::Error;
+
+use crate::{ports::SendPort, DartRuntime};
+
+use super::{CObject, CObjectMut, TypeError, TypedData, TypedDataRef, TypedDataType};
+
+/// Types which can be decoded from a single element of an array message.
+pub trait FromCObjectElement: Sized {
+    /// A description of the expected type, used in errors.
+    const EXPECTED: &'static str;
+
+    /// Decodes the object, returns `None` if it has the wrong type.
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self>;
+}
+
+impl FromCObjectElement for bool {
+    const EXPECTED: &'static str = "a bool";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_bool(rt)
+    }
+}
+
+impl FromCObjectElement for i64 {
+    const EXPECTED: &'static str = "an int";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_int(rt)
+    }
+}
+
+impl FromCObjectElement for i32 {
+    const EXPECTED: &'static str = "an int in the i32 range";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_int(rt).and_then(|value| value.try_into().ok())
+    }
+}
+
+impl FromCObjectElement for f64 {
+    const EXPECTED: &'static str = "a double";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_double(rt)
+    }
+}
+
+impl FromCObjectElement for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_string(rt).map(ToOwned::to_owned)
+    }
+}
+
+impl FromCObjectElement for SendPort {
+    const EXPECTED: &'static str = "a send port other than ILLEGAL_PORT";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_send_port(rt).flatten()
+    }
+}
+
+impl FromCObjectElement for Vec<u8> {
+    const EXPECTED: &'static str = "Uint8 typed data";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        match obj.as_typed_data(rt) {
+            Some((Ok(TypedDataRef::Uint8(bytes)), _)) => Some(bytes.to_vec()),
+            _ => None,
+        }
+    }
+}
+
+impl FromCObjectElement for Vec<i64> {
+    const EXPECTED: &'static str = "an array of ints";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_array(rt)?
+            .iter()
+            .map(|element| element.as_int(rt))
+            .collect()
+    }
+}
+
+impl FromCObjectElement for Vec<f64> {
+    const EXPECTED: &'static str = "an array of doubles";
+
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_array(rt)?
+            .iter()
+            .map(|element| element.as_double(rt))
+            .collect()
+    }
+}
+
+impl<T> FromCObjectElement for Option<T>
+where
+    T: FromCObjectElement,
+{
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    /// Decodes null as `None`.
+    fn from_cobject_element(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<Self> {
+        obj.as_optional(rt, |obj| T::from_cobject_element(obj, rt))
+    }
+}
+
+/// Types which can be encoded as a single element of an array message.
+///
+/// This is the counterpart of [`FromCObjectElement`].
+pub trait IntoCObjectElement {
+    /// Encodes the value.
+    ///
+    /// # Errors
+    ///
+    /// If the value contains a string with a nul byte.
+    fn into_cobject_element(self) -> Result<CObject, NulError>;
+}
+
+impl IntoCObjectElement for bool {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::bool(self))
+    }
+}
+
+impl IntoCObjectElement for i64 {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::int64(self))
+    }
+}
+
+impl IntoCObjectElement for f64 {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::double(self))
+    }
+}
+
+impl IntoCObjectElement for String {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        CObject::string(self)
+    }
+}
+
+impl IntoCObjectElement for SendPort {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::send_port(self))
+    }
+}
+
+impl IntoCObjectElement for Vec<u8> {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::typed_data(TypedData::Uint8(self)))
+    }
+}
+
+impl IntoCObjectElement for Vec<i64> {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::array(
+            self.into_iter()
+                .map(|value| Box::new(CObject::int64(value)))
+                .collect(),
+        ))
+    }
+}
+
+impl IntoCObjectElement for Vec<f64> {
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        Ok(CObject::array(
+            self.into_iter()
+                .map(|value| Box::new(CObject::double(value)))
+                .collect(),
+        ))
+    }
+}
+
+/// A [`CObjectMut`] bundled with the runtime, to read it with [`TryFrom`].
+///
+/// ```
+/// # use xayn_dart_api_dl::{cobject::{CObjectMut, TypeError, WithRuntime}, DartRuntime};
+/// fn greet(rt: DartRuntime, msg: &CObjectMut<'_>) -> Result<String, TypeError> {
+///     let name = <&str>::try_from(WithRuntime(msg, rt))?;
+///     Ok(format!("Hello {name}!"))
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct WithRuntime<'r, 'a>(pub &'r CObjectMut<'a>, pub DartRuntime);
+
+macro_rules! impl_try_from_with_runtime {
+    ($($t:ty),*) => ($(
+        impl TryFrom<WithRuntime<'_, '_>> for $t {
+            type Error = TypeError;
+
+            fn try_from(WithRuntime(obj, rt): WithRuntime<'_, '_>) -> Result<Self, Self::Error> {
+                <$t>::from_cobject_element(obj, rt)
+                    .ok_or_else(|| obj.type_error(<$t as FromCObjectElement>::EXPECTED))
+            }
+        }
+    )*);
+}
+
+impl_try_from_with_runtime!(bool, i32, i64, f64, Vec<u8>);
+
+impl<'r> TryFrom<WithRuntime<'r, '_>> for &'r str {
+    type Error = TypeError;
+
+    fn try_from(WithRuntime(obj, rt): WithRuntime<'r, '_>) -> Result<Self, Self::Error> {
+        obj.require_str(rt)
+    }
+}
+
+impl<T> IntoCObjectElement for Option<T>
+where
+    T: IntoCObjectElement,
+{
+    /// Encodes `None` as null.
+    fn into_cobject_element(self) -> Result<CObject, NulError> {
+        self.map_or_else(|| Ok(CObject::null()), T::into_cobject_element)
+    }
+}
+
+/// A message which can be sent to and received from dart.
+///
+/// See [`TypedPort`](crate::ports::TypedPort). With the `derive` feature it can be
+/// derived together with [`DartSource`](crate::cobject::DartSource).
+pub trait DartMessage: Sized {
+    /// Encodes the message into a new object.
+    ///
+    /// # Errors
+    ///
+    /// If the message contains a string with a nul byte.
+    fn encode(self) -> Result<CObject, NulError>;
+
+    /// Decodes the message from an object.
+    ///
+    /// # Errors
+    ///
+    /// If the object doesn't represent a message of this type.
+    fn decode(obj: &CObjectMut<'_>, rt: DartRuntime) -> Result<Self, DecodeError>;
+}
+
+/// Types which can be decoded from an array message, see [`CObjectMut::decode_tuple()`].
+pub trait FromCObjectArray: Sized {
+    /// Decodes the elements of the array positionally.
+    ///
+    /// # Errors
+    ///
+    /// If the array has the wrong length or an element has the wrong type.
+    fn from_cobject_array(array: &[CObjectMut<'_>], rt: DartRuntime) -> Result<Self, DecodeError>;
+}
+
+macro_rules! impl_from_cobject_array_for_tuples {
+    ($(($($idx:tt: $t:ident),+)),* $(,)?) => ($(
+        impl<$($t),+> FromCObjectArray for ($($t,)+)
+        where
+            $($t: FromCObjectElement),+
+        {
+            fn from_cobject_array(
+                array: &[CObjectMut<'_>],
+                rt: DartRuntime,
+            ) -> Result<Self, DecodeError> {
+                const LEN: usize = [$($idx),+].len();
+                if array.len() != LEN {
+                    return Err(DecodeError::WrongLength {
+                        expected: LEN,
+                        found: array.len(),
+                    });
+                }
+                Ok(($(decode_array_element::<$t>(array, $idx, rt)?,)+))
+            }
+        }
+    )*);
+}
+
+impl_from_cobject_array_for_tuples!(
+    (0: A),
+    (0: A, 1: B),
+    (0: A, 1: B, 2: C),
+    (0: A, 1: B, 2: C, 3: D),
+    (0: A, 1: B, 2: C, 3: D, 4: E),
+    (0: A, 1: B, 2: C, 3: D, 4: E, 5: F),
+    (0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G),
+    (0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G, 7: H),
+);
+
+/// Decodes the element at `index` of an array message.
+///
+/// This is used by the code generated by `#[derive(FromCObject)]` and not part
+/// of the public API.
+///
+/// # Errors
+///
+/// If the element has the wrong type, the error contains its index.
+///
+/// # Panics
+///
+/// If `index` is out of bounds.
+#[doc(hidden)]
+pub fn decode_array_element<T>(
+    array: &[CObjectMut<'_>],
+    index: usize,
+    rt: DartRuntime,
+) -> Result<T, DecodeError>
+where
+    T: FromCObjectElement,
+{
+    let element = &array[index];
+    T::from_cobject_element(element, rt).ok_or_else(|| DecodeError::Element {
+        index,
+        source: element.type_error(T::EXPECTED),
+    })
+}
+
+/// Decoding an array message failed.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message is not an array.
+    #[error("{0}")]
+    NotAnArray(TypeError),
+    /// The array has the wrong length.
+    #[error("Expected an array of length {expected}, found length {found}.")]
+    WrongLength {
+        /// The expected length.
+        expected: usize,
+        /// The found length.
+        found: usize,
+    },
+    /// An element of the array has the wrong type.
+    #[error("At [{index}]: {source}")]
+    Element {
+        /// The index of the element.
+        index: usize,
+        /// The type mismatch of the element.
+        source: TypeError,
+    },
+}
+
+/// Reading an element of an array message failed, see [`CObjectMut::element_as()`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ElementError {
+    /// The message is not an array.
+    #[error("{0}")]
+    NotAnArray(TypeError),
+    /// The array has no element at the index.
+    #[error("At [{index}]: Expected an element, found an array of length {len}.")]
+    OutOfBounds {
+        /// The index of the element.
+        index: usize,
+        /// The length of the array.
+        len: usize,
+    },
+    /// The element has the wrong type.
+    #[error("At [{index}]: {source}")]
+    WrongType {
+        /// The index of the element.
+        index: usize,
+        /// The type mismatch of the element.
+        source: TypeError,
+    },
+}
+
+/// A batch of typed data isn't homogeneous, see [`CObjectMut::typed_data_batch_element_type()`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BatchTypeError {
+    /// The message is not an array.
+    #[error("{0}")]
+    NotAnArray(TypeError),
+    /// The batch has no elements.
+    #[error("Expected a non-empty batch.")]
+    Empty,
+    /// An element is not typed data of a known type.
+    #[error("At [{index}]: {source}")]
+    NotTypedData {
+        /// The index of the element.
+        index: usize,
+        /// The type mismatch of the element.
+        source: TypeError,
+    },
+    /// An element has a different typed data type than the previous elements.
+    #[error("At [{index}]: Expected {expected:?}, found {found:?}.")]
+    Mixed {
+        /// The index of the element.
+        index: usize,
+        /// The typed data type of the previous elements.
+        expected: TypedDataType,
+        /// The typed data type of the element.
+        found: TypedDataType,
+    },
+}
+
+impl CObjectMut<'_> {
+    /// Decodes an array message into a tuple.
+    ///
+    /// ```
+    /// # use xayn_dart_api_dl::{cobject::{CObjectMut, DecodeError}, DartRuntime};
+    /// fn decode(rt: DartRuntime, msg: &CObjectMut<'_>) -> Result<(), DecodeError> {
+    ///     let (cmd, a, b) = msg.decode_tuple::<(String, i64, i64)>(rt)?;
+    ///     # let _ = (cmd, a, b);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array.
+    /// - If the array doesn't have the length of the tuple.
+    /// - If an element has the wrong type, the error contains its index.
+    pub fn decode_tuple<T>(&self, rt: DartRuntime) -> Result<T, DecodeError>
+    where
+        T: FromCObjectArray,
+    {
+        let array = self.require_array(rt).map_err(DecodeError::NotAnArray)?;
+        T::from_cobject_array(array, rt)
+    }
+
+    /// Reads the element at `index` of an array message.
+    ///
+    /// This is a shorthand for reading the array, getting the element and reading
+    /// it with an error which contains the index.
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array.
+    /// - If the array has no element at `index`.
+    /// - If the element has the wrong type.
+    pub fn element_as<T>(&self, rt: DartRuntime, index: usize) -> Result<T, ElementError>
+    where
+        T: FromCObjectElement,
+    {
+        let array = self.require_array(rt).map_err(ElementError::NotAnArray)?;
+        let element = array.get(index).ok_or(ElementError::OutOfBounds {
+            index,
+            len: array.len(),
+        })?;
+        T::from_cobject_element(element, rt).ok_or_else(|| ElementError::WrongType {
+            index,
+            source: element.type_error(T::EXPECTED),
+        })
+    }
+
+    /// Returns the typed data type shared by all elements of an array message.
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array or the array is empty.
+    /// - If an element is not typed data of a known type.
+    /// - If the elements have different typed data types, the error contains
+    ///   the index of the first differing element.
+    pub fn typed_data_batch_element_type(
+        &self,
+        rt: DartRuntime,
+    ) -> Result<TypedDataType, BatchTypeError> {
+        let array = self.require_array(rt).map_err(BatchTypeError::NotAnArray)?;
+        let mut common = None;
+        for (index, element) in array.iter().enumerate() {
+            let found = match element.as_typed_data(rt) {
+                Some((Ok(data), _)) => data.data_type(),
+                _ => {
+                    return Err(BatchTypeError::NotTypedData {
+                        index,
+                        source: element.type_error("typed data of a known type"),
+                    });
+                }
+            };
+            match common {
+                Some(expected) if expected != found => {
+                    return Err(BatchTypeError::Mixed {
+                        index,
+                        expected,
+                        found,
+                    });
+                }
+                _ => common = Some(found),
+            }
+        }
+        common.ok_or(BatchTypeError::Empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cobject::CObjectType;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_three_tuple() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::int32(7)),
+            Box::new(CObject::string("seven").unwrap()),
+            Box::new(CObject::double(7.5)),
+        ]);
+        let decoded = msg.as_mut().decode_tuple::<(i64, String, f64)>(rt);
+        assert_eq!(decoded, Ok((7, "seven".to_owned(), 7.5)));
+    }
+
+    #[test]
+    fn test_decode_reports_wrong_typed_element() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::int64(7)),
+            Box::new(CObject::string("seven").unwrap()),
+            Box::new(CObject::string("7.5").unwrap()),
+        ]);
+        let msg = msg.as_mut();
+        let err = msg.decode_tuple::<(i64, String, f64)>(rt).unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::Element {
+                index: 2,
+                source: TypeError {
+                    expected: "a double",
+                    found: Some(CObjectType::String),
+                },
+            }
+        );
+        assert_eq!(err.to_string(), "At [2]: Expected a double, found String.");
+
+        assert_eq!(
+            msg.decode_tuple::<(i64, String)>(rt),
+            Err(DecodeError::WrongLength {
+                expected: 2,
+                found: 3
+            })
+        );
+        assert!(matches!(
+            CObject::null().as_mut().decode_tuple::<(i64,)>(rt),
+            Err(DecodeError::NotAnArray(_))
+        ));
+    }
+
+    #[test]
+    fn test_try_from_with_runtime() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::bool(true)),
+            Box::new(CObject::int32(-3)),
+            Box::new(CObject::int64(1 << 40)),
+            Box::new(CObject::double(0.5)),
+            Box::new(CObject::string("hy").unwrap()),
+            Box::new(CObject::typed_data(TypedData::Uint8(vec![1, 2]))),
+        ]);
+        let msg = msg.as_mut();
+        let array = msg.as_array(rt).unwrap();
+        let with_rt = |index: usize| WithRuntime(&array[index], rt);
+
+        assert_eq!(bool::try_from(with_rt(0)), Ok(true));
+        assert_eq!(i32::try_from(with_rt(1)), Ok(-3));
+        assert_eq!(i64::try_from(with_rt(1)), Ok(-3));
+        assert_eq!(i64::try_from(with_rt(2)), Ok(1 << 40));
+        assert_eq!(f64::try_from(with_rt(3)), Ok(0.5));
+        assert_eq!(<&str>::try_from(with_rt(4)), Ok("hy"));
+        assert_eq!(<Vec<u8>>::try_from(with_rt(5)), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_try_from_with_runtime_wrong_type() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::int64(1 << 40)),
+            Box::new(CObject::typed_data(TypedData::Int8(vec![1]))),
+            Box::new(CObject::null()),
+        ]);
+        let msg = msg.as_mut();
+        let array = msg.as_array(rt).unwrap();
+        let with_rt = |index: usize| WithRuntime(&array[index], rt);
+
+        let error = i32::try_from(with_rt(0)).unwrap_err();
+        assert_eq!(error.found, Some(CObjectType::Int64));
+        assert_eq!(
+            error.to_string(),
+            "Expected an int in the i32 range, found Int64."
+        );
+        assert_eq!(
+            <Vec<u8>>::try_from(with_rt(1)).unwrap_err().found,
+            Some(CObjectType::ExternalTypedData)
+        );
+        let error = <&str>::try_from(with_rt(2)).unwrap_err();
+        assert_eq!(error.expected, "a string");
+        assert_eq!(error.found, Some(CObjectType::Null));
+        assert_eq!(
+            bool::try_from(WithRuntime(&msg, rt)).unwrap_err().found,
+            Some(CObjectType::Array)
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derived_struct_round_trip() {
+        use crate::cobject::{FromCObject, IntoCObject};
+
+        #[derive(Debug, PartialEq, IntoCObject, FromCObject)]
+        struct Point {
+            x: i64,
+            y: i64,
+        }
+
+        #[derive(Debug, PartialEq, IntoCObject, FromCObject)]
+        struct Shape {
+            name: String,
+            origin: Point,
+            label: Option<String>,
+            scale: Option<f64>,
+        }
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let shape = Shape {
+            name: "square".into(),
+            origin: Point { x: 1, y: -2 },
+            label: None,
+            scale: Some(0.5),
+        };
+        let mut obj = CObject::try_from(shape).unwrap();
+        let obj = obj.as_mut();
+        assert_eq!(obj.element_as::<Option<String>>(rt, 2), Ok(None));
+        let decoded = Shape::try_from(WithRuntime(&obj, rt)).unwrap();
+        assert_eq!(
+            decoded,
+            Shape {
+                name: "square".into(),
+                origin: Point { x: 1, y: -2 },
+                label: None,
+                scale: Some(0.5),
+            }
+        );
+
+        let mut obj = CObject::array(vec![
+            Box::new(CObject::int64(1)),
+            Box::new(CObject::string("2").unwrap()),
+        ]);
+        assert_eq!(
+            Point::try_from(WithRuntime(&obj.as_mut(), rt)),
+            Err(DecodeError::Element {
+                index: 1,
+                source: TypeError {
+                    expected: "an int",
+                    found: Some(CObjectType::String),
+                },
+            })
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derived_struct_rejects_nul_bytes() {
+        use crate::cobject::IntoCObject;
+
+        #[derive(IntoCObject)]
+        struct Named {
+            name: Option<String>,
+        }
+
+        assert!(CObject::try_from(Named {
+            name: Some("a\0b".into()),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_element_as() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut msg = CObject::array(vec![
+            Box::new(CObject::string("add").unwrap()),
+            Box::new(CObject::int32(3)),
+        ]);
+        let msg = msg.as_mut();
+        assert_eq!(msg.element_as::<String>(rt, 0), Ok("add".to_owned()));
+        assert_eq!(msg.element_as::<i64>(rt, 1), Ok(3));
+
+        let err = msg.element_as::<i64>(rt, 2).unwrap_err();
+        assert_eq!(err, ElementError::OutOfBounds { index: 2, len: 2 });
+        assert_eq!(
+            err.to_string(),
+            "At [2]: Expected an element, found an array of length 2."
+        );
+
+        let err = msg.element_as::<bool>(rt, 1).unwrap_err();
+        assert_eq!(
+            err,
+            ElementError::WrongType {
+                index: 1,
+                source: TypeError {
+                    expected: "a bool",
+                    found: Some(CObjectType::Int32),
+                },
+            }
+        );
+        assert_eq!(err.to_string(), "At [1]: Expected a bool, found Int32.");
+    }
+
+    #[test]
+    fn test_typed_data_batch_element_type() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut batch = CObject::array(vec![
+            Box::new(CObject::typed_data(TypedData::Float32(vec![1., 2.]))),
+            Box::new(CObject::external_typed_data(TypedData::Float32(vec![3.]))),
+        ]);
+        assert_eq!(
+            batch.as_mut().typed_data_batch_element_type(rt),
+            Ok(TypedDataType::Float32)
+        );
+
+        let mut batch = CObject::array(vec![
+            Box::new(CObject::typed_data(TypedData::Float32(vec![1., 2.]))),
+            Box::new(CObject::typed_data(TypedData::Float64(vec![3.]))),
+            Box::new(CObject::int64(4)),
+        ]);
+        let err = batch
+            .as_mut()
+            .typed_data_batch_element_type(rt)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            BatchTypeError::Mixed {
+                index: 1,
+                expected: TypedDataType::Float32,
+                found: TypedDataType::Float64,
+            }
+        );
+        assert_eq!(err.to_string(), "At [1]: Expected Float32, found Float64.");
+
+        let mut batch = CObject::array(vec![Box::new(CObject::int64(4))]);
+        assert!(matches!(
+            batch.as_mut().typed_data_batch_element_type(rt),
+            Err(BatchTypeError::NotTypedData { index: 0, .. })
+        ));
+        assert_eq!(
+            CObject::array(Vec::new())
+                .as_mut()
+                .typed_data_batch_element_type(rt),
+            Err(BatchTypeError::Empty)
+        );
+    }
+}
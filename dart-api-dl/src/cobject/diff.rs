@@ -0,0 +1,162 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display};
+
+use crate::{utils::quietly, DartRuntime};
+
+use super::{CObjectMut, CObjectValuesRef};
+
+/// A difference between two objects, see [`CObjectMut::diff()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    /// The array indices leading to the difference, empty for the root object.
+    pub path: Vec<usize>,
+    /// A description of the expected value.
+    pub expected: String,
+    /// A description of the found value.
+    pub found: String,
+}
+
+impl Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "at root")?;
+        } else {
+            write!(f, "at ")?;
+            for idx in &self.path {
+                write!(f, "[{idx}]")?;
+            }
+        }
+        write!(f, ": expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Compares this (expected) object structurally with the `other` (found) object.
+    ///
+    /// Arrays are compared element wise, all other values are compared by their
+    /// [`CObjectValuesRef`] representation. As such an `Int32` and an `Int64` with
+    /// the same value differ.
+    ///
+    /// Returns an empty vector if the objects don't differ.
+    ///
+    /// This is meant as debugging aid, e.g. for tests of message protocols.
+    pub fn diff(&self, other: &CObjectMut<'_>, rt: DartRuntime) -> Vec<DiffEntry> {
+        let mut diff = Vec::new();
+        let mut path = Vec::new();
+        quietly(|| diff_into(rt, self, other, &mut path, &mut diff));
+        diff
+    }
+}
+
+fn diff_into(
+    rt: DartRuntime,
+    expected: &CObjectMut<'_>,
+    found: &CObjectMut<'_>,
+    path: &mut Vec<usize>,
+    diff: &mut Vec<DiffEntry>,
+) {
+    if let (Some(expected), Some(found)) = (expected.as_array(rt), found.as_array(rt)) {
+        for idx in 0..expected.len().max(found.len()) {
+            path.push(idx);
+            match (expected.get(idx), found.get(idx)) {
+                (Some(expected), Some(found)) => diff_into(rt, expected, found, path, diff),
+                (expected, found) => diff.push(DiffEntry {
+                    path: path.clone(),
+                    expected: expected.map_or_else(|| "nothing".into(), |obj| describe(rt, obj)),
+                    found: found.map_or_else(|| "nothing".into(), |obj| describe(rt, obj)),
+                }),
+            }
+            path.pop();
+        }
+    } else {
+        let expected = describe(rt, expected);
+        let found = describe(rt, found);
+        if expected != found {
+            diff.push(DiffEntry {
+                path: path.clone(),
+                expected,
+                found,
+            });
+        }
+    }
+}
+
+fn describe(rt: DartRuntime, obj: &CObjectMut<'_>) -> String {
+    match obj.value_ref(rt) {
+        Ok(CObjectValuesRef::Array(array)) => format!("Array(len {})", array.len()),
+        Ok(value) => format!("{value:?}"),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cobject::CObject;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_nested_messages() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut expected = CObject::array(vec![
+            Box::new(CObject::string("add").unwrap()),
+            Box::new(CObject::int64(5)),
+            Box::new(CObject::array(vec![
+                Box::new(CObject::bool(true)),
+                Box::new(CObject::null()),
+            ])),
+        ]);
+        let mut found = CObject::array(vec![
+            Box::new(CObject::string("add").unwrap()),
+            Box::new(CObject::string("5").unwrap()),
+            Box::new(CObject::array(vec![Box::new(CObject::bool(true))])),
+            Box::new(CObject::int32(1)),
+        ]);
+
+        let diff = expected.as_mut().diff(&found.as_mut(), rt);
+        let diff = diff.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            diff,
+            [
+                r#"at [1]: expected Int64(5), found String("5")"#,
+                "at [2][1]: expected Null, found nothing",
+                "at [3]: expected nothing, found Int32(1)",
+            ]
+        );
+        let expected = expected.as_mut();
+        assert!(expected.diff(&expected, rt).is_empty());
+    }
+
+    #[test]
+    fn test_diff_root() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let diff = CObject::int64(1)
+            .as_mut()
+            .diff(&CObject::array(Vec::new()).as_mut(), rt);
+        assert_eq!(
+            diff,
+            [DiffEntry {
+                path: Vec::new(),
+                expected: "Int64(1)".into(),
+                found: "Array(len 0)".into(),
+            }]
+        );
+        assert_eq!(
+            diff[0].to_string(),
+            "at root: expected Int64(1), found Array(len 0)"
+        );
+    }
+}
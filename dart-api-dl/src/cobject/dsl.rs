@@ -0,0 +1,143 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{convert::Infallible, ffi::NulError};
+
+use thiserror::Error;
+
+/// Building a [`CObject`] with the [`cobject!`] macro failed.
+///
+/// [`CObject`]: super::CObject
+/// [`cobject!`]: crate::cobject!
+#[derive(Debug, Error)]
+pub enum CObjectError {
+    /// A string contained a null byte.
+    #[error("A string contained a null byte.")]
+    NulInString(#[from] NulError),
+}
+
+impl From<Infallible> for CObjectError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+/// Builds a [`CObject`] tree from a JSON like description.
+///
+/// Evaluates to a `Result<CObject, CObjectError>`.
+///
+/// Supported forms are:
+///
+/// - `null` for a null object.
+/// - `[...]` for an array, with a comma separated list of any of these forms as elements.
+/// - `{ bytes: expr }` for `Uint8` typed data, `expr` must be convertible into a `Vec<u8>`,
+///   e.g. an array literal like `[1, 2, 3]`.
+/// - Any other expression is converted with `CObject::try_from()`, which supports
+///   e.g. bools, ints, floats, strings, send ports and other [`CObject`]s.
+///
+/// # Example
+///
+/// ```
+/// use xayn_dart_api_dl::{cobject, cobject::CObject};
+///
+/// let name = "ok";
+/// let reply: CObject = cobject!([name, { bytes: [1, 2, 3] }, 42, [true, -0.5, null]]).unwrap();
+/// # drop(reply);
+/// ```
+///
+/// [`CObject`]: crate::cobject::CObject
+#[macro_export]
+macro_rules! cobject {
+    // Splits the elements of an array at the top level commas.
+    (@elements [$($done:tt)*] [$($current:tt)+] , $($rest:tt)*) => {
+        $crate::cobject!(@elements [$($done)* ($crate::cobject!($($current)+)),] [] $($rest)*)
+    };
+    (@elements [$($done:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::cobject!(@elements [$($done)*] [$($current)* $next] $($rest)*)
+    };
+    (@elements [$($done:tt)*] [$($current:tt)+]) => {
+        $crate::cobject!(@elements [$($done)* ($crate::cobject!($($current)+)),] [])
+    };
+    (@elements [$(($done:expr),)*] []) => {
+        ::std::vec![$($done.map(::std::boxed::Box::new)),*]
+    };
+    (null) => {
+        ::core::result::Result::<_, $crate::cobject::CObjectError>::Ok(
+            $crate::cobject::CObject::null(),
+        )
+    };
+    ([ $($elements:tt)* ]) => {
+        ::core::iter::Iterator::collect::<
+            ::core::result::Result<::std::vec::Vec<_>, $crate::cobject::CObjectError>,
+        >(::core::iter::IntoIterator::into_iter(
+            $crate::cobject!(@elements [] [] $($elements)*),
+        ))
+        .map($crate::cobject::CObject::array)
+    };
+    ({ bytes: $bytes:expr }) => {
+        ::core::result::Result::<_, $crate::cobject::CObjectError>::Ok(
+            $crate::cobject::CObject::typed_data($crate::cobject::TypedData::Uint8(
+                ::core::convert::Into::<::std::vec::Vec<u8>>::into($bytes),
+            )),
+        )
+    };
+    ($value:expr) => {
+        <$crate::cobject::CObject as ::core::convert::TryFrom<_>>::try_from($value)
+            .map_err($crate::cobject::CObjectError::from)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cobject::{CObject, TypedDataRef},
+        DartRuntime,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_building_nested_cobject() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw(10).unwrap();
+        let mut obj =
+            cobject!([port, "ok", { bytes: [1, 2, 3] }, 42, [true, -0.5, null, []]]).unwrap();
+        let obj = obj.as_mut();
+        let elements = obj.as_array(rt).unwrap();
+        assert_eq!(elements.len(), 5);
+        assert_eq!(
+            elements[0].as_send_port(rt).unwrap().unwrap().as_raw().0,
+            10
+        );
+        assert_eq!(elements[1].as_string(rt), Some("ok"));
+        assert!(matches!(
+            elements[2].as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8(&[1, 2, 3])), _))
+        ));
+        assert_eq!(elements[3].as_int(rt), Some(42));
+
+        let nested = elements[4].as_array(rt).unwrap();
+        assert_eq!(nested.len(), 4);
+        assert_eq!(nested[0].as_bool(rt), Some(true));
+        assert_eq!(nested[1].as_double(rt), Some(-0.5));
+        assert_eq!(nested[2].as_null(rt), Some(()));
+        assert_eq!(nested[3].as_array(rt).map(<[_]>::len), Some(0));
+    }
+
+    #[test]
+    fn test_building_cobject_with_nul_fails() {
+        let res: Result<CObject, _> = cobject!([1, "a\0b"]);
+        assert!(matches!(res, Err(CObjectError::NulInString(_))));
+    }
+}
@@ -14,7 +14,12 @@
 
 use std::{
     convert::{TryFrom, TryInto},
-    ffi::{c_void, CString, NulError},
+    ffi::{c_void, CStr, CString, NulError},
+    fmt::{self, Display},
+    ptr,
+    str::Utf8Error,
+    sync::mpsc::Sender,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use dart_api_dl_sys::{
@@ -26,16 +31,92 @@ use dart_api_dl_sys::{
     _Dart_CObject__bindgen_ty_1__bindgen_ty_3,
 };
 
-use crate::{ports::SendPort, utils::prepare_dart_array_parts_mut};
+use thiserror::Error;
 
-use super::{CObjectMut, Capability, CustomExternalTyped, TypedData};
+use crate::{
+    ports::{DartPortId, SendPort},
+    utils::prepare_dart_array_parts_mut,
+    DartRuntime,
+    ReadOnlyRuntime,
+};
+
+use super::{
+    CObjectMut,
+    CObjectValuesRef,
+    Capability,
+    CustomExternalTyped,
+    ExternalTypedData,
+    TypedData,
+    TypedDataType,
+    UnknownCObjectType,
+};
+
+#[cfg(feature = "allocator-api")]
+mod allocator;
+mod eq;
+
+#[cfg(feature = "allocator-api")]
+pub use self::allocator::CObjectAllocator;
 
 /// Wrapper around a [`Dart_CObject`] which is owned by rust.
 //FIXME impl debug when we add a `CObjectRef` with a `value_ref()` method.
 #[repr(transparent)]
 pub struct CObject(Dart_CObject);
 
+/// The packed typed data buffers have different data types.
+#[derive(Debug, Error)]
+#[error("The packed typed data buffers have different data types.")]
+pub struct MixedTypedDataTypes;
+
+/// The string is longer than allowed, see [`CObject::string_bounded()`].
+#[derive(Debug, Error)]
+#[error("The string of {len} bytes exceeds the maximum of {max_len} bytes.")]
+pub struct TooLong {
+    /// The length of the string in bytes.
+    pub len: usize,
+    /// The maximum length in bytes.
+    pub max_len: usize,
+}
+
+/// Creating a bounded string failed, see [`CObject::string_bounded()`].
+#[derive(Debug, Error)]
+pub enum StringBoundedFailed {
+    /// The string is longer than allowed.
+    #[error(transparent)]
+    TooLong(#[from] TooLong),
+    /// The string contains `0` bytes.
+    #[error(transparent)]
+    InteriorNul(#[from] NulError),
+}
+
+/// The data doesn't match the shape of the matrix, see [`CObject::matrix_f64()`].
+#[derive(Debug, Error)]
+#[error("{len} elements don't form a {rows}x{cols} matrix.")]
+pub struct ShapeError {
+    /// The number of rows.
+    pub rows: usize,
+    /// The number of columns.
+    pub cols: usize,
+    /// The number of elements.
+    pub len: usize,
+}
+
+/// A raw object can't be copied, see [`CObject::from_raw_copy()`].
+#[derive(Debug, Error)]
+pub enum UnsupportedForCopy {
+    /// The object is (or contains) external typed data.
+    #[error("External typed data can't be copied as it's owner is unknown.")]
+    ExternalTypedData,
+    /// The object is (or contains objects) of an unknown type.
+    #[error(transparent)]
+    UnknownType(#[from] UnknownCObjectType),
+}
+
 impl CObject {
+    fn from_raw(raw: Dart_CObject) -> Self {
+        Self(raw)
+    }
+
     /// Create a [`CObjectMut`].
     ///
     /// Be aware that this acts mostly like a read-only reference but due to
@@ -48,15 +129,22 @@ impl CObject {
 
     /// Create a [`CObject`] containing null.
     pub fn null() -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kNull,
             value: _Dart_CObject__bindgen_ty_1 { as_bool: false },
         })
     }
 
+    /// Create a [`CObject`] for a nullable value, `None` is mapped to null.
+    ///
+    /// See [`CObjectMut::as_optional()`].
+    pub fn optional(val: Option<CObject>) -> Self {
+        val.unwrap_or_else(Self::null)
+    }
+
     /// Create a [`CObject`] containing a bool.
     pub fn bool(val: bool) -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kBool,
             value: _Dart_CObject__bindgen_ty_1 { as_bool: val },
         })
@@ -64,7 +152,7 @@ impl CObject {
 
     /// Create a [`CObject`] containing a 32bit signed int.
     pub fn int32(val: i32) -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kInt32,
             value: _Dart_CObject__bindgen_ty_1 { as_int32: val },
         })
@@ -72,20 +160,70 @@ impl CObject {
 
     /// Create a [`CObject`] containing a 64bit signed int.
     pub fn int64(val: i64) -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kInt64,
             value: _Dart_CObject__bindgen_ty_1 { as_int64: val },
         })
     }
 
+    /// Create a [`CObject`] containing flags packed into a 64bit int.
+    ///
+    /// The bits are reinterpreted as `i64`, like dart's `int` does.
+    ///
+    /// See [`CObjectMut::as_flags()`].
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn flags(bits: u64) -> Self {
+        Self::int64(bits as i64)
+    }
+
     /// Create a [`CObject`] containing a 64bit float.
     pub fn double(val: f64) -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kDouble,
             value: _Dart_CObject__bindgen_ty_1 { as_double: val },
         })
     }
 
+    /// Create a [`CObject`] containing a 32bit float.
+    ///
+    /// Dart has no 32bit float objects, so this is sent as double. The widening
+    /// is lossless, use [`CObjectMut::as_float32()`] to read it back.
+    pub fn float32(val: f32) -> Self {
+        Self::double(val.into())
+    }
+
+    /// Create a [`CObject`] containing a duration.
+    ///
+    /// The duration is encoded as 64bit int of microseconds, like dart's
+    /// `Duration.inMicroseconds`. Durations which don't fit are saturated.
+    pub fn duration(val: Duration) -> Self {
+        Self::int64(val.as_micros().try_into().unwrap_or(i64::MAX))
+    }
+
+    /// Create a [`CObject`] containing a point in time.
+    ///
+    /// The time is encoded as 64bit int of milliseconds since the unix epoch,
+    /// as expected by dart's `DateTime.fromMillisecondsSinceEpoch`. Times which
+    /// don't fit are saturated.
+    pub fn from_system_time(val: SystemTime) -> Self {
+        let millis = match val.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_millis().try_into().unwrap_or(i64::MAX),
+            Err(before) => {
+                i64::try_from(before.duration().as_millis()).map_or(i64::MIN, |millis| -millis)
+            }
+        };
+        Self::int64(millis)
+    }
+
+    /// Create a [`CObject`] containing a UUID.
+    ///
+    /// The UUID is encoded as `Uint8List` of its 16 bytes in big endian order,
+    /// see [`CObjectMut::as_uuid()`].
+    #[cfg(feature = "uuid")]
+    pub fn uuid(val: uuid::Uuid) -> Self {
+        Self::typed_data(TypedData::Uint8(val.as_bytes().to_vec()))
+    }
+
     /// Create a [`CObject`] containing a string.
     ///
     /// This clones the string.
@@ -95,7 +233,7 @@ impl CObject {
     /// If the string contains `0` bytes an error is returned.
     pub fn string(val: impl AsRef<str>) -> Result<Self, NulError> {
         let val = CString::new(val.as_ref())?;
-        Ok(Self(Dart_CObject {
+        Ok(Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kString,
             value: _Dart_CObject__bindgen_ty_1 {
                 as_string: val.into_raw(),
@@ -103,6 +241,85 @@ impl CObject {
         }))
     }
 
+    /// Create a [`CObject`] containing a string of at most `max_len` bytes.
+    ///
+    /// This guards against sending accidentally huge strings, e.g. a log line
+    /// which ballooned, without allocating a copy of them first.
+    ///
+    /// # Errors
+    ///
+    /// If the string is longer than `max_len` bytes or, like with [`CObject::string()`],
+    /// if it contains `0` bytes.
+    pub fn string_bounded(val: &str, max_len: usize) -> Result<Self, StringBoundedFailed> {
+        if val.len() > max_len {
+            Err(TooLong {
+                len: val.len(),
+                max_len,
+            }
+            .into())
+        } else {
+            Ok(Self::string(val)?)
+        }
+    }
+
+    /// Create a [`CObject`] containing a copy of a string.
+    ///
+    /// Like [`CObject::string()`], but skips the check for nul bytes when already
+    /// holding a [`CStr`], e.g. from interop with another C library. The string is
+    /// still validated to be utf-8, use [`CObject::string_from_cstr()`] to skip
+    /// that, too.
+    ///
+    /// The string is only borrowed, so it must be copied into the object like with
+    /// [`CObject::string()`]. Use [`CObject::from_c_string()`] to move an owned
+    /// string into the object without copying it.
+    ///
+    /// # Errors
+    ///
+    /// If the string is not valid utf-8.
+    pub fn from_c_str(val: &CStr) -> Result<Self, Utf8Error> {
+        val.to_str()?;
+        Ok(Self::from_c_string_unchecked(val.to_owned()))
+    }
+
+    /// Create a [`CObject`] containing a copy of a string without validating it.
+    ///
+    /// Like [`CObject::from_c_str()`], but skips the utf-8 validation, e.g. for
+    /// strings from another C library which are known to be valid.
+    ///
+    /// # Safety
+    ///
+    /// The string must be valid UTF-8, as dart requires it and this library
+    /// reads strings without validating them.
+    pub unsafe fn string_from_cstr(val: &CStr) -> Self {
+        Self::from_c_string_unchecked(val.to_owned())
+    }
+
+    /// Create a [`CObject`] containing a string without copying it into the object.
+    ///
+    /// The allocation of the [`CString`] is owned by the object from now on and
+    /// freed when the object is dropped, so it must not be freed elsewhere, e.g.
+    /// by recreating the [`CString`] from a pointer read from the object. Dart
+    /// copies the string when the object is posted, it never takes ownership of
+    /// the allocation.
+    ///
+    /// # Errors
+    ///
+    /// If the string is not valid utf-8.
+    pub fn from_c_string(val: CString) -> Result<Self, Utf8Error> {
+        val.to_str()?;
+        Ok(Self::from_c_string_unchecked(val))
+    }
+
+    /// Moves the string into a new object, the caller must make sure it's valid utf-8.
+    fn from_c_string_unchecked(val: CString) -> Self {
+        Self::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kString,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_string: val.into_raw(),
+            },
+        })
+    }
+
     /// Create a [`CObject`] containing a string.
     ///
     /// Like [`CObject::string()`], but cuts off when encountering a `'\0'`.
@@ -111,7 +328,7 @@ impl CObject {
         let end_idx = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
         //Safe we just did the checks
         let c_string = unsafe { CString::from_vec_unchecked(bytes[..end_idx].to_owned()) };
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kString,
             value: _Dart_CObject__bindgen_ty_1 {
                 as_string: c_string.into_raw(),
@@ -120,9 +337,20 @@ impl CObject {
     }
 
     /// Create a [`CObject`] containing a [`SendPort`].
+    ///
+    /// Both the port id and the origin id are written into the object,
+    /// so a port received from dart keeps its origin when sent back.
+    /// Use [`SendPort::without_origin()`] to drop the origin.
     pub fn send_port(port: SendPort) -> Self {
         let (id, origin_id) = port.as_raw();
-        Self(Dart_CObject {
+        Self::raw_send_port(id, origin_id)
+    }
+
+    /// Create a [`CObject`] containing a send port with given raw ids.
+    ///
+    /// Unlike [`CObject::send_port()`] this can represent the `ILLEGAL_PORT`.
+    pub(crate) fn raw_send_port(id: DartPortId, origin_id: DartPortId) -> Self {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kSendPort,
             value: _Dart_CObject__bindgen_ty_1 {
                 as_send_port: _Dart_CObject__bindgen_ty_1__bindgen_ty_1 { id, origin_id },
@@ -132,7 +360,7 @@ impl CObject {
 
     /// Create a [`CObject`] containing a [`Capability`].
     pub fn capability(id: Capability) -> Self {
-        Self(Dart_CObject {
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kCapability,
             value: _Dart_CObject__bindgen_ty_1 {
                 as_capability: _Dart_CObject__bindgen_ty_1__bindgen_ty_2 { id },
@@ -141,15 +369,27 @@ impl CObject {
     }
 
     /// Create a [`CObject`] containing an array of boxed [`CObject`]'s.
+    ///
+    /// Dart receives arrays as fixed-length `List<Object?>`, its elements can be
+    /// replaced but it can't grow. See [`CObject::fixed_array()`].
+    ///
+    /// An array object can't grow in place: a [`CObject`] is `repr(transparent)`
+    /// over a [`Dart_CObject`], which has no field for the capacity of the array.
+    /// Use a [`CObjectArray`] to build an array element by element instead.
     #[allow(clippy::vec_box)]
     pub fn array(array: Vec<Box<CObject>>) -> Self {
-        let bs = array.into_boxed_slice();
-        // We can't really have an array.len() > isize::MAX here, but we
-        // really don't want to panic.
-        let len = bs.len().try_into().unwrap_or(isize::MAX);
-        // SAFE: as CObject is repr(transparent) as such `Box<CObject>` and `*mut Dart_CObject` have same layout.
-        let ptr = Box::into_raw(bs).cast::<*mut Dart_CObject>();
-        Self(Dart_CObject {
+        // dart expects a null pointer for empty arrays
+        let (len, ptr) = if array.is_empty() {
+            (0, ptr::null_mut())
+        } else {
+            let bs = array.into_boxed_slice();
+            // We can't really have an array.len() > isize::MAX here, but we
+            // really don't want to panic.
+            let len = bs.len().try_into().unwrap_or(isize::MAX);
+            // SAFE: as CObject is repr(transparent) as such `Box<CObject>` and `*mut Dart_CObject` have same layout.
+            (len, Box::into_raw(bs).cast::<*mut Dart_CObject>())
+        };
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kArray,
             value: _Dart_CObject__bindgen_ty_1 {
                 as_array: _Dart_CObject__bindgen_ty_1__bindgen_ty_3 {
@@ -160,6 +400,46 @@ impl CObject {
         })
     }
 
+    /// Create a [`CObject`] containing an array which dart receives as fixed-length list.
+    ///
+    /// This is the same as [`CObject::array()`], as dart receives all arrays as
+    /// fixed-length lists. Use it to make the intent explicit, if dart ever adds a
+    /// way to send const lists it will be used here.
+    ///
+    /// ```
+    /// # use xayn_dart_api_dl::cobject::{CObject, CObjectType};
+    /// let mut list = CObject::fixed_array(vec![Box::new(CObject::int64(1))]);
+    /// assert_eq!(list.as_mut().r#type(), Ok(CObjectType::Array));
+    /// ```
+    #[allow(clippy::vec_box)]
+    pub fn fixed_array(array: Vec<Box<CObject>>) -> Self {
+        Self::array(array)
+    }
+
+    /// Create a [`CObject`] containing an array of the objects of the iterator.
+    ///
+    /// The elements are boxed while collecting them, if the iterator has a lower
+    /// size hint the array is pre-allocated accordingly. Like with
+    /// [`CObject::array()`] dart receives it as fixed-length list.
+    pub fn array_from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = CObject>,
+    {
+        let iter = iter.into_iter();
+        let mut array = Vec::with_capacity(iter.size_hint().0);
+        array.extend(iter.map(Box::new));
+        Self::array(array)
+    }
+
+    /// Starts building an array object with space for `capacity` elements.
+    ///
+    /// Push the elements onto the returned [`CObjectArray`] and create the array
+    /// object with [`CObjectArray::finish()`].
+    #[must_use = "the elements must be pushed onto the returned array"]
+    pub fn array_with_capacity(capacity: usize) -> CObjectArray {
+        CObjectArray::with_capacity(capacity)
+    }
+
     /// Create a [`CObject`] containing typed data.
     ///
     /// This will for now internally delegate to creating external
@@ -172,23 +452,306 @@ impl CObject {
         Self::external_typed_data(data)
     }
 
-    /// Create a [`CObject`] containing a .
+    /// Packs multiple typed data buffers into a single [`CObject`] containing typed data.
+    ///
+    /// This has less overhead than sending each buffer as separate (external) typed data,
+    /// e.g. only a single finalizer needs to run once dart drops the data.
+    ///
+    /// Returns the packed data and an array of `buffers.len() + 1` int objects. The
+    /// elements of the `i`-th buffer are in the range `offsets[i]..offsets[i + 1]`, which
+    /// can be used in dart with e.g. `Float32List.sublistView(data, start, end)`.
+    ///
+    /// Packing no buffers returns empty `ByteData`.
+    ///
+    /// # Errors
+    ///
+    /// If the buffers have different data types.
+    pub fn packed_typed_data(buffers: Vec<TypedData>) -> Result<(Self, Self), MixedTypedDataTypes> {
+        let mut buffers = buffers.into_iter();
+        let mut packed = TypedData::ByteData(Box::default());
+        let mut offsets = vec![0];
+        if let Some(first) = buffers.next() {
+            packed = first;
+            offsets.push(packed.len());
+        }
+        for buffer in buffers {
+            packed.try_append(buffer).map_err(|_| MixedTypedDataTypes)?;
+            offsets.push(packed.len());
+        }
+        let offsets = offsets
+            .into_iter()
+            .map(|offset| Box::new(CObject::int64(offset.try_into().unwrap_or(i64::MAX))))
+            .collect();
+        Ok((Self::typed_data(packed), Self::array(offsets)))
+    }
+
+    /// Create a [`CObject`] containing external typed data.
     pub fn external_typed_data<CET>(data: CET) -> Self
     where
         CET: CustomExternalTyped,
     {
-        Self(Dart_CObject {
+        //Safe: due to the unsafe contract on CustomExternalTyped
+        let etd = data.into_external_typed_data();
+        let etd = crate::diagnostics::track_external_typed_data(etd);
+        Self::from_raw(Dart_CObject {
             type_: Dart_CObject_Type::Dart_CObject_kExternalTypedData,
             value: _Dart_CObject__bindgen_ty_1 {
-                //Safe: due to the unsafe contract on CustomExternalTyped
-                as_external_typed_data: data.into_external_typed_data(),
+                as_external_typed_data: etd,
             },
         })
     }
+
+    /// Create a [`CObject`] containing `Uint8List` external typed data whose buffer is recycled.
+    ///
+    /// Once the data is finalized, i.e. when dart garbage collects the received
+    /// list or when the object is dropped without being sent, the buffer is sent
+    /// to `return_to`, e.g. to reuse the allocation for the next message. If the
+    /// receiver was dropped the buffer is dropped too.
+    ///
+    /// Dart can write to the list, so the returned buffer might have been modified.
+    pub fn external_typed_data_recyclable(buf: Vec<u8>, return_to: Sender<Vec<u8>>) -> Self {
+        Self::external_typed_data(RecyclableBuffer { buf, return_to })
+    }
+
+    /// Create a [`CObject`] containing `Float32List` external typed data.
+    ///
+    /// Each element is narrowed to `f32`, which is lossy: values are rounded to
+    /// the nearest representable `f32` and values out of range become infinite.
+    pub fn float32_from_f64(data: &[f64]) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let data = data.iter().map(|&val| val as f32).collect::<Vec<_>>();
+        Self::external_typed_data(data)
+    }
+
+    /// Create a [`CObject`] containing a matrix of doubles.
+    ///
+    /// The `data` is in row-major order and is sent as `[rows, cols, Float64List]`,
+    /// so that the dart side can reshape it. See [`CObjectMut::as_matrix_f64()`].
+    ///
+    /// # Errors
+    ///
+    /// If the length of `data` isn't `rows * cols`.
+    pub fn matrix_f64(rows: usize, cols: usize, data: &[f64]) -> Result<Self, ShapeError> {
+        match (i64::try_from(rows), i64::try_from(cols)) {
+            (Ok(dart_rows), Ok(dart_cols)) if rows.checked_mul(cols) == Some(data.len()) => {
+                Ok(Self::array(vec![
+                    Box::new(Self::int64(dart_rows)),
+                    Box::new(Self::int64(dart_cols)),
+                    Box::new(Self::typed_data(TypedData::Float64(data.to_vec()))),
+                ]))
+            }
+            _ => Err(ShapeError {
+                rows,
+                cols,
+                len: data.len(),
+            }),
+        }
+    }
+
+    /// Creates an owned deep copy of a raw [`Dart_CObject`] produced by other code.
+    ///
+    /// Arrays are copied recursively and typed data is copied into new external
+    /// typed data.
+    ///
+    /// Like reading, copying never calls into dart, so no [`DartRuntime`] is needed.
+    ///
+    /// # Safety
+    ///
+    /// The object must be sound as described in [`CObjectMut::with_pointer()`] and
+    /// the object as well as all nested objects must not be accessed by other code
+    /// while copying.
+    ///
+    /// This can't be a safe function: the fields of a [`Dart_CObject`] are public,
+    /// so safe code can put arbitrary pointers and lengths into strings, arrays and
+    /// typed data. Null pointers could be rejected, but dangling or misaligned
+    /// pointers and wrong lengths can't be detected, neither for the object itself
+    /// nor for any nested object.
+    ///
+    /// # Errors
+    ///
+    /// - If the object is (or contains) external typed data, as it's unknown who
+    ///   owns and eventually frees its data.
+    /// - If the object is (or contains objects) of an unknown type.
+    pub unsafe fn from_raw_copy(raw: &Dart_CObject) -> Result<Self, UnsupportedForCopy> {
+        // the shallow copy is not owned, so it must not be dropped as `CObject`
+        let mut shallow = *raw;
+        let shallow = CObjectMut {
+            partial_mut: &mut shallow,
+        };
+        let rt = ReadOnlyRuntime::new();
+        if contains_external_typed_data(rt, &shallow) {
+            return Err(UnsupportedForCopy::ExternalTypedData);
+        }
+        Ok(shallow.deep_copy(rt)?)
+    }
+
+    /// Replaces all (nested) external typed data with null, without finalizing it.
+    ///
+    /// This is for when the ownership of the external typed data was transferred
+    /// elsewhere, e.g. after handing the buffers of a manually managed object to
+    /// another C library which frees them. Dropping the object afterwards won't
+    /// run the finalizers, so it won't free the buffers a second time. If the
+    /// ownership wasn't transferred the buffers are leaked.
+    pub fn neutralize_external_data(&mut self, rt: DartRuntime) {
+        self.as_mut().null_external_typed_objects(rt);
+    }
+
+    /// Returns a short human-readable summary of the object, e.g. for logging.
+    ///
+    /// This doesn't need a [`DartRuntime`], see [`CObjectValuesRef`] for the format.
+    /// The data of (external) typed data isn't read, only its size is shown.
+    /// Objects of an unknown type are shown as `<unknown>`.
+    pub fn display(&self) -> impl Display + '_ {
+        DisplayCObject(self)
+    }
+
+    /// Creates a deep copy of this object, e.g. to cache message templates.
+    ///
+    /// Arrays are cloned recursively and (external) typed data is copied into new
+    /// external typed data of the same [`TypedDataType`], which owns its copy.
+    /// Dropping the clone and the original is independent of each other.
+    ///
+    /// # Errors
+    ///
+    /// If the object is (or contains objects) of an unknown type, which can only be
+    /// created through unsafe code, e.g. typed data with an unknown element type
+    /// from a custom [`CustomExternalTyped`] implementation.
+    pub fn deep_clone(&self) -> Result<Self, UnknownCObjectType> {
+        let rt = ReadOnlyRuntime::new();
+        // the shallow copy is not owned, so it must not be dropped as `CObject`
+        let mut shallow = self.0;
+        let shallow = CObjectMut {
+            partial_mut: &mut shallow,
+        };
+        shallow.deep_copy(rt)
+    }
+}
+
+/// See [`CObject::external_typed_data_recyclable()`].
+struct RecyclableBuffer {
+    buf: Vec<u8>,
+    return_to: Sender<Vec<u8>>,
+}
+
+unsafe impl CustomExternalTyped for RecyclableBuffer {
+    fn into_external_typed_data(mut self) -> ExternalTypedData {
+        unsafe extern "C" fn recycle(_data: *mut c_void, peer: *mut c_void) {
+            // Safe: the peer is the leaked box
+            let RecyclableBuffer { buf, return_to } =
+                *unsafe { Box::from_raw(peer.cast::<RecyclableBuffer>()) };
+            // the pool is gone if this fails
+            let _ = return_to.send(buf);
+        }
+
+        // empty data must use a null pointer, see `prepare_dart_array_parts()`
+        let data = if self.buf.is_empty() {
+            ptr::null_mut()
+        } else {
+            self.buf.as_mut_ptr()
+        };
+        let length = self.buf.len().try_into().unwrap();
+        ExternalTypedData {
+            type_: TypedDataType::Uint8.into(),
+            length,
+            data,
+            peer: Box::into_raw(Box::new(self)).cast(),
+            callback: Some(recycle),
+        }
+    }
+}
+
+/// See [`CObject::display()`].
+struct DisplayCObject<'a>(&'a CObject);
+
+impl Display for DisplayCObject<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut shallow = self.0 .0;
+        let shallow = CObjectMut {
+            partial_mut: &mut shallow,
+        };
+        let rt = ReadOnlyRuntime::new();
+        match shallow.read_value_ref(rt) {
+            Ok(value) => value.fmt(f),
+            Err(_) => write!(f, "<unknown>"),
+        }
+    }
+}
+
+/// An owned array which can grow, converted into an array [`CObject`] once complete.
+///
+/// A [`Dart_CObject`] can't store the capacity of an array, so an array [`CObject`]
+/// can't grow in place. Instead keep the elements in this type, e.g. across multiple
+/// handler calls, where pushing grows the backing allocation amortized like a [`Vec`].
+///
+/// ```
+/// # use xayn_dart_api_dl::cobject::{CObject, CObjectArray};
+/// let mut array = CObjectArray::new();
+/// for value in 0..3 {
+///     array.push(CObject::int64(value));
+/// }
+/// let message = array.finish();
+/// ```
+#[derive(Default)]
+#[must_use]
+pub struct CObjectArray {
+    // dart arrays are arrays of pointers
+    #[allow(clippy::vec_box)]
+    elements: Vec<Box<CObject>>,
+}
+
+impl CObjectArray {
+    /// Creates an empty array.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty array with space for `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            elements: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Appends an element to the array.
+    pub fn push(&mut self, value: CObject) {
+        self.elements.push(Box::new(value));
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns `true` if the array has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns the number of elements the array can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.elements.capacity()
+    }
+
+    /// Creates the array object, same as [`CObject::from()`].
+    pub fn finish(self) -> CObject {
+        CObject::array(self.elements)
+    }
+}
+
+impl From<CObjectArray> for CObject {
+    fn from(array: CObjectArray) -> Self {
+        array.finish()
+    }
 }
 
 impl Drop for CObject {
     fn drop(&mut self) {
+        #[cfg(feature = "allocator-api")]
+        if let Some(allocator) = self.take_allocator() {
+            // Safe: the buffers were allocated by the allocator
+            unsafe { self.drop_in(allocator) };
+            return;
+        }
         match self.0.type_ {
             Dart_CObject_Type::Dart_CObject_kNull
             | Dart_CObject_Type::Dart_CObject_kBool
@@ -200,13 +763,20 @@ impl Drop for CObject {
             Dart_CObject_Type::Dart_CObject_kString => {
                 drop(unsafe { CString::from_raw(self.0.value.as_string) });
             }
-            Dart_CObject_Type::Dart_CObject_kArray => drop(unsafe {
-                let (ptr, len) = prepare_dart_array_parts_mut(
-                    self.0.value.as_array.values,
-                    self.0.value.as_array.length,
-                );
-                Vec::from_raw_parts(ptr, len, len)
-            }),
+            Dart_CObject_Type::Dart_CObject_kArray => {
+                let (ptr, len) = unsafe {
+                    prepare_dart_array_parts_mut(
+                        self.0.value.as_array.values.cast::<Box<CObject>>(),
+                        self.0.value.as_array.length,
+                    )
+                };
+                // Safe:
+                // - the array was created by `CObject::array()` from a boxed slice
+                // - `Box<CObject>` and `*mut Dart_CObject` have the same layout
+                // - an empty array has a null pointer, which `prepare_dart_array_parts_mut()`
+                //   maps to a dangling pointer like the one of an empty boxed slice
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) });
+            }
             Dart_CObject_Type::Dart_CObject_kExternalTypedData => {
                 // we can only hit this if we didn't send it, in
                 // which case we can drop it.
@@ -252,6 +822,7 @@ impl_from!(
     bool => bool;
     i32 => int32;
     i64 => int64;
+    f64 => double;
     SendPort => send_port;
     Vec<Box<CObject>> => array;
     TypedData => typed_data;
@@ -264,3 +835,490 @@ impl TryFrom<String> for CObject {
         CObject::string(value)
     }
 }
+
+impl TryFrom<&str> for CObject {
+    type Error = NulError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        CObject::string(value)
+    }
+}
+
+fn contains_external_typed_data(rt: ReadOnlyRuntime, obj: &CObjectMut<'_>) -> bool {
+    match obj.read_value_ref(rt) {
+        Ok(CObjectValuesRef::TypedData { external_typed, .. }) => external_typed,
+        Ok(CObjectValuesRef::Array(array)) => array
+            .iter()
+            .any(|element| contains_external_typed_data(rt, element)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem::ManuallyDrop,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use static_assertions::{assert_eq_align, assert_eq_size};
+
+    use crate::cobject::{CObjectType, ExternalTypedData, TypedDataRef, TypedDataType};
+
+    use super::*;
+
+    #[test]
+    fn test_cobject_has_the_layout_of_dart_cobject() {
+        assert_eq_size!(CObject, Dart_CObject);
+        assert_eq_align!(CObject, Dart_CObject);
+    }
+
+    #[test]
+    fn test_cobject_array_grows_amortized() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut array = CObjectArray::new();
+        assert!(array.is_empty());
+
+        let mut reallocations = 0;
+        for value in 0..1000 {
+            let capacity = array.capacity();
+            array.push(CObject::int64(value));
+            if array.capacity() != capacity {
+                reallocations += 1;
+            }
+        }
+        assert_eq!(array.len(), 1000);
+        assert!(reallocations < 20);
+
+        let mut array = CObject::from(array);
+        let values = array
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|element| element.as_int64(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+
+        let mut empty = CObject::from(CObjectArray::new());
+        assert_eq!(empty.as_mut().as_array(rt).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_duration_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let duration = Duration::from_micros(1_234_567);
+        let mut obj = CObject::duration(duration);
+        assert_eq!(obj.as_mut().as_int64(rt), Some(1_234_567));
+        assert_eq!(obj.as_mut().as_duration(rt), Some(duration));
+        assert_eq!(CObject::int64(-1).as_mut().as_duration(rt), None);
+    }
+
+    #[test]
+    fn test_system_time_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for time in [
+            UNIX_EPOCH + Duration::from_millis(1_650_000_000_123),
+            UNIX_EPOCH - Duration::from_millis(86_400_123),
+        ] {
+            let mut obj = CObject::from_system_time(time);
+            assert_eq!(obj.as_mut().as_system_time(rt), Some(time));
+        }
+        let mut obj = CObject::from_system_time(UNIX_EPOCH - Duration::from_millis(5));
+        assert_eq!(obj.as_mut().as_int64(rt), Some(-5));
+    }
+
+    #[test]
+    fn test_packed_typed_data() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (mut data, mut offsets) = CObject::packed_typed_data(vec![
+            TypedData::Float32(vec![1.0, 2.0]),
+            TypedData::Float32(vec![3.0, 4.0, 5.0]),
+            TypedData::Float32(vec![6.0]),
+        ])
+        .unwrap();
+
+        let offsets = offsets
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|offset| offset.as_int64(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 2, 5, 6]);
+        match data.as_mut().as_typed_data(rt) {
+            Some((Ok(TypedDataRef::Float32(data)), _)) => {
+                assert_eq!(data, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+            }
+            _ => panic!("expected float32 typed data"),
+        }
+    }
+
+    #[test]
+    fn test_packed_typed_data_requires_same_type() {
+        let res = CObject::packed_typed_data(vec![
+            TypedData::Float32(vec![1.0]),
+            TypedData::Float64(vec![1.0]),
+        ]);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_float32_from_f64() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let values = [0.1, -2.5, 1e10, 123.456_789_012];
+        let mut obj = CObject::float32_from_f64(&values);
+        match obj.as_mut().as_typed_data(rt) {
+            Some((Ok(TypedDataRef::Float32(data)), true)) => {
+                assert_eq!(data.len(), values.len());
+                for (&narrowed, &value) in data.iter().zip(&values) {
+                    assert!((f64::from(narrowed) - value).abs() <= value.abs() * 1e-7);
+                }
+            }
+            _ => panic!("expected float32 external typed data"),
+        }
+    }
+
+    #[test]
+    fn test_matrix_f64_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let data = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mut obj = CObject::matrix_f64(2, 3, &data).unwrap();
+        assert_eq!(obj.as_mut().as_matrix_f64(rt), Some((2, 3, &data[..])));
+
+        let mut bad_shape = CObject::array(vec![
+            Box::new(CObject::int64(3)),
+            Box::new(CObject::int64(3)),
+            Box::new(CObject::typed_data(TypedData::Float64(data.to_vec()))),
+        ]);
+        assert_eq!(bad_shape.as_mut().as_matrix_f64(rt), None);
+    }
+
+    #[test]
+    fn test_matrix_f64_rejects_shape_mismatch() {
+        match CObject::matrix_f64(2, 2, &[1.0, 2.0, 3.0]) {
+            Err(ShapeError { rows, cols, len }) => assert_eq!((rows, cols, len), (2, 2, 3)),
+            Ok(_) => panic!("expected a shape error"),
+        }
+        assert!(CObject::matrix_f64(usize::MAX, 2, &[]).is_err());
+    }
+
+    #[test]
+    fn test_recyclable_buffer_is_returned_on_drop() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let buf = Vec::with_capacity(16);
+        let ptr = buf.as_ptr();
+        let mut obj = CObject::external_typed_data_recyclable(buf, sender.clone());
+        assert!(matches!(
+            obj.as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8([])), true))
+        ));
+        assert!(receiver.try_recv().is_err());
+        drop(obj);
+
+        let recycled = receiver.try_recv().unwrap();
+        assert_eq!(recycled.as_ptr(), ptr);
+        assert_eq!(recycled.capacity(), 16);
+
+        let obj = CObject::external_typed_data_recyclable(vec![1, 2, 3], sender);
+        drop(receiver);
+        drop(obj);
+    }
+
+    #[test]
+    fn test_string_bounded() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = CObject::string_bounded("grüße", 7).unwrap();
+        assert_eq!(obj.as_mut().as_string(rt), Some("grüße"));
+        assert!(CObject::string_bounded("", 0).is_ok());
+
+        match CObject::string_bounded("grüße", 6) {
+            Err(StringBoundedFailed::TooLong(TooLong { len, max_len })) => {
+                assert_eq!((len, max_len), (7, 6));
+            }
+            _ => panic!("expected the string to be too long"),
+        }
+        assert!(matches!(
+            CObject::string_bounded("a\0b", 3),
+            Err(StringBoundedFailed::InteriorNul(_))
+        ));
+        // the length is checked first
+        assert!(matches!(
+            CObject::string_bounded("a\0b", 2),
+            Err(StringBoundedFailed::TooLong(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_c_str() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let c_str = CStr::from_bytes_with_nul("grüße\0".as_bytes()).unwrap();
+        let mut obj = CObject::from_c_str(c_str).unwrap();
+        assert_eq!(obj.as_mut().as_string(rt), Some("grüße"));
+    }
+
+    #[test]
+    fn test_string_from_cstr() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let c_str = CStr::from_bytes_with_nul("grüße\0".as_bytes()).unwrap();
+        let mut obj = unsafe { CObject::string_from_cstr(c_str) };
+        assert_eq!(obj.as_mut().as_string(rt), Some("grüße"));
+    }
+
+    #[test]
+    fn test_from_c_string_rejects_invalid_utf8() {
+        let invalid = CString::new([0xff]).unwrap();
+        assert!(CObject::from_c_str(&invalid).is_err());
+        assert!(CObject::from_c_string(invalid).is_err());
+    }
+
+    #[test]
+    fn test_from_c_string_keeps_allocation() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let c_string = CString::new("grüße").unwrap();
+        let ptr = c_string.as_ptr();
+        let mut obj = CObject::from_c_string(c_string).unwrap();
+        assert_eq!(
+            unsafe { CStr::from_ptr(obj.0.value.as_string) }.as_ptr(),
+            ptr
+        );
+        assert_eq!(obj.as_mut().as_string(rt), Some("grüße"));
+    }
+
+    #[test]
+    fn test_array_from_iter() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut array = CObject::array_from_iter((0..5).map(CObject::int64));
+        let values = array
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|element| element.as_int64(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, [0, 1, 2, 3, 4]);
+
+        let mut empty = CObject::array_from_iter(None);
+        assert_eq!(empty.as_mut().as_array(rt).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_array_builder() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut builder = CObject::array_with_capacity(2);
+        assert!(builder.is_empty());
+        builder.push(CObject::bool(true));
+        builder.push(CObject::string("built").unwrap());
+        assert_eq!(builder.len(), 2);
+        let mut array = builder.finish();
+        let array = array.as_mut();
+        let array = array.as_array(rt).unwrap();
+        assert_eq!(array[0].as_bool(rt), Some(true));
+        assert_eq!(array[1].as_string(rt), Some("built"));
+    }
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct DropCounter;
+
+    unsafe impl CustomExternalTyped for DropCounter {
+        fn into_external_typed_data(self) -> ExternalTypedData {
+            unsafe extern "C" fn count_drop(_data: *mut c_void, _peer: *mut c_void) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+            ExternalTypedData {
+                type_: TypedDataType::Uint8.into(),
+                length: 0,
+                data: ptr::null_mut(),
+                peer: ptr::null_mut(),
+                callback: Some(count_drop),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dropping_grown_array_drops_elements() {
+        let mut array = CObjectArray::with_capacity(1);
+        for _ in 0..10 {
+            array.push(CObject::external_typed_data(DropCounter));
+        }
+        drop(CObject::from(array));
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_neutralized_external_data_is_not_finalized() {
+        static FINALIZED: AtomicUsize = AtomicUsize::new(0);
+
+        struct HandedOff;
+
+        unsafe impl CustomExternalTyped for HandedOff {
+            fn into_external_typed_data(self) -> ExternalTypedData {
+                unsafe extern "C" fn finalize(_data: *mut c_void, _peer: *mut c_void) {
+                    FINALIZED.fetch_add(1, Ordering::SeqCst);
+                }
+                ExternalTypedData {
+                    type_: TypedDataType::Uint8.into(),
+                    length: 0,
+                    data: ptr::null_mut(),
+                    peer: ptr::null_mut(),
+                    callback: Some(finalize),
+                }
+            }
+        }
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = CObject::array(vec![
+            Box::new(CObject::external_typed_data(HandedOff)),
+            Box::new(CObject::int64(1)),
+        ]);
+        obj.neutralize_external_data(rt);
+        let view = obj.as_mut();
+        let array = view.as_array(rt).unwrap();
+        assert_eq!(array[0].r#type(), Ok(CObjectType::Null));
+        assert_eq!(array[1].as_int64(rt), Some(1));
+        drop(obj);
+        assert_eq!(FINALIZED.load(Ordering::SeqCst), 0);
+
+        drop(CObject::external_typed_data(HandedOff));
+        assert_eq!(FINALIZED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_display() {
+        let (loopback, _inbox) = crate::test_utils::loopback();
+        let port = unsafe { DartRuntime::instance_unchecked() }
+            .send_port_from_raw(loopback.id())
+            .unwrap();
+        let cases = [
+            (CObject::null(), "null".to_owned()),
+            (CObject::bool(true), "true".to_owned()),
+            (CObject::int32(-7), "-7".to_owned()),
+            (CObject::int64(42), "42".to_owned()),
+            (CObject::double(2.0), "2.0".to_owned()),
+            (CObject::string("a\tb").unwrap(), "\"a\\tb\"".to_owned()),
+            (
+                CObject::array(vec![Box::new(CObject::null()), Box::new(CObject::null())]),
+                "[2 elements]".to_owned(),
+            ),
+            (
+                CObject::typed_data(TypedData::Float32(vec![1.0, 2.0])),
+                "Float32[8 bytes, external]".to_owned(),
+            ),
+            (
+                CObject::send_port(port),
+                format!("SendPort({})", port.id_string()),
+            ),
+            (
+                CObject::raw_send_port(0, 0),
+                "SendPort(ILLEGAL_PORT)".to_owned(),
+            ),
+            (CObject::capability(5), "Capability(5)".to_owned()),
+        ];
+        for (obj, expected) in &cases {
+            assert_eq!(obj.display().to_string(), *expected);
+        }
+
+        let mut bytes = [1_u8, 2, 3];
+        let obj = ManuallyDrop::new(CObject::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kTypedData,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_typed_data: dart_api_dl_sys::_Dart_CObject__bindgen_ty_1__bindgen_ty_4 {
+                    type_: TypedDataType::Uint8.into(),
+                    length: 3,
+                    values: bytes.as_mut_ptr(),
+                },
+            },
+        }));
+        assert_eq!(obj.display().to_string(), "Uint8[3 bytes]");
+
+        let unknown = ManuallyDrop::new(CObject::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kUnsupported,
+            value: _Dart_CObject__bindgen_ty_1 { as_int64: 0 },
+        }));
+        assert_eq!(unknown.display().to_string(), "<unknown>");
+    }
+
+    #[test]
+    fn test_from_raw_copy() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let raw = Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kInt64,
+            value: _Dart_CObject__bindgen_ty_1 { as_int64: -12 },
+        };
+        let mut copy = unsafe { CObject::from_raw_copy(&raw) }.unwrap();
+        assert_eq!(copy.as_mut().as_int64(rt), Some(-12));
+
+        let array = CObject::array(vec![Box::new(CObject::string("copied").unwrap())]);
+        let mut copy = unsafe { CObject::from_raw_copy(&array.0) }.unwrap();
+        let copy = copy.as_mut();
+        assert_eq!(copy.as_array(rt).unwrap()[0].as_string(rt), Some("copied"));
+    }
+
+    #[test]
+    fn test_deep_clone_is_independent() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let template = || {
+            CObject::array(vec![
+                Box::new(CObject::string("template").unwrap()),
+                Box::new(CObject::external_typed_data(TypedData::Uint8Clamped(vec![
+                    1, 2,
+                ]))),
+                Box::new(CObject::array(vec![Box::new(CObject::typed_data(
+                    TypedData::Float64(vec![0.5]),
+                ))])),
+            ])
+        };
+        let check = |obj: &mut CObject| {
+            let obj = obj.as_mut();
+            let array = obj.as_array(rt).unwrap();
+            assert_eq!(array[0].as_string(rt), Some("template"));
+            assert!(matches!(
+                array[1].as_typed_data(rt),
+                Some((Ok(TypedDataRef::Uint8Clamped(&[1, 2])), true))
+            ));
+            let nested = array[2].as_array(rt).unwrap();
+            assert!(matches!(
+                nested[0].as_typed_data(rt),
+                Some((Ok(TypedDataRef::Float64(data)), true)) if data == [0.5]
+            ));
+        };
+
+        let original = template();
+        let mut clone = original.deep_clone().unwrap();
+        drop(original);
+        check(&mut clone);
+
+        let mut original = template();
+        let mut clone = original.deep_clone().unwrap();
+        let data = |obj: &mut CObject| {
+            obj.as_mut().as_array(rt).unwrap()[1]
+                .raw_typed_data_bytes(rt)
+                .unwrap()
+                .as_ptr()
+        };
+        assert_ne!(data(&mut original), data(&mut clone));
+        drop(clone);
+        check(&mut original);
+
+        let unknown = ManuallyDrop::new(CObject::array(vec![Box::new(CObject::from_raw(
+            Dart_CObject {
+                type_: Dart_CObject_Type::Dart_CObject_kUnsupported,
+                value: _Dart_CObject__bindgen_ty_1 { as_int64: 0 },
+            },
+        ))]));
+        assert!(unknown.deep_clone().is_err());
+    }
+
+    #[test]
+    fn test_from_raw_copy_rejects_external_typed_data() {
+        let array = CObject::array(vec![Box::new(CObject::external_typed_data(
+            TypedData::Uint8(vec![1, 2]),
+        ))]);
+        assert!(matches!(
+            unsafe { CObject::from_raw_copy(&array.0) },
+            Err(UnsupportedForCopy::ExternalTypedData)
+        ));
+    }
+}
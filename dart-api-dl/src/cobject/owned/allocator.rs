@@ -0,0 +1,347 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for allocating the buffers of [`CObject`]s with a custom allocator.
+
+use std::{
+    alloc::{handle_alloc_error, GlobalAlloc, Layout},
+    collections::HashMap,
+    convert::TryInto,
+    ffi::{c_void, CStr, CString, NulError},
+    ptr,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use dart_api_dl_sys::{
+    Dart_CObject,
+    Dart_CObject_Type,
+    _Dart_CObject__bindgen_ty_1,
+    _Dart_CObject__bindgen_ty_1__bindgen_ty_3,
+};
+use once_cell::sync::Lazy;
+
+use crate::{
+    cobject::{ExternalTypedData, TypedData},
+    utils::prepare_dart_array_parts_mut,
+};
+
+use super::CObject;
+
+/// Allocator used by the `_in` constructors of [`CObject`].
+///
+/// Any [`GlobalAlloc`] can be used, e.g. a bump allocator which is reset once
+/// all messages got dropped. As dart might drop external typed data on any
+/// thread it must be [`Sync`].
+///
+/// The buffers of strings and arrays are registered in a global table, so that
+/// dropping them frees them with the right allocator. With the `allocator-api`
+/// feature enabled dropping any string or array object looks up that table.
+pub type CObjectAllocator = dyn GlobalAlloc + Sync;
+
+/// The allocators of the string and array buffers allocated by the `_in` constructors.
+///
+/// A [`Dart_CObject`] can't store its allocator, so it's looked up by the address
+/// of the buffer when dropping a string or array object.
+static ALLOCATIONS: Lazy<Mutex<HashMap<usize, &'static CObjectAllocator>>> =
+    Lazy::new(Mutex::default);
+
+fn allocations() -> MutexGuard<'static, HashMap<usize, &'static CObjectAllocator>> {
+    ALLOCATIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Header of the allocations backing external typed data.
+struct TypedDataHeader {
+    allocator: &'static CObjectAllocator,
+    layout: Layout,
+}
+
+fn allocate(allocator: &CObjectAllocator, layout: Layout) -> *mut u8 {
+    // Safe: all layouts used in this module have a non-zero size
+    let ptr = unsafe { allocator.alloc(layout) };
+    if ptr.is_null() {
+        handle_alloc_error(layout);
+    }
+    ptr
+}
+
+fn array_buffer_layout(len: usize) -> Layout {
+    Layout::array::<*mut Dart_CObject>(len).expect("array length overflow")
+}
+
+impl CObject {
+    /// Like [`CObject::string()`], but the string buffer is allocated by `allocator`.
+    ///
+    /// # Errors
+    ///
+    /// If the string contains `0` bytes an error is returned.
+    ///
+    /// # Panics
+    ///
+    /// If the string is longer than `isize::MAX` bytes.
+    pub fn string_in(
+        val: impl AsRef<str>,
+        allocator: &'static CObjectAllocator,
+    ) -> Result<Self, NulError> {
+        let bytes = val.as_ref().as_bytes();
+        if bytes.contains(&0) {
+            return Err(CString::new(bytes).unwrap_err(/* we checked for 0 bytes */));
+        }
+        let layout = Layout::array::<u8>(bytes.len() + 1).expect("string length overflow");
+        let ptr = allocate(allocator, layout);
+        // Safe: the allocation has space for the bytes and the terminating `'\0'`
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+            ptr.add(bytes.len()).write(0);
+        }
+        allocations().insert(ptr as usize, allocator);
+        Ok(Self::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kString,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_string: ptr.cast(),
+            },
+        }))
+    }
+
+    /// Like [`CObject::array()`], but the array is allocated by `allocator`.
+    ///
+    /// This includes the elements, which are moved into the allocator.
+    ///
+    /// # Panics
+    ///
+    /// If there are more than `isize::MAX` elements.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn array_in(
+        elements: impl IntoIterator<Item = CObject>,
+        allocator: &'static CObjectAllocator,
+    ) -> Self {
+        let elements = elements.into_iter().collect::<Vec<_>>();
+        if elements.is_empty() {
+            return Self::array(Vec::new());
+        }
+        let len = elements.len();
+        let values = allocate(allocator, array_buffer_layout(len)).cast::<*mut Dart_CObject>();
+        for (idx, value) in elements.into_iter().enumerate() {
+            let element = allocate(allocator, Layout::new::<CObject>()).cast::<CObject>();
+            // Safe:
+            // - the element allocation fits and is aligned for a `CObject`
+            // - the buffer has space for `len` elements
+            unsafe {
+                element.write(value);
+                values.add(idx).write(element.cast());
+            }
+        }
+        allocations().insert(values as usize, allocator);
+        Self::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kArray,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_array: _Dart_CObject__bindgen_ty_1__bindgen_ty_3 {
+                    length: len.try_into().unwrap(),
+                    values,
+                },
+            },
+        })
+    }
+
+    /// Like [`CObject::typed_data()`], but the data is copied into a buffer
+    /// allocated by `allocator`.
+    ///
+    /// # Panics
+    ///
+    /// If the data is longer than `isize::MAX` bytes.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn typed_data_in(data: &TypedData, allocator: &'static CObjectAllocator) -> Self {
+        let (bytes, length, align) = data.as_raw_parts();
+        let data_layout = Layout::from_size_align(bytes.len(), align).expect("invalid data layout");
+        let (layout, offset) = Layout::new::<TypedDataHeader>()
+            .extend(data_layout)
+            .expect("typed data length overflow");
+        let block = allocate(allocator, layout);
+        // Safe:
+        // - the block is aligned for the header, which is at its start
+        // - the data starts at the offset and has space for the bytes
+        let values = unsafe {
+            block
+                .cast::<TypedDataHeader>()
+                .write(TypedDataHeader { allocator, layout });
+            // empty data must use a null pointer, see `prepare_dart_array_parts()`
+            if bytes.is_empty() {
+                ptr::null_mut()
+            } else {
+                let values = block.add(offset);
+                ptr::copy_nonoverlapping(bytes.as_ptr(), values, bytes.len());
+                values
+            }
+        };
+        let etd = ExternalTypedData {
+            type_: data.data_type().into(),
+            length: length.try_into().unwrap(),
+            data: values,
+            peer: block.cast(),
+            callback: Some(free_typed_data),
+        };
+        let etd = crate::diagnostics::track_external_typed_data(etd);
+        Self::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kExternalTypedData,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_external_typed_data: etd,
+            },
+        })
+    }
+
+    /// Returns the allocator of the buffers of a string or array object and forgets about them.
+    ///
+    /// Returns `None` if the buffers weren't allocated by an allocator.
+    pub(super) fn take_allocator(&self) -> Option<&'static CObjectAllocator> {
+        // Safe: we checked the type
+        let buffer = match self.0.type_ {
+            Dart_CObject_Type::Dart_CObject_kString => unsafe { self.0.value.as_string as usize },
+            Dart_CObject_Type::Dart_CObject_kArray => unsafe {
+                self.0.value.as_array.values as usize
+            },
+            _ => return None,
+        };
+        if buffer == 0 {
+            return None;
+        }
+        allocations().remove(&buffer)
+    }
+
+    /// Frees the buffers of a string or array object.
+    ///
+    /// # Safety
+    ///
+    /// The object must be a string or array created by `allocator`.
+    #[allow(clippy::cast_ptr_alignment)]
+    pub(super) unsafe fn drop_in(&mut self, allocator: &'static CObjectAllocator) {
+        match self.0.type_ {
+            Dart_CObject_Type::Dart_CObject_kString => unsafe {
+                let ptr = self.0.value.as_string;
+                let len = CStr::from_ptr(ptr).to_bytes_with_nul().len();
+                allocator.dealloc(ptr.cast(), Layout::array::<u8>(len).unwrap());
+            },
+            Dart_CObject_Type::Dart_CObject_kArray => unsafe {
+                let as_array = &self.0.value.as_array;
+                let (values, len) = prepare_dart_array_parts_mut(as_array.values, as_array.length);
+                for idx in 0..len {
+                    let element = values.add(idx).read().cast::<CObject>();
+                    ptr::drop_in_place(element);
+                    allocator.dealloc(element.cast(), Layout::new::<CObject>());
+                }
+                allocator.dealloc(values.cast(), array_buffer_layout(len));
+            },
+            _ => unreachable!("only strings and arrays have an allocator"),
+        }
+    }
+}
+
+unsafe extern "C" fn free_typed_data(_data: *mut c_void, peer: *mut c_void) {
+    // Safe: the peer is the block allocated in `CObject::typed_data_in()`
+    unsafe {
+        let TypedDataHeader { allocator, layout } = peer.cast::<TypedDataHeader>().read();
+        allocator.dealloc(peer.cast(), layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::System,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{cobject::TypedDataRef, DartRuntime};
+
+    use super::*;
+
+    struct CountingAllocator {
+        allocations: AtomicUsize,
+        frees: AtomicUsize,
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.allocations.fetch_add(1, Ordering::SeqCst);
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.frees.fetch_add(1, Ordering::SeqCst);
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    static ALLOCATOR: CountingAllocator = CountingAllocator {
+        allocations: AtomicUsize::new(0),
+        frees: AtomicUsize::new(0),
+    };
+
+    #[test]
+    fn test_allocations_and_frees_balance() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut message = CObject::array_in(
+            [
+                CObject::string_in("hy", &ALLOCATOR).unwrap(),
+                CObject::typed_data_in(&TypedData::Float64(vec![0.5, 2.]), &ALLOCATOR),
+            ]
+            .into_iter()
+            .chain((0..10).map(CObject::int64)),
+            &ALLOCATOR,
+        );
+
+        {
+            let message = message.as_mut();
+            let elements = message.as_array(rt).unwrap();
+            assert_eq!(elements.len(), 12);
+            assert_eq!(elements[0].as_string(rt), Some("hy"));
+            assert!(matches!(
+                elements[1].as_typed_data(rt),
+                Some((Ok(TypedDataRef::Float64(&[0.5, 2.])), true))
+            ));
+            assert_eq!(elements[11].as_int(rt), Some(9));
+        }
+        drop(message);
+
+        let allocations = ALLOCATOR.allocations.load(Ordering::SeqCst);
+        assert!(allocations > 0);
+        assert_eq!(allocations, ALLOCATOR.frees.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_empty_typed_data_in_uses_null_pointer() {
+        static EMPTY: CountingAllocator = CountingAllocator {
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+        };
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut empty = CObject::typed_data_in(&TypedData::Int32(Vec::new()), &EMPTY);
+        // Safe: the object is external typed data
+        assert!(unsafe { empty.0.value.as_external_typed_data.data }.is_null());
+        assert!(matches!(
+            empty.as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Int32(&[])), true))
+        ));
+        drop(empty);
+        assert_eq!(EMPTY.frees.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_string_in_rejects_nul_bytes() {
+        static UNUSED: CountingAllocator = CountingAllocator {
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+        };
+        assert!(CObject::string_in("a\0b", &UNUSED).is_err());
+        assert_eq!(UNUSED.allocations.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Value based comparison and hashing of [`CObject`]s.
+
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    cobject::{CObjectMut, CObjectValuesRef},
+    ReadOnlyRuntime,
+};
+
+use super::CObject;
+
+/// Compares objects by value.
+///
+/// - `Int32` and `Int64` objects are equal if they have the same value, as dart
+///   receives both as `int`.
+/// - Doubles are compared like `f64`, i.e. `NaN` is not equal to any double,
+///   not even to itself. Hence [`Eq`] is not implemented.
+/// - Strings are compared by their content.
+/// - Arrays are compared element wise.
+/// - Typed data is equal if it has the same data type and the same bytes, no
+///   matter if it's external typed data or not.
+/// - Send ports are equal if they have the same id and origin id.
+/// - Objects of an unknown type, including typed data of an unknown type, are
+///   not equal to any object.
+impl PartialEq for CObject {
+    fn eq(&self, other: &Self) -> bool {
+        let rt = ReadOnlyRuntime::new();
+        // the shallow copies are not owned, so they must not be dropped as `CObject`
+        let (mut this, mut other) = (self.0, other.0);
+        value_eq(
+            rt,
+            &CObjectMut {
+                partial_mut: &mut this,
+            },
+            &CObjectMut {
+                partial_mut: &mut other,
+            },
+        )
+    }
+}
+
+/// Hashes objects consistently with their [`PartialEq`] implementation.
+impl Hash for CObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let rt = ReadOnlyRuntime::new();
+        // the shallow copy is not owned, so it must not be dropped as `CObject`
+        let mut shallow = self.0;
+        hash_value(
+            rt,
+            &CObjectMut {
+                partial_mut: &mut shallow,
+            },
+            state,
+        );
+    }
+}
+
+fn value_eq(rt: ReadOnlyRuntime, a: &CObjectMut<'_>, b: &CObjectMut<'_>) -> bool {
+    #![allow(clippy::enum_glob_use)]
+    use CObjectValuesRef::*;
+    match (a.read_value_ref(rt), b.read_value_ref(rt)) {
+        (Ok(a), Ok(b)) => match (a, b) {
+            (Null, Null) => true,
+            (Bool(a), Bool(b)) => a == b,
+            (Int32(a), Int32(b)) => a == b,
+            (Int32(a), Int64(b)) | (Int64(b), Int32(a)) => i64::from(a) == b,
+            (Int64(a), Int64(b)) | (Capability(a), Capability(b)) => a == b,
+            #[allow(clippy::float_cmp)]
+            (Double(a), Double(b)) => a == b,
+            (String(a), String(b)) => a == b,
+            (Array(a), Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| value_eq(rt, a, b))
+            }
+            (TypedData { data: Ok(a), .. }, TypedData { data: Ok(b), .. }) => {
+                a.data_type() == b.data_type() && a.as_bytes() == b.as_bytes()
+            }
+            (SendPort(a), SendPort(b)) => {
+                a.map(|port| port.as_raw()) == b.map(|port| port.as_raw())
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn hash_value(rt: ReadOnlyRuntime, obj: &CObjectMut<'_>, state: &mut impl Hasher) {
+    match obj.read_value_ref(rt) {
+        Ok(CObjectValuesRef::Null) => 0_u8.hash(state),
+        Ok(CObjectValuesRef::Bool(value)) => (1_u8, value).hash(state),
+        Ok(CObjectValuesRef::Int32(value)) => (2_u8, i64::from(value)).hash(state),
+        Ok(CObjectValuesRef::Int64(value)) => (2_u8, value).hash(state),
+        Ok(CObjectValuesRef::Double(value)) => {
+            // `0.0 == -0.0`, so both must have the same hash
+            let value = if value == 0.0 { 0.0 } else { value };
+            (3_u8, value.to_bits()).hash(state);
+        }
+        Ok(CObjectValuesRef::String(value)) => (4_u8, value).hash(state),
+        Ok(CObjectValuesRef::Array(array)) => {
+            (5_u8, array.len()).hash(state);
+            for element in array {
+                hash_value(rt, element, state);
+            }
+        }
+        Ok(CObjectValuesRef::TypedData { data: Ok(data), .. }) => {
+            (6_u8, data.data_type(), data.as_bytes()).hash(state);
+        }
+        Ok(CObjectValuesRef::SendPort(port)) => {
+            (7_u8, port.map(|port| port.as_raw())).hash(state);
+        }
+        Ok(CObjectValuesRef::Capability(id)) => (8_u8, id).hash(state),
+        Ok(CObjectValuesRef::TypedData { data: Err(_), .. }) | Err(_) => 9_u8.hash(state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use dart_api_dl_sys::{
+        _Dart_CObject__bindgen_ty_1,
+        _Dart_CObject__bindgen_ty_1__bindgen_ty_4,
+        Dart_CObject,
+        Dart_CObject_Type,
+    };
+
+    use crate::cobject::{TypedData, TypedDataType};
+
+    use super::*;
+
+    fn hash_of(obj: &CObject) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        obj.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn assert_equal(a: &CObject, b: &CObject) {
+        assert!(a == b);
+        assert_eq!(hash_of(a), hash_of(b));
+    }
+
+    #[test]
+    fn test_equal_values() {
+        assert_equal(&CObject::null(), &CObject::null());
+        assert_equal(&CObject::bool(true), &CObject::bool(true));
+        assert_equal(&CObject::int32(7), &CObject::int64(7));
+        assert_equal(&CObject::double(0.0), &CObject::double(-0.0));
+        assert_equal(
+            &CObject::string("grüße").unwrap(),
+            &CObject::string("grüße").unwrap(),
+        );
+        assert_equal(
+            &CObject::array(vec![
+                Box::new(CObject::int64(1)),
+                Box::new(CObject::array(vec![Box::new(CObject::null())])),
+            ]),
+            &CObject::array(vec![
+                Box::new(CObject::int32(1)),
+                Box::new(CObject::array(vec![Box::new(CObject::null())])),
+            ]),
+        );
+        assert_equal(&CObject::capability(3), &CObject::capability(3));
+        assert_equal(
+            &CObject::raw_send_port(12, 13),
+            &CObject::raw_send_port(12, 13),
+        );
+    }
+
+    #[test]
+    fn test_unequal_values() {
+        let double_nan = CObject::double(f64::NAN);
+        assert!(double_nan != double_nan);
+        assert!(CObject::int64(1) != CObject::double(1.0));
+        assert!(CObject::bool(false) != CObject::null());
+        assert!(CObject::string("a").unwrap() != CObject::string("b").unwrap());
+        assert!(
+            CObject::array(vec![Box::new(CObject::null())])
+                != CObject::array(vec![Box::new(CObject::null()), Box::new(CObject::null())])
+        );
+        assert!(
+            CObject::typed_data(TypedData::Uint8(vec![0; 4]))
+                != CObject::typed_data(TypedData::Int32(vec![0]))
+        );
+        assert!(CObject::raw_send_port(12, 13) != CObject::raw_send_port(12, 14));
+    }
+
+    #[test]
+    fn test_external_typed_data_equals_typed_data() {
+        let external = CObject::typed_data(TypedData::Uint16(vec![1, 2]));
+        let mut values = [1_u16, 2];
+        let typed = std::mem::ManuallyDrop::new(CObject::from_raw(Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kTypedData,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_typed_data: _Dart_CObject__bindgen_ty_1__bindgen_ty_4 {
+                    type_: TypedDataType::Uint16.into(),
+                    length: 2,
+                    values: values.as_mut_ptr().cast(),
+                },
+            },
+        }));
+        assert_equal(&external, &typed);
+    }
+}
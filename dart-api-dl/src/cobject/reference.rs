@@ -17,20 +17,27 @@ use std::{
     ffi::CStr,
     fmt::{self, Debug},
     slice,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use dart_api_dl_sys::{Dart_CObject, Dart_CObject_Type};
 
+use thiserror::Error;
+
 use crate::{
-    ports::SendPort,
-    utils::{prepare_dart_array_parts, prepare_dart_array_parts_mut},
+    ports::{PostingMessageFailed, SendPort},
+    utils::{prepare_dart_array_parts, prepare_dart_array_parts_mut, quietly},
     DartRuntime,
+    ReadOnlyRuntime,
 };
 
 use super::{
+    CObject,
     CObjectType,
     CObjectValuesRef,
     Capability,
+    ElementError,
+    TypedData,
     TypedDataRef,
     TypedDataType,
     UnknownCObjectType,
@@ -135,74 +142,219 @@ impl<'a> CObjectMut<'a> {
     }
 
     /// Returns `Some` if the object is null.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_null(&self, rt: DartRuntime) -> Option<()> {
         if let Ok(CObjectValuesRef::Null) = self.value_ref(rt) {
             Some(())
         } else {
-            None
+            self.coercion_failed("Null")
         }
     }
 
     /// Returns `Some` if the object is a bool.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_bool(&self, rt: DartRuntime) -> Option<bool> {
         if let Ok(CObjectValuesRef::Bool(b)) = self.value_ref(rt) {
             Some(b)
         } else {
-            None
+            self.coercion_failed("Bool")
         }
     }
 
     /// Returns `Some` if the object is a 32bit int.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_int32(&self, rt: DartRuntime) -> Option<i32> {
         if let Ok(CObjectValuesRef::Int32(v)) = self.value_ref(rt) {
             Some(v)
         } else {
-            None
+            self.coercion_failed("Int32")
         }
     }
 
     /// Returns `Some` if the object is a 64bit int.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_int64(&self, rt: DartRuntime) -> Option<i64> {
         if let Ok(CObjectValuesRef::Int64(v)) = self.value_ref(rt) {
             Some(v)
         } else {
-            None
+            self.coercion_failed("Int64")
         }
     }
 
     /// Returns `Some` if the object is a 32bit or 64bit int.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_int(&self, rt: DartRuntime) -> Option<i64> {
-        self.as_int32(rt)
-            .map_or_else(|| self.as_int64(rt), |v| Some(v.into()))
+        match self.value_ref(rt) {
+            Ok(CObjectValuesRef::Int32(v)) => Some(v.into()),
+            Ok(CObjectValuesRef::Int64(v)) => Some(v),
+            _ => self.coercion_failed("Int32 or Int64"),
+        }
+    }
+
+    /// Returns `None`, with the `trace-coercions` feature the failed coercion is traced.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    #[cfg_attr(not(feature = "trace-coercions"), allow(unused_variables))]
+    fn coercion_failed<T>(&self, expected: &'static str) -> Option<T> {
+        #[cfg(feature = "trace-coercions")]
+        crate::diagnostics::coercion::trace_coercion(
+            expected,
+            self.r#type().ok(),
+            std::panic::Location::caller(),
+        );
+        None
     }
 
     /// Returns `Some` if the object is a 64bit float.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_double(&self, rt: DartRuntime) -> Option<f64> {
         if let Ok(CObjectValuesRef::Double(d)) = self.value_ref(rt) {
             Some(d)
         } else {
-            None
+            self.coercion_failed("Double")
+        }
+    }
+
+    /// Returns `Some` if the object is a 64bit float, narrowed to 32bit.
+    ///
+    /// There are no 32bit float objects, see [`CObject::float32()`]. Doubles which
+    /// aren't representable as 32bit float are rounded to the nearest one, doubles
+    /// out of its range become infinite.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn as_float32(&self, rt: DartRuntime) -> Option<f32> {
+        self.as_double(rt).map(|double| double as f32)
+    }
+
+    /// Returns `Some` if the object is an int encoding flags.
+    ///
+    /// See [`CObject::flags()`].
+    #[allow(clippy::cast_sign_loss)]
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn as_flags(&self, rt: DartRuntime) -> Option<u64> {
+        self.as_int(rt).map(|bits| bits as u64)
+    }
+
+    /// Returns `Some` if the object is an int encoding flags of type `F`.
+    ///
+    /// Returns `None` if it's not an int or any bit is set which doesn't
+    /// correspond to a flag of `F`.
+    ///
+    /// See [`CObject::flags()`].
+    #[cfg(feature = "bitflags")]
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn as_bitflags<F>(&self, rt: DartRuntime) -> Option<F>
+    where
+        F: bitflags::Flags<Bits = u64>,
+    {
+        F::from_bits(self.as_flags(rt)?)
+    }
+
+    /// Returns `Some` if the object is an int encoding a duration.
+    ///
+    /// See [`CObject::duration()`], negative durations are not supported.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn as_duration(&self, rt: DartRuntime) -> Option<Duration> {
+        let micros = self.as_int(rt)?.try_into().ok()?;
+        Some(Duration::from_micros(micros))
+    }
+
+    /// Returns `Some` if the object is an int encoding a point in time.
+    ///
+    /// See [`CObject::from_system_time()`].
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn as_system_time(&self, rt: DartRuntime) -> Option<SystemTime> {
+        let millis = self.as_int(rt)?;
+        let offset = Duration::from_millis(millis.unsigned_abs());
+        if millis < 0 {
+            UNIX_EPOCH.checked_sub(offset)
+        } else {
+            UNIX_EPOCH.checked_add(offset)
+        }
+    }
+
+    /// Returns `Some` if the object is byte typed data encoding a UUID.
+    ///
+    /// Returns `None` if the data doesn't have exactly 16 bytes.
+    ///
+    /// See [`CObject::uuid()`].
+    #[cfg(feature = "uuid")]
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn as_uuid(&self, rt: DartRuntime) -> Option<uuid::Uuid> {
+        match self.as_typed_data(rt)?.0 {
+            Ok(
+                TypedDataRef::ByteData(bytes)
+                | TypedDataRef::Uint8(bytes)
+                | TypedDataRef::Uint8Clamped(bytes),
+            ) => uuid::Uuid::from_slice(bytes).ok(),
+            _ => None,
         }
     }
 
     /// Returns `Some` if the object is a string.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_string(&self, rt: DartRuntime) -> Option<&str> {
         if let Ok(CObjectValuesRef::String(s)) = self.value_ref(rt) {
             Some(s)
         } else {
-            None
+            self.coercion_failed("String")
         }
     }
 
     /// Returns `Some` if the object is an array of references to [`CObjectMut`]s.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_array(&self, rt: DartRuntime) -> Option<&[CObjectMut<'_>]> {
         if let Ok(CObjectValuesRef::Array(array)) = self.value_ref(rt) {
             Some(array)
         } else {
-            None
+            self.coercion_failed("Array")
         }
     }
 
+    /// Returns `Some` with an iterator over the elements if the object is an array.
+    ///
+    /// Unlike [`CObjectMut::as_array()`] the elements are yielded by value, use
+    /// [`CObjectMut::reborrow()`] to iterate without consuming the object.
+    #[allow(clippy::iter_not_returning_iterator)]
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn iter(
+        self,
+        rt: DartRuntime,
+    ) -> Option<impl DoubleEndedIterator<Item = CObjectMut<'a>> + ExactSizeIterator> {
+        self.as_array(rt)?;
+        // Safe:
+        // - the CObject behind the reference is sound
+        // - we checked the type
+        let elements: &'a [*mut Dart_CObject] = unsafe {
+            let as_array = &self.partial_mut.value.as_array;
+            let (ptr, len) = prepare_dart_array_parts(as_array.values, as_array.length);
+            slice::from_raw_parts(ptr, len)
+        };
+        Some(elements.iter().map(|&element| CObjectMut {
+            // Safe: Like for `as_array()` the elements are sound and distinct objects
+            //       and we gave up our reference to the array.
+            partial_mut: unsafe { &mut *element },
+        }))
+    }
+
+    /// Returns the nested element reached by following the array indices of `path`.
+    ///
+    /// E.g. `[1, 0]` is the first element of the second element of this array.
+    /// An empty path returns this object.
+    ///
+    /// Returns `None` if any step isn't an array or its index is out of range.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn descend(&self, rt: DartRuntime, path: &[usize]) -> Option<&CObjectMut<'_>> {
+        let root: &CObjectMut<'_> = self;
+        path.iter()
+            .try_fold(root, |obj, &idx| obj.as_array(rt)?.get(idx))
+    }
+
+    /// Returns `Some` with the number of elements if the object is an array.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn array_len(&self, rt: DartRuntime) -> Option<usize> {
+        self.as_array(rt).map(<[_]>::len)
+    }
+
     /// Returns `Some` if the object is typed data.
     ///
     /// It's `Some((Ok(), _))` if it's typed data of a typed data
@@ -211,6 +363,7 @@ impl<'a> CObjectMut<'a> {
     /// It's `Some(_, true)` if it's externally typed data, normally
     /// if it's externally or not-externally typed data doesn't make
     /// a difference for the consumer.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_typed_data(
         &self,
         rt: DartRuntime,
@@ -222,7 +375,7 @@ impl<'a> CObjectMut<'a> {
         {
             Some((data, external_typed))
         } else {
-            None
+            self.coercion_failed("TypedData or ExternalTypedData")
         }
     }
 
@@ -232,21 +385,333 @@ impl<'a> CObjectMut<'a> {
     /// is a send port variant but doesn't contain a `SendPort` as
     /// such it's an `Option<Option<>>`.
     #[allow(clippy::option_option)]
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_send_port(&self, rt: DartRuntime) -> Option<Option<SendPort>> {
         if let Ok(CObjectValuesRef::SendPort(port)) = self.value_ref(rt) {
             Some(port)
         } else {
-            None
+            self.coercion_failed("SendPort")
         }
     }
 
     /// Returns `Some` if the object is a capability.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
     pub fn as_capability(&self, rt: DartRuntime) -> Option<Capability> {
         if let Ok(CObjectValuesRef::Capability(cap)) = self.value_ref(rt) {
             Some(cap)
         } else {
-            None
+            self.coercion_failed("Capability")
+        }
+    }
+
+    /// Reads a nullable value.
+    ///
+    /// Returns `Some(None)` if the object is null, `Some(Some(_))` if `f` extracts
+    /// a value and `None` otherwise.
+    ///
+    /// See [`CObject::optional()`].
+    #[allow(clippy::option_option)]
+    pub fn as_optional<T>(
+        &self,
+        rt: DartRuntime,
+        f: impl Fn(&CObjectMut<'_>) -> Option<T>,
+    ) -> Option<Option<T>> {
+        if quietly(|| self.as_null(rt)).is_some() {
+            Some(None)
+        } else {
+            f(self).map(Some)
+        }
+    }
+
+    /// Returns `Some((rows, cols, data))` if the object is a matrix of doubles.
+    ///
+    /// The matrix must be of the form `[rows, cols, Float64List]` with the data in
+    /// row-major order, like sent by [`CObject::matrix_f64()`].
+    pub fn as_matrix_f64(&self, rt: DartRuntime) -> Option<(usize, usize, &[f64])> {
+        match self.as_array(rt)? {
+            [rows, cols, data] => {
+                let rows = rows.as_int(rt)?.try_into().ok()?;
+                let cols = cols.as_int(rt)?.try_into().ok()?;
+                match data.as_typed_data(rt)?.0 {
+                    Ok(TypedDataRef::Float64(data))
+                        if usize::checked_mul(rows, cols) == Some(data.len()) =>
+                    {
+                        Some((rows, cols, data))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Like [`CObjectMut::as_int()`] but returns a descriptive error.
+    ///
+    /// # Errors
+    ///
+    /// If the object is not a 32bit or 64bit int.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn require_int(&self, rt: DartRuntime) -> Result<i64, TypeError> {
+        self.as_int(rt).ok_or_else(|| self.type_error("an int"))
+    }
+
+    /// Like [`CObjectMut::as_string()`] but returns a descriptive error.
+    ///
+    /// # Errors
+    ///
+    /// If the object is not a string.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn require_str(&self, rt: DartRuntime) -> Result<&str, TypeError> {
+        self.as_string(rt)
+            .ok_or_else(|| self.type_error("a string"))
+    }
+
+    /// Like [`CObjectMut::as_array()`] but returns a descriptive error.
+    ///
+    /// # Errors
+    ///
+    /// If the object is not an array.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn require_array(&self, rt: DartRuntime) -> Result<&[CObjectMut<'_>], TypeError> {
+        self.as_array(rt).ok_or_else(|| self.type_error("an array"))
+    }
+
+    /// Like [`CObjectMut::as_send_port()`] but returns a descriptive error.
+    ///
+    /// # Errors
+    ///
+    /// If the object is not a send port or is a send port to the `ILLEGAL_PORT`.
+    #[cfg_attr(feature = "trace-coercions", track_caller)]
+    pub fn require_send_port(&self, rt: DartRuntime) -> Result<SendPort, TypeError> {
+        self.as_send_port(rt)
+            .flatten()
+            .ok_or_else(|| self.type_error("a send port other than ILLEGAL_PORT"))
+    }
+
+    pub(super) fn type_error(&self, expected: &'static str) -> TypeError {
+        TypeError {
+            expected,
+            found: self.r#type().ok(),
+        }
+    }
+
+    /// Checks that the object is the `expected` capability.
+    ///
+    /// This can be used to reject unauthorized requests.
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not a capability.
+    /// - If the capability doesn't match.
+    pub fn require_capability(
+        &self,
+        rt: DartRuntime,
+        expected: Capability,
+    ) -> Result<(), AuthError> {
+        match self.as_capability(rt) {
+            Some(capability) if capability == expected => Ok(()),
+            Some(_) => Err(AuthError::CapabilityMismatch),
+            None => Err(AuthError::MissingCapability),
+        }
+    }
+
+    /// Checks that the element at `index` of an array object is the `expected` capability.
+    ///
+    /// See [`CObjectMut::require_capability()`].
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array or has no element at `index`.
+    /// - If the element is not a capability.
+    /// - If the capability doesn't match.
+    pub fn require_capability_at(
+        &self,
+        rt: DartRuntime,
+        index: usize,
+        expected: Capability,
+    ) -> Result<(), AuthError> {
+        self.as_array(rt)
+            .and_then(|array| array.get(index))
+            .ok_or(AuthError::MissingCapability)?
+            .require_capability(rt, expected)
+    }
+
+    /// Maps each element of an array object to a new owned [`CObject`].
+    ///
+    /// The results are collected into a new array [`CObject`] in the same order.
+    ///
+    /// Returns `None` if the object is not an array.
+    pub fn map_array(
+        &self,
+        rt: DartRuntime,
+        mut f: impl FnMut(&CObjectMut<'_>) -> CObject,
+    ) -> Option<CObject> {
+        self.as_array(rt)
+            .map(|array| CObject::array(array.iter().map(|element| Box::new(f(element))).collect()))
+    }
+
+    /// Maps each element of an array object and posts the resulting array to `reply`.
+    ///
+    /// See [`CObjectMut::map_array()`] and [`SendPort::post_cobject()`].
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array.
+    /// - If posting the message failed.
+    pub fn map_array_to_reply(
+        &self,
+        rt: DartRuntime,
+        reply: SendPort,
+        f: impl FnMut(&CObjectMut<'_>) -> CObject,
+    ) -> Result<(), ArrayReplyFailed> {
+        let array = self.map_array(rt, f).ok_or(ArrayReplyFailed::NotAnArray)?;
+        reply.post_cobject(array)?;
+        Ok(())
+    }
+
+    /// Builds a reply array which echoes elements of this array message.
+    ///
+    /// The elements at the `echo_indices` are deep copied in the given order and
+    /// followed by the `payload`. This is useful to echo correlation fields like
+    /// an id or timestamp of a request.
+    ///
+    /// # Errors
+    ///
+    /// - If the object is not an array.
+    /// - If an index is out of bounds.
+    /// - If an echoed element is (or contains objects) of an unknown type.
+    pub fn reply_echoing(
+        &self,
+        rt: DartRuntime,
+        echo_indices: &[usize],
+        payload: CObject,
+    ) -> Result<CObject, ElementError> {
+        let array = self.require_array(rt).map_err(ElementError::NotAnArray)?;
+        let mut reply = Vec::with_capacity(echo_indices.len() + 1);
+        for &index in echo_indices {
+            let element = array.get(index).ok_or(ElementError::OutOfBounds {
+                index,
+                len: array.len(),
+            })?;
+            let copy = element
+                .deep_copy(rt.into())
+                .map_err(|_| ElementError::WrongType {
+                    index,
+                    source: element.type_error("an object of a known type"),
+                })?;
+            reply.push(Box::new(copy));
         }
+        reply.push(Box::new(payload));
+        Ok(CObject::array(reply))
+    }
+
+    /// Iterates over the elements of an array object, copying each into an owned [`CObject`].
+    ///
+    /// Unlike copying the whole array at once, each element is only copied when
+    /// the iterator reaches it. So processing and dropping the elements one by one
+    /// only needs memory for a single element at a time.
+    ///
+    /// Yields an error for elements of (or containing objects of) an unknown type.
+    ///
+    /// Returns `None` if the object is not an array.
+    pub fn owned_element_iter(
+        &self,
+        rt: DartRuntime,
+    ) -> Option<impl Iterator<Item = Result<CObject, UnknownCObjectType>> + '_> {
+        self.as_array(rt).map(move |array| {
+            array
+                .iter()
+                .map(move |element| element.deep_copy(rt.into()))
+        })
+    }
+
+    /// Collects an array of scalar objects into typed data of given type.
+    ///
+    /// This is the reverse of [`TypedData::into_scalar_array()`]:
+    ///
+    /// - Integer types accept int elements which fit into the element type,
+    ///   except for `Uint64` which reinterprets `i64`s like dart does and
+    ///   `Uint8Clamped` which clamps them into the `u8` range.
+    /// - Float types accept double and int elements.
+    /// - `Int32x4`, `Float32x4` and `Float64x2` expect the flattened lanes,
+    ///   i.e. the array length must be a multiple of the number of lanes.
+    ///
+    /// Returns `None` if the object is not an array or any element can't be
+    /// converted.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn collect_scalar_array_as(
+        &self,
+        rt: DartRuntime,
+        data_type: TypedDataType,
+    ) -> Option<TypedData> {
+        fn ints<T: TryFrom<i64>>(array: &[CObjectMut<'_>], rt: DartRuntime) -> Option<Vec<T>> {
+            array
+                .iter()
+                .map(|element| element.as_int(rt)?.try_into().ok())
+                .collect()
+        }
+
+        fn doubles(array: &[CObjectMut<'_>], rt: DartRuntime) -> Option<Vec<f64>> {
+            array
+                .iter()
+                .map(|element| {
+                    quietly(|| element.as_double(rt))
+                        .or_else(|| element.as_int(rt).map(|int| int as f64))
+                })
+                .collect()
+        }
+
+        fn lanes<T: Copy, const N: usize>(scalars: &[T]) -> Option<Vec<[T; N]>> {
+            let chunks = scalars.chunks_exact(N);
+            chunks.remainder().is_empty().then(|| {
+                chunks
+                    .map(|chunk| chunk.try_into().unwrap(/* chunk has len N */))
+                    .collect()
+            })
+        }
+
+        let array = self.as_array(rt)?;
+        Some(match data_type {
+            TypedDataType::ByteData => TypedData::ByteData(ints(array, rt)?.into()),
+            TypedDataType::Int8 => TypedData::Int8(ints(array, rt)?),
+            TypedDataType::Uint8 => TypedData::Uint8(ints(array, rt)?),
+            TypedDataType::Uint8Clamped => TypedData::Uint8Clamped(
+                ints::<i64>(array, rt)?
+                    .into_iter()
+                    .map(|int| int.clamp(0, 255) as u8)
+                    .collect(),
+            ),
+            TypedDataType::Int16 => TypedData::Int16(ints(array, rt)?),
+            TypedDataType::Uint16 => TypedData::Uint16(ints(array, rt)?),
+            TypedDataType::Int32 => TypedData::Int32(ints(array, rt)?),
+            TypedDataType::Uint32 => TypedData::Uint32(ints(array, rt)?),
+            TypedDataType::Int64 => TypedData::Int64(ints(array, rt)?),
+            TypedDataType::Uint64 => TypedData::Uint64(
+                ints::<i64>(array, rt)?
+                    .into_iter()
+                    .map(|int| int as u64)
+                    .collect(),
+            ),
+            TypedDataType::Float32 => TypedData::Float32(
+                doubles(array, rt)?
+                    .into_iter()
+                    .map(|double| double as f32)
+                    .collect(),
+            ),
+            TypedDataType::Float64 => TypedData::Float64(doubles(array, rt)?),
+            TypedDataType::Int32x4 => TypedData::Int32x4(lanes(&ints(array, rt)?)?),
+            TypedDataType::Float32x4 => TypedData::Float32x4(lanes(
+                &doubles(array, rt)?
+                    .into_iter()
+                    .map(|double| double as f32)
+                    .collect::<Vec<_>>(),
+            )?),
+            TypedDataType::Float64x2 => TypedData::Float64x2(lanes(&doubles(array, rt)?)?),
+        })
     }
 
     /// Returns `Some` if the object is typed data.
@@ -264,6 +729,58 @@ impl<'a> CObjectMut<'a> {
             })
     }
 
+    /// Returns the number of elements if the object is typed data.
+    ///
+    /// Unlike [`CObjectMut.as_typed_data()`] this also works if the typed data
+    /// type isn't supported by this library.
+    pub fn raw_typed_data_len(&self, _: DartRuntime) -> Option<usize> {
+        let _ = self.typed_data_type()?;
+        // Safe: We checked the object type, the length field has the same layout
+        // for typed data and external typed data.
+        let length = unsafe { self.partial_mut.value.as_typed_data.length };
+        length.try_into().ok()
+    }
+
+    /// Returns the raw bytes if the object is typed data.
+    ///
+    /// Like [`CObjectMut::raw_typed_data_len()`] this also works if the typed
+    /// data type isn't supported by this library, in which case the elements
+    /// are assumed to be bytes.
+    pub fn raw_typed_data_bytes(&self, rt: DartRuntime) -> Option<&[u8]> {
+        let element_size = self
+            .typed_data_type()?
+            .map_or(1, TypedDataType::element_size);
+        let len = self.raw_typed_data_len(rt)?.checked_mul(element_size)?;
+        // Safe:
+        // - the CObject behind the reference is sound
+        // - we checked the type
+        // - the values are `length` elements of `element_size` bytes
+        unsafe {
+            let as_typed_data = &self.partial_mut.value.as_typed_data;
+            let (ptr, len) = prepare_dart_array_parts(as_typed_data.values, len.try_into().ok()?);
+            Some(slice::from_raw_parts(ptr, len))
+        }
+    }
+
+    /// Returns `true` if both objects are typed data whose buffers overlap.
+    ///
+    /// This allows detecting aliased buffers, e.g. a message which contains the
+    /// same external typed data twice, before forwarding or handling them twice.
+    /// Empty typed data doesn't share a buffer with any object.
+    pub fn shares_buffer_with(&self, other: &CObjectMut<'_>, rt: DartRuntime) -> bool {
+        match (
+            self.raw_typed_data_bytes(rt),
+            other.raw_typed_data_bytes(rt),
+        ) {
+            (Some(a), Some(b)) if !a.is_empty() && !b.is_empty() => {
+                let a = a.as_ptr_range();
+                let b = b.as_ptr_range();
+                a.start < b.end && b.start < a.end
+            }
+            _ => false,
+        }
+    }
+
     /// Reads the typed data type union field.
     ///
     /// # Safety
@@ -289,6 +806,14 @@ impl<'a> CObjectMut<'a> {
     ///
     /// If the object type is not supported an error is returned.
     pub fn value_ref(&self, rt: DartRuntime) -> Result<CObjectValuesRef<'_>, UnknownCObjectType> {
+        self.read_value_ref(rt.into())
+    }
+
+    /// Like [`CObjectMut::value_ref()`], but only needs a [`ReadOnlyRuntime`].
+    pub(crate) fn read_value_ref(
+        &self,
+        _rt: ReadOnlyRuntime,
+    ) -> Result<CObjectValuesRef<'_>, UnknownCObjectType> {
         #![allow(clippy::enum_glob_use)]
         use CObjectValuesRef::*;
         let r#type = self.r#type()?;
@@ -369,7 +894,7 @@ impl<'a> CObjectMut<'a> {
                 // - we checked the type
                 Ok(SendPort(unsafe {
                     let sp = &self.partial_mut.value.as_send_port;
-                    rt.send_port_from_raw_with_origin(sp.id, sp.origin_id)
+                    crate::ports::SendPort::from_raw_parts(sp.id, sp.origin_id)
                 }))
             }
             CObjectType::Capability => {
@@ -383,6 +908,108 @@ impl<'a> CObjectMut<'a> {
         }
     }
 
+    /// Returns an estimate of the number of bytes dart needs to copy the message.
+    ///
+    /// Strings and typed data count with the size of their data, arrays with
+    /// 8 bytes per element plus the size of the elements and all other
+    /// objects with 8 bytes. External typed data is moved instead of copied,
+    /// so its data isn't counted.
+    pub fn estimated_size(&self, rt: DartRuntime) -> usize {
+        const WORD: usize = 8;
+        match self.value_ref(rt) {
+            Ok(CObjectValuesRef::String(string)) => string.len(),
+            Ok(CObjectValuesRef::Array(array)) => array
+                .iter()
+                .map(|element| WORD + element.estimated_size(rt))
+                .sum(),
+            Ok(CObjectValuesRef::TypedData {
+                data: Ok(data),
+                external_typed: false,
+            }) => data.as_bytes().len(),
+            _ => WORD,
+        }
+    }
+
+    /// Creates an owned deep copy of this object.
+    ///
+    /// Typed data is copied into new external typed data.
+    ///
+    /// # Errors
+    ///
+    /// If the object or any nested object is of an unknown type,
+    /// including typed data with an unknown element type.
+    pub(crate) fn deep_copy(&self, rt: ReadOnlyRuntime) -> Result<CObject, UnknownCObjectType> {
+        Ok(match self.read_value_ref(rt)? {
+            CObjectValuesRef::Null => CObject::null(),
+            CObjectValuesRef::Bool(v) => CObject::bool(v),
+            CObjectValuesRef::Int32(v) => CObject::int32(v),
+            CObjectValuesRef::Int64(v) => CObject::int64(v),
+            CObjectValuesRef::Double(v) => CObject::double(v),
+            // a string from a `Dart_CObject` can't contain a `'\0'`
+            CObjectValuesRef::String(v) => CObject::string_lossy(v),
+            CObjectValuesRef::Array(array) => CObject::array(
+                array
+                    .iter()
+                    .map(|element| element.deep_copy(rt).map(Box::new))
+                    .collect::<Result<_, _>>()?,
+            ),
+            CObjectValuesRef::TypedData { data, .. } => CObject::typed_data(
+                data.map_err(|_| UnknownCObjectType(self.partial_mut.type_))?
+                    .into_owned(),
+            ),
+            CObjectValuesRef::SendPort(_) => {
+                // Safe: we checked the type
+                let sp = unsafe { &self.partial_mut.value.as_send_port };
+                CObject::raw_send_port(sp.id, sp.origin_id)
+            }
+            CObjectValuesRef::Capability(cap) => CObject::capability(cap),
+        })
+    }
+
+    /// Like [`CObjectMut::deep_copy()`], but the copy can be sent to other threads.
+    ///
+    /// # Errors
+    ///
+    /// See [`CObjectMut::deep_copy()`].
+    pub(crate) fn sendable_copy(
+        &self,
+        rt: ReadOnlyRuntime,
+    ) -> Result<SendableCopy, UnknownCObjectType> {
+        self.deep_copy(rt).map(SendableCopy)
+    }
+
+    /// Runs the finalizers of all external typed data, like dart does after receiving them.
+    ///
+    /// # Safety
+    ///
+    /// The external typed data must be nulled afterwards.
+    #[cfg(test)]
+    pub(crate) unsafe fn finalize_external_typed_objects(&mut self, rt: DartRuntime) {
+        match self.r#type() {
+            Ok(CObjectType::ExternalTypedData) => unsafe {
+                let etd = &self.partial_mut.value.as_external_typed_data;
+                (etd.callback.expect("unexpected null pointer callback"))(
+                    etd.data.cast(),
+                    etd.peer,
+                );
+            },
+            Ok(CObjectType::Array) => {
+                let array = unsafe {
+                    let as_array = &mut self.partial_mut.value.as_array;
+                    let (ptr, len) = prepare_dart_array_parts_mut(
+                        as_array.values.cast::<CObjectMut<'a>>(),
+                        as_array.length,
+                    );
+                    slice::from_raw_parts_mut(ptr, len)
+                };
+                for element in array {
+                    unsafe { element.finalize_external_typed_objects(rt) };
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub(crate) fn null_external_typed_objects(&mut self, rt: DartRuntime) {
         match self.r#type() {
             Ok(CObjectType::ExternalTypedData) => self.set_to_null(),
@@ -405,6 +1032,54 @@ impl<'a> CObjectMut<'a> {
     }
 }
 
+/// An owned deep copy of an object, see [`CObjectMut::sendable_copy()`].
+pub(crate) struct SendableCopy(CObject);
+
+// Safe: a deep copy shares no data with the copied object, it only owns
+// allocations made by this library which can be freed on any thread.
+unsafe impl Send for SendableCopy {}
+
+impl SendableCopy {
+    pub(crate) fn into_inner(self) -> CObject {
+        self.0
+    }
+}
+
+/// Replying with a mapped array failed.
+#[derive(Debug, Error)]
+pub enum ArrayReplyFailed {
+    /// The object which should be mapped is not an array.
+    #[error("The object is not an array.")]
+    NotAnArray,
+    /// Posting the reply failed.
+    #[error(transparent)]
+    Posting(#[from] PostingMessageFailed),
+}
+
+/// A message failed the capability check.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AuthError {
+    /// The message doesn't contain a capability at the expected position.
+    #[error("The message doesn't contain a capability.")]
+    MissingCapability,
+    /// The message contains a different capability.
+    #[error("The capability of the message doesn't match.")]
+    CapabilityMismatch,
+}
+
+/// The object is not of the required type.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error(
+    "Expected {expected}, found {}.",
+    found.map_or_else(|| "an unknown type".to_owned(), |found| format!("{found:?}"))
+)]
+pub struct TypeError {
+    /// Description of the expected type.
+    pub expected: &'static str,
+    /// The found type, `None` if it's not known by this library.
+    pub found: Option<CObjectType>,
+}
+
 impl Debug for CObjectMut<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Ok(rt) = DartRuntime::instance() {
@@ -418,3 +1093,479 @@ impl Debug for CObjectMut<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    use dart_api_dl_sys::{Dart_TypedData_Type, ILLEGAL_PORT};
+
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[test]
+    fn test_map_array() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut input = CObject::array(vec![
+            Box::new(CObject::int64(1)),
+            Box::new(CObject::int32(-4)),
+            Box::new(CObject::int64(21)),
+        ]);
+        let mut output = input
+            .as_mut()
+            .map_array(rt, |element| {
+                CObject::int64(element.as_int(rt).unwrap() * 2)
+            })
+            .unwrap();
+        let doubled = output
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|element| element.as_int64(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(doubled, [2, -8, 42]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut empty = CObject::array(vec![]);
+        assert_eq!(empty.as_mut().array_len(rt), Some(0));
+        assert_eq!(empty.as_mut().iter(rt).unwrap().count(), 0);
+
+        let mut single = CObject::array(vec![Box::new(CObject::int32(7))]);
+        assert_eq!(single.as_mut().array_len(rt), Some(1));
+        let ints = single
+            .as_mut()
+            .iter(rt)
+            .unwrap()
+            .map(|element| element.as_int(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ints, [7]);
+
+        let mut nested = CObject::array(vec![
+            Box::new(CObject::array(vec![
+                Box::new(CObject::int32(1)),
+                Box::new(CObject::int32(2)),
+            ])),
+            Box::new(CObject::array(vec![Box::new(CObject::int32(3))])),
+        ]);
+        let ints = nested
+            .as_mut()
+            .iter(rt)
+            .unwrap()
+            .rev()
+            .flat_map(|inner| inner.iter(rt).unwrap())
+            .map(|element| element.as_int(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ints, [3, 1, 2]);
+
+        assert_eq!(CObject::int32(1).as_mut().array_len(rt), None);
+        assert!(CObject::null().as_mut().iter(rt).is_none());
+    }
+
+    #[test]
+    fn test_descend() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut command = CObject::array(vec![
+            Box::new(CObject::string("outer").unwrap()),
+            Box::new(CObject::array(vec![
+                Box::new(CObject::string("inner").unwrap()),
+                Box::new(CObject::array(vec![Box::new(CObject::int64(42))])),
+            ])),
+        ]);
+        let command = command.as_mut();
+        assert_eq!(
+            command.descend(rt, &[1, 0]).unwrap().as_string(rt),
+            Some("inner")
+        );
+        assert_eq!(
+            command.descend(rt, &[1, 1, 0]).unwrap().as_int(rt),
+            Some(42)
+        );
+        assert_eq!(command.descend(rt, &[]).unwrap().array_len(rt), Some(2));
+        assert!(command.descend(rt, &[1, 2]).is_none());
+        assert!(command.descend(rt, &[0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_map_array_to_reply_requires_array() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw(12).unwrap();
+        let res = CObject::int64(1)
+            .as_mut()
+            .map_array_to_reply(rt, port, |_| CObject::null());
+        assert!(matches!(res, Err(ArrayReplyFailed::NotAnArray)));
+    }
+
+    #[test]
+    fn test_map_array_to_reply() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut input = CObject::array(vec![
+            Box::new(CObject::int64(1)),
+            Box::new(CObject::int32(-4)),
+            Box::new(CObject::int64(21)),
+        ]);
+        let mut replies = posted_messages(|port| {
+            input
+                .as_mut()
+                .map_array_to_reply(rt, port, |element| {
+                    CObject::int64(element.as_int(rt).unwrap() * 2)
+                })
+                .unwrap();
+        });
+
+        assert_eq!(replies.len(), 1);
+        let doubled = replies[0]
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|element| element.as_int(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(doubled, [2, -8, 42]);
+    }
+
+    #[test]
+    fn test_as_optional() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let as_int = |obj: &CObjectMut<'_>| obj.as_int(rt);
+        assert_eq!(
+            CObject::optional(None).as_mut().as_optional(rt, as_int),
+            Some(None)
+        );
+        assert_eq!(
+            CObject::optional(Some(CObject::int32(3)))
+                .as_mut()
+                .as_optional(rt, as_int),
+            Some(Some(3))
+        );
+        assert_eq!(
+            CObject::string("3")
+                .unwrap()
+                .as_mut()
+                .as_optional(rt, as_int),
+            None
+        );
+    }
+
+    #[test]
+    fn test_require_typed_accessors() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw(10).unwrap();
+        let mut request = CObject::array(vec![
+            Box::new(CObject::string("add").unwrap()),
+            Box::new(CObject::int32(4)),
+            Box::new(CObject::send_port(port)),
+        ]);
+        let request = request.as_mut();
+        let args = request.require_array(rt).unwrap();
+        assert_eq!(args[0].require_str(rt), Ok("add"));
+        assert_eq!(args[1].require_int(rt), Ok(4));
+        assert!(args[2].require_send_port(rt).unwrap().same_target(&port));
+    }
+
+    #[test]
+    fn test_require_type_mismatch_is_descriptive() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let err = CObject::string("4")
+            .unwrap()
+            .as_mut()
+            .require_int(rt)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            TypeError {
+                expected: "an int",
+                found: Some(CObjectType::String),
+            }
+        );
+        assert_eq!(err.to_string(), "Expected an int, found String.");
+        assert_eq!(
+            CObject::null()
+                .as_mut()
+                .require_array(rt)
+                .unwrap_err()
+                .to_string(),
+            "Expected an array, found Null."
+        );
+        assert!(CObject::raw_send_port(ILLEGAL_PORT, ILLEGAL_PORT)
+            .as_mut()
+            .require_send_port(rt)
+            .is_err());
+    }
+
+    #[test]
+    fn test_require_capability() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        assert_eq!(
+            CObject::capability(7).as_mut().require_capability(rt, 7),
+            Ok(())
+        );
+        assert_eq!(
+            CObject::capability(8).as_mut().require_capability(rt, 7),
+            Err(AuthError::CapabilityMismatch)
+        );
+        assert_eq!(
+            CObject::int64(7).as_mut().require_capability(rt, 7),
+            Err(AuthError::MissingCapability)
+        );
+
+        let mut request = CObject::array(vec![
+            Box::new(CObject::string("cmd").unwrap()),
+            Box::new(CObject::capability(7)),
+        ]);
+        let request = request.as_mut();
+        assert_eq!(request.require_capability_at(rt, 1, 7), Ok(()));
+        assert_eq!(
+            request.require_capability_at(rt, 1, 9),
+            Err(AuthError::CapabilityMismatch)
+        );
+        assert_eq!(
+            request.require_capability_at(rt, 0, 7),
+            Err(AuthError::MissingCapability)
+        );
+        assert_eq!(
+            request.require_capability_at(rt, 2, 7),
+            Err(AuthError::MissingCapability)
+        );
+    }
+
+    #[test]
+    fn test_owned_element_iter() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut array = CObject::array(vec![
+            Box::new(CObject::int64(42)),
+            Box::new(CObject::string("element").unwrap()),
+            Box::new(CObject::typed_data(TypedData::Int16(vec![1, 2, 3]))),
+        ]);
+        let elements = array
+            .as_mut()
+            .owned_element_iter(rt)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        drop(array);
+
+        let mut elements = elements.into_iter();
+        assert_eq!(elements.next().unwrap().as_mut().as_int(rt), Some(42));
+        assert_eq!(
+            elements.next().unwrap().as_mut().as_string(rt),
+            Some("element")
+        );
+        let mut typed_data = elements.next().unwrap();
+        assert!(matches!(
+            typed_data.as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Int16(&[1, 2, 3])), _))
+        ));
+        assert!(elements.next().is_none());
+
+        assert!(CObject::null().as_mut().owned_element_iter(rt).is_none());
+    }
+
+    #[test]
+    fn test_scalar_array_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let data = vec![0.5, -1.25, f64::MAX];
+        let mut array = TypedData::Float64(data.clone()).into_scalar_array();
+        let elements = array
+            .as_mut()
+            .as_array(rt)
+            .unwrap()
+            .iter()
+            .map(|element| element.as_double(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(elements, data);
+
+        let collected = array
+            .as_mut()
+            .collect_scalar_array_as(rt, TypedDataType::Float64)
+            .unwrap();
+        assert!(matches!(collected, TypedData::Float64(collected) if collected == data));
+    }
+
+    #[test]
+    fn test_collect_scalar_array_rejects_mismatches() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut array = TypedData::Int16(vec![1, -300, 3]).into_scalar_array();
+        let array = array.as_mut();
+        assert!(array
+            .collect_scalar_array_as(rt, TypedDataType::Uint8)
+            .is_none());
+        assert!(matches!(
+            array.collect_scalar_array_as(rt, TypedDataType::Uint8Clamped),
+            Some(TypedData::Uint8Clamped(data)) if data == [1, 0, 3]
+        ));
+        assert!(array
+            .collect_scalar_array_as(rt, TypedDataType::Int32x4)
+            .is_none());
+        assert!(CObject::int64(1)
+            .as_mut()
+            .collect_scalar_array_as(rt, TypedDataType::Int64)
+            .is_none());
+    }
+
+    #[test]
+    fn test_flags_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for bits in [0, 0b101, 1 << 63, u64::MAX] {
+            let mut obj = CObject::flags(bits);
+            let obj = obj.as_mut();
+            assert_eq!(obj.r#type(), Ok(CObjectType::Int64));
+            assert_eq!(obj.as_flags(rt), Some(bits));
+        }
+        assert_eq!(CObject::int32(3).as_mut().as_flags(rt), Some(3));
+        assert_eq!(CObject::bool(true).as_mut().as_flags(rt), None);
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let uuid = uuid::Uuid::from_u128(0x0123_4567_89ab_cdef_fedc_ba98_7654_3210);
+        let mut obj = CObject::uuid(uuid);
+        assert_eq!(obj.as_mut().as_uuid(rt), Some(uuid));
+
+        let mut obj = CObject::typed_data(TypedData::Uint8(vec![0; 15]));
+        assert_eq!(obj.as_mut().as_uuid(rt), None);
+    }
+
+    #[cfg(feature = "bitflags")]
+    #[test]
+    fn test_decode_bitflags() {
+        bitflags::bitflags! {
+            #[derive(Debug, PartialEq, Eq)]
+            struct Permissions: u64 {
+                const READ = 0b001;
+                const WRITE = 0b010;
+                const EXECUTE = 0b100;
+            }
+        }
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let flags = Permissions::READ | Permissions::EXECUTE;
+        let mut obj = CObject::flags(flags.bits());
+        assert_eq!(obj.as_mut().as_bitflags::<Permissions>(rt), Some(flags));
+        let mut obj = CObject::flags(0b1001);
+        assert_eq!(obj.as_mut().as_bitflags::<Permissions>(rt), None);
+    }
+
+    #[test]
+    fn test_reply_echoing() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut request = CObject::array(vec![
+            Box::new(CObject::string("get").unwrap()),
+            Box::new(CObject::int64(17)),
+        ]);
+        let request = request.as_mut();
+        let mut reply = request
+            .reply_echoing(rt, &[1], CObject::string("value").unwrap())
+            .unwrap();
+        let reply = reply.as_mut();
+        let reply = reply.as_array(rt).unwrap();
+        assert_eq!(reply.len(), 2);
+        assert_eq!(reply[0].as_int64(rt), Some(17));
+        assert_eq!(reply[1].as_string(rt), Some("value"));
+
+        assert!(matches!(
+            request.reply_echoing(rt, &[1, 2], CObject::null()),
+            Err(ElementError::OutOfBounds { index: 2, len: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_raw_typed_data_len() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = CObject::typed_data(TypedData::Uint16(vec![7; 1024]));
+        let obj = obj.as_mut();
+        assert_eq!(obj.raw_typed_data_len(rt), Some(1024));
+
+        // simulate a typed data type added in a newer dart version
+        obj.partial_mut.value.as_external_typed_data.type_ = Dart_TypedData_Type(99);
+        assert!(matches!(obj.as_typed_data(rt), Some((Err(_), _))));
+        assert_eq!(obj.raw_typed_data_len(rt), Some(1024));
+
+        assert_eq!(CObject::int32(1024).as_mut().raw_typed_data_len(rt), None);
+    }
+
+    #[test]
+    fn test_raw_typed_data_bytes_hash() {
+        fn digest(bytes: &[u8]) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            bytes.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let values = vec![1.5_f64, -0.25, 1e300];
+        let expected = values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect::<Vec<_>>();
+        let mut obj = CObject::typed_data(TypedData::Float64(values.clone()));
+        let mut copy = CObject::typed_data(TypedData::Float64(values));
+        let bytes = obj.as_mut().raw_typed_data_bytes(rt).unwrap().to_vec();
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            digest(&bytes),
+            digest(copy.as_mut().raw_typed_data_bytes(rt).unwrap())
+        );
+
+        // unknown element types are treated as bytes
+        let obj = obj.as_mut();
+        obj.partial_mut.value.as_external_typed_data.type_ = Dart_TypedData_Type(99);
+        assert_eq!(obj.raw_typed_data_bytes(rt), Some(&expected[..3]));
+    }
+
+    #[test]
+    fn test_shares_buffer_with() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = CObject::typed_data(TypedData::Uint16(vec![1, 2, 3]));
+        let mut copy = CObject::typed_data(TypedData::Uint16(vec![1, 2, 3]));
+        let obj = obj.as_mut();
+        let mut alias = *obj.partial_mut;
+        let alias = CObjectMut {
+            partial_mut: &mut alias,
+        };
+        assert!(obj.shares_buffer_with(&alias, rt));
+        assert!(!obj.shares_buffer_with(&copy.as_mut(), rt));
+
+        let mut tail = *obj.partial_mut;
+        unsafe {
+            tail.value.as_external_typed_data.data = tail.value.as_external_typed_data.data.add(4);
+            tail.value.as_external_typed_data.length = 1;
+        }
+        let tail = CObjectMut {
+            partial_mut: &mut tail,
+        };
+        assert!(tail.shares_buffer_with(&obj, rt));
+        assert!(!obj.shares_buffer_with(&CObject::int64(1).as_mut(), rt));
+    }
+
+    #[test]
+    fn test_float32_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for val in [1.5_f32, f32::MIN_POSITIVE, f32::MAX, 0.1] {
+            let mut obj = CObject::float32(val);
+            let obj = obj.as_mut();
+            assert_eq!(obj.r#type(), Ok(CObjectType::Double));
+            assert_eq!(obj.as_float32(rt).map(f32::to_bits), Some(val.to_bits()));
+        }
+    }
+
+    #[test]
+    fn test_as_float32_narrows_doubles() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let narrow = |val: f64| CObject::double(val).as_mut().as_float32(rt).unwrap();
+        assert_eq!(narrow(0.1).to_bits(), 0.1_f32.to_bits());
+        assert_eq!(narrow(f64::from(f32::MAX)).to_bits(), f32::MAX.to_bits());
+        assert_eq!(narrow(f64::MAX).to_bits(), f32::INFINITY.to_bits());
+        assert_eq!(narrow(-1e39).to_bits(), f32::NEG_INFINITY.to_bits());
+        assert!(narrow(f64::NAN).is_nan());
+    }
+}
@@ -12,13 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{convert::TryInto, ffi::c_void};
+use std::{
+    convert::TryInto,
+    ffi::c_void,
+    fmt::{self, Display},
+    iter,
+    ops::Deref,
+    ptr::{self, NonNull},
+    sync::Arc,
+};
 
 use dart_api_dl_sys::_Dart_CObject__bindgen_ty_1__bindgen_ty_5;
+use thiserror::Error;
 
 use crate::ports::SendPort;
 
-use super::{CObjectMut, TypedDataType, UnknownTypedDataType};
+use super::{CObject, CObjectMut, TypedDataType, UnknownTypedDataType};
 
 /// External Typed Data as represented in a [`Dart_CObject`].
 pub type ExternalTypedData = _Dart_CObject__bindgen_ty_1__bindgen_ty_5;
@@ -26,6 +35,26 @@ pub type ExternalTypedData = _Dart_CObject__bindgen_ty_1__bindgen_ty_5;
 /// Dart Capability
 pub type Capability = i64;
 
+/// The number of bytes isn't a multiple of the element size of the data type.
+#[derive(Debug, Error)]
+#[error("{length} bytes are not a multiple of the element size of {data_type:?}.")]
+pub struct ByteLengthError {
+    /// The requested data type.
+    pub data_type: TypedDataType,
+    /// The number of bytes.
+    pub length: usize,
+}
+
+/// The destination buffer is too small, see [`TypedDataRef::copy_into()`].
+#[derive(Debug, Error)]
+#[error("The buffer of {available} bytes is too small for {required} bytes.")]
+pub struct BufferTooSmall {
+    /// The number of bytes of the data.
+    pub required: usize,
+    /// The number of bytes of the buffer.
+    pub available: usize,
+}
+
 /// A reference to the data in the `CObject`.
 ///
 /// In case of copy data a copy is used instead.
@@ -58,6 +87,43 @@ pub enum CObjectValuesRef<'b> {
     Capability(Capability),
 }
 
+/// A short human-readable summary, e.g. for logging.
+///
+/// Scalars and strings are shown as value, e.g. `null`, `42` or `"hello"`. Arrays,
+/// typed data and external typed data only show their size, e.g. `[3 elements]`,
+/// `Uint8[128 bytes]` or `Float32[8 bytes, external]`.
+impl Display for CObjectValuesRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CObjectValuesRef::Null => write!(f, "null"),
+            CObjectValuesRef::Bool(value) => write!(f, "{value}"),
+            CObjectValuesRef::Int32(value) => write!(f, "{value}"),
+            CObjectValuesRef::Int64(value) => write!(f, "{value}"),
+            CObjectValuesRef::Double(value) => write!(f, "{value:?}"),
+            CObjectValuesRef::String(value) => write!(f, "{value:?}"),
+            CObjectValuesRef::Array(array) => write!(f, "[{} elements]", array.len()),
+            CObjectValuesRef::TypedData {
+                data,
+                external_typed,
+            } => {
+                match data {
+                    Ok(data) => {
+                        write!(f, "{:?}[{} bytes", data.data_type(), data.as_bytes().len())?;
+                    }
+                    Err(_) => write!(f, "Unknown[? bytes")?,
+                }
+                if *external_typed {
+                    write!(f, ", external")?;
+                }
+                write!(f, "]")
+            }
+            CObjectValuesRef::SendPort(Some(port)) => write!(f, "SendPort({})", port.id_string()),
+            CObjectValuesRef::SendPort(None) => write!(f, "SendPort(ILLEGAL_PORT)"),
+            CObjectValuesRef::Capability(id) => write!(f, "Capability({id})"),
+        }
+    }
+}
+
 /// Reference to typed data in a `CObject`.
 #[derive(Debug, Clone, Copy)]
 pub enum TypedDataRef<'b> {
@@ -99,33 +165,220 @@ pub enum TypedDataRef<'b> {
     Float64x2(&'b [[f64; 2]]),
 }
 
-impl TypedDataRef<'_> {
+impl<'b> TypedDataRef<'b> {
     pub(super) unsafe fn from_raw(data_type: TypedDataType, data: *const u8, len: usize) -> Self {
         #![allow(
             unsafe_op_in_unsafe_fn,
             clippy::enum_glob_use,
             clippy::cast_ptr_alignment
         )]
-        use std::slice::from_raw_parts;
         use TypedDataRef::*;
+
+        /// The dangling pointer of empty data isn't aligned for the element type.
+        unsafe fn slice<'b, T>(data: *const u8, len: usize) -> &'b [T] {
+            if len == 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(data.cast::<T>(), len)
+            }
+        }
+
         match data_type {
-            TypedDataType::ByteData => ByteData(from_raw_parts(data, len)),
-            TypedDataType::Int8 => Int8(from_raw_parts(data.cast::<i8>(), len)),
-            TypedDataType::Uint8 => Uint8(from_raw_parts(data, len)),
-            TypedDataType::Uint8Clamped => Uint8Clamped(from_raw_parts(data, len)),
-            TypedDataType::Int16 => Int16(from_raw_parts(data.cast::<i16>(), len)),
-            TypedDataType::Uint16 => Uint16(from_raw_parts(data.cast::<u16>(), len)),
-            TypedDataType::Int32 => Int32(from_raw_parts(data.cast::<i32>(), len)),
-            TypedDataType::Uint32 => Uint32(from_raw_parts(data.cast::<u32>(), len)),
-            TypedDataType::Int64 => Int64(from_raw_parts(data.cast::<i64>(), len)),
-            TypedDataType::Uint64 => Uint64(from_raw_parts(data.cast::<u64>(), len)),
-            TypedDataType::Float32 => Float32(from_raw_parts(data.cast::<f32>(), len)),
-            TypedDataType::Float64 => Float64(from_raw_parts(data.cast::<f64>(), len)),
-            TypedDataType::Int32x4 => Int32x4(from_raw_parts(data.cast::<[i32; 4]>(), len)),
-            TypedDataType::Float32x4 => Float32x4(from_raw_parts(data.cast::<[f32; 4]>(), len)),
-            TypedDataType::Float64x2 => Float64x2(from_raw_parts(data.cast::<[f64; 2]>(), len)),
+            TypedDataType::ByteData => ByteData(slice(data, len)),
+            TypedDataType::Int8 => Int8(slice(data, len)),
+            TypedDataType::Uint8 => Uint8(slice(data, len)),
+            TypedDataType::Uint8Clamped => Uint8Clamped(slice(data, len)),
+            TypedDataType::Int16 => Int16(slice(data, len)),
+            TypedDataType::Uint16 => Uint16(slice(data, len)),
+            TypedDataType::Int32 => Int32(slice(data, len)),
+            TypedDataType::Uint32 => Uint32(slice(data, len)),
+            TypedDataType::Int64 => Int64(slice(data, len)),
+            TypedDataType::Uint64 => Uint64(slice(data, len)),
+            TypedDataType::Float32 => Float32(slice(data, len)),
+            TypedDataType::Float64 => Float64(slice(data, len)),
+            TypedDataType::Int32x4 => Int32x4(slice(data, len)),
+            TypedDataType::Float32x4 => Float32x4(slice(data, len)),
+            TypedDataType::Float64x2 => Float64x2(slice(data, len)),
+        }
+    }
+
+    /// Returns the data type of the referenced data.
+    pub fn data_type(&self) -> TypedDataType {
+        match self {
+            TypedDataRef::ByteData(_) => TypedDataType::ByteData,
+            TypedDataRef::Int8(_) => TypedDataType::Int8,
+            TypedDataRef::Uint8(_) => TypedDataType::Uint8,
+            TypedDataRef::Uint8Clamped(_) => TypedDataType::Uint8Clamped,
+            TypedDataRef::Int16(_) => TypedDataType::Int16,
+            TypedDataRef::Uint16(_) => TypedDataType::Uint16,
+            TypedDataRef::Int32(_) => TypedDataType::Int32,
+            TypedDataRef::Uint32(_) => TypedDataType::Uint32,
+            TypedDataRef::Int64(_) => TypedDataType::Int64,
+            TypedDataRef::Uint64(_) => TypedDataType::Uint64,
+            TypedDataRef::Float32(_) => TypedDataType::Float32,
+            TypedDataRef::Float64(_) => TypedDataType::Float64,
+            TypedDataRef::Int32x4(_) => TypedDataType::Int32x4,
+            TypedDataRef::Float32x4(_) => TypedDataType::Float32x4,
+            TypedDataRef::Float64x2(_) => TypedDataType::Float64x2,
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        match self {
+            TypedDataRef::ByteData(data)
+            | TypedDataRef::Uint8(data)
+            | TypedDataRef::Uint8Clamped(data) => data.len(),
+            TypedDataRef::Int8(data) => data.len(),
+            TypedDataRef::Int16(data) => data.len(),
+            TypedDataRef::Uint16(data) => data.len(),
+            TypedDataRef::Int32(data) => data.len(),
+            TypedDataRef::Uint32(data) => data.len(),
+            TypedDataRef::Int64(data) => data.len(),
+            TypedDataRef::Uint64(data) => data.len(),
+            TypedDataRef::Float32(data) => data.len(),
+            TypedDataRef::Float64(data) => data.len(),
+            TypedDataRef::Int32x4(data) => data.len(),
+            TypedDataRef::Float32x4(data) => data.len(),
+            TypedDataRef::Float64x2(data) => data.len(),
         }
     }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the raw bytes backing the data, without copying.
+    ///
+    /// The elements are in host byte order and each takes
+    /// [`TypedDataType::element_size()`] bytes, e.g. 8 bytes for `Float64` and
+    /// 16 bytes for `Float32x4`.
+    ///
+    /// See [`TypedData::from_bytes()`] for the reverse.
+    pub fn as_bytes(&self) -> &'b [u8] {
+        fn bytes<T>(data: &[T]) -> &[u8] {
+            use std::{mem::size_of_val, slice::from_raw_parts};
+
+            // Safe: all element types are plain numbers or arrays of them
+            unsafe { from_raw_parts(data.as_ptr().cast::<u8>(), size_of_val(data)) }
+        }
+
+        match *self {
+            TypedDataRef::ByteData(data)
+            | TypedDataRef::Uint8(data)
+            | TypedDataRef::Uint8Clamped(data) => data,
+            TypedDataRef::Int8(data) => bytes(data),
+            TypedDataRef::Int16(data) => bytes(data),
+            TypedDataRef::Uint16(data) => bytes(data),
+            TypedDataRef::Int32(data) => bytes(data),
+            TypedDataRef::Uint32(data) => bytes(data),
+            TypedDataRef::Int64(data) => bytes(data),
+            TypedDataRef::Uint64(data) => bytes(data),
+            TypedDataRef::Float32(data) => bytes(data),
+            TypedDataRef::Float64(data) => bytes(data),
+            TypedDataRef::Int32x4(data) => bytes(data),
+            TypedDataRef::Float32x4(data) => bytes(data),
+            TypedDataRef::Float64x2(data) => bytes(data),
+        }
+    }
+
+    /// Copies the raw bytes backing the data into the start of `dst`.
+    ///
+    /// This allows reusing a buffer instead of allocating one for each message.
+    /// The bytes are the same as returned by [`TypedDataRef::as_bytes()`].
+    ///
+    /// Returns the number of copied bytes.
+    ///
+    /// # Errors
+    ///
+    /// If `dst` is too small, in which case nothing is copied.
+    pub fn copy_into(&self, dst: &mut [u8]) -> Result<usize, BufferTooSmall> {
+        let bytes = self.as_bytes();
+        if let Some(dst) = dst.get_mut(..bytes.len()) {
+            dst.copy_from_slice(bytes);
+            Ok(bytes.len())
+        } else {
+            Err(BufferTooSmall {
+                required: bytes.len(),
+                available: dst.len(),
+            })
+        }
+    }
+
+    /// Copies the referenced data into a new [`TypedData`] instance.
+    ///
+    /// The data type is preserved, e.g. `Uint8Clamped` data stays `Uint8Clamped`.
+    ///
+    /// See [`TypedData::as_ref()`] for the reverse.
+    pub fn into_owned(self) -> TypedData {
+        match self {
+            TypedDataRef::ByteData(data) => TypedData::ByteData(data.into()),
+            TypedDataRef::Int8(data) => TypedData::Int8(data.to_vec()),
+            TypedDataRef::Uint8(data) => TypedData::Uint8(data.to_vec()),
+            TypedDataRef::Uint8Clamped(data) => TypedData::Uint8Clamped(data.to_vec()),
+            TypedDataRef::Int16(data) => TypedData::Int16(data.to_vec()),
+            TypedDataRef::Uint16(data) => TypedData::Uint16(data.to_vec()),
+            TypedDataRef::Int32(data) => TypedData::Int32(data.to_vec()),
+            TypedDataRef::Uint32(data) => TypedData::Uint32(data.to_vec()),
+            TypedDataRef::Int64(data) => TypedData::Int64(data.to_vec()),
+            TypedDataRef::Uint64(data) => TypedData::Uint64(data.to_vec()),
+            TypedDataRef::Float32(data) => TypedData::Float32(data.to_vec()),
+            TypedDataRef::Float64(data) => TypedData::Float64(data.to_vec()),
+            TypedDataRef::Int32x4(data) => TypedData::Int32x4(data.to_vec()),
+            TypedDataRef::Float32x4(data) => TypedData::Float32x4(data.to_vec()),
+            TypedDataRef::Float64x2(data) => TypedData::Float64x2(data.to_vec()),
+        }
+    }
+
+    /// Iterates over windows of `n` elements of the same data type, without copying.
+    ///
+    /// The last window is shorter if the number of elements isn't a multiple of `n`.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is 0.
+    pub fn chunks(&self, n: usize) -> impl Iterator<Item = TypedDataRef<'b>> {
+        assert!(n != 0, "chunk size must be non-zero");
+        let mut rest = *self;
+        iter::from_fn(move || {
+            (!rest.is_empty()).then(|| {
+                let (chunk, tail) = rest.split_at(n.min(rest.len()));
+                rest = tail;
+                chunk
+            })
+        })
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        macro_rules! split {
+            ($($variant:ident),+) => (
+                match self {
+                    $(TypedDataRef::$variant(data) => {
+                        let (head, tail) = data.split_at(mid);
+                        (TypedDataRef::$variant(head), TypedDataRef::$variant(tail))
+                    })+
+                }
+            );
+        }
+        split!(
+            ByteData,
+            Int8,
+            Uint8,
+            Uint8Clamped,
+            Int16,
+            Uint16,
+            Int32,
+            Uint32,
+            Int64,
+            Uint64,
+            Float32,
+            Float64,
+            Int32x4,
+            Float32x4,
+            Float64x2
+        )
+    }
 }
 
 /// Owned typed data you can send to dart (through a [`CObject`]).
@@ -184,6 +437,218 @@ impl TypedData {
             TypedData::Float64x2(_) => TypedDataType::Float64x2,
         }
     }
+
+    /// Borrows the data, e.g. to handle owned and referenced data the same way.
+    ///
+    /// See [`TypedDataRef::into_owned()`] for the reverse.
+    pub fn as_ref(&self) -> TypedDataRef<'_> {
+        match self {
+            TypedData::ByteData(data) => TypedDataRef::ByteData(data),
+            TypedData::Int8(data) => TypedDataRef::Int8(data),
+            TypedData::Uint8(data) => TypedDataRef::Uint8(data),
+            TypedData::Uint8Clamped(data) => TypedDataRef::Uint8Clamped(data),
+            TypedData::Int16(data) => TypedDataRef::Int16(data),
+            TypedData::Uint16(data) => TypedDataRef::Uint16(data),
+            TypedData::Int32(data) => TypedDataRef::Int32(data),
+            TypedData::Uint32(data) => TypedDataRef::Uint32(data),
+            TypedData::Int64(data) => TypedDataRef::Int64(data),
+            TypedData::Uint64(data) => TypedDataRef::Uint64(data),
+            TypedData::Float32(data) => TypedDataRef::Float32(data),
+            TypedData::Float64(data) => TypedDataRef::Float64(data),
+            TypedData::Int32x4(data) => TypedDataRef::Int32x4(data),
+            TypedData::Float32x4(data) => TypedDataRef::Float32x4(data),
+            TypedData::Float64x2(data) => TypedDataRef::Float64x2(data),
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        match self {
+            TypedData::ByteData(data) => data.len(),
+            TypedData::Int8(data) => data.len(),
+            TypedData::Uint8(data) | TypedData::Uint8Clamped(data) => data.len(),
+            TypedData::Int16(data) => data.len(),
+            TypedData::Uint16(data) => data.len(),
+            TypedData::Int32(data) => data.len(),
+            TypedData::Uint32(data) => data.len(),
+            TypedData::Int64(data) => data.len(),
+            TypedData::Uint64(data) => data.len(),
+            TypedData::Float32(data) => data.len(),
+            TypedData::Float64(data) => data.len(),
+            TypedData::Int32x4(data) => data.len(),
+            TypedData::Float32x4(data) => data.len(),
+            TypedData::Float64x2(data) => data.len(),
+        }
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Creates typed data of the given type from its raw bytes.
+    ///
+    /// The bytes are interpreted in host byte order, see [`TypedDataRef::as_bytes()`].
+    /// Byte data types reuse the allocation, all other types are copied to get
+    /// a correctly aligned buffer.
+    ///
+    /// # Errors
+    ///
+    /// If the number of bytes isn't a multiple of the element size.
+    pub fn from_bytes(data_type: TypedDataType, bytes: Vec<u8>) -> Result<Self, ByteLengthError> {
+        fn elements<T: Copy + Default>(bytes: &[u8], len: usize) -> Vec<T> {
+            let mut data = vec![T::default(); len];
+            // Safe: all element types are plain numbers or arrays of them, `len` elements
+            // have exactly the size of the bytes and the buffers don't overlap
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    data.as_mut_ptr().cast::<u8>(),
+                    bytes.len(),
+                );
+            }
+            data
+        }
+
+        let len = bytes.len() / data_type.element_size();
+        if len * data_type.element_size() != bytes.len() {
+            return Err(ByteLengthError {
+                data_type,
+                length: bytes.len(),
+            });
+        }
+        Ok(match data_type {
+            TypedDataType::ByteData => TypedData::ByteData(bytes.into_boxed_slice()),
+            TypedDataType::Int8 => TypedData::Int8(elements(&bytes, len)),
+            TypedDataType::Uint8 => TypedData::Uint8(bytes),
+            TypedDataType::Uint8Clamped => TypedData::Uint8Clamped(bytes),
+            TypedDataType::Int16 => TypedData::Int16(elements(&bytes, len)),
+            TypedDataType::Uint16 => TypedData::Uint16(elements(&bytes, len)),
+            TypedDataType::Int32 => TypedData::Int32(elements(&bytes, len)),
+            TypedDataType::Uint32 => TypedData::Uint32(elements(&bytes, len)),
+            TypedDataType::Int64 => TypedData::Int64(elements(&bytes, len)),
+            TypedDataType::Uint64 => TypedData::Uint64(elements(&bytes, len)),
+            TypedDataType::Float32 => TypedData::Float32(elements(&bytes, len)),
+            TypedDataType::Float64 => TypedData::Float64(elements(&bytes, len)),
+            TypedDataType::Int32x4 => TypedData::Int32x4(elements(&bytes, len)),
+            TypedDataType::Float32x4 => TypedData::Float32x4(elements(&bytes, len)),
+            TypedDataType::Float64x2 => TypedData::Float64x2(elements(&bytes, len)),
+        })
+    }
+
+    /// Appends the elements of `other`.
+    ///
+    /// Returns `other` back if it's of a different data type.
+    pub(super) fn try_append(&mut self, other: TypedData) -> Result<(), TypedData> {
+        match (self, other) {
+            (TypedData::ByteData(data), TypedData::ByteData(other)) => {
+                let mut appended = Vec::from(std::mem::take(data));
+                appended.extend_from_slice(&other);
+                *data = appended.into();
+            }
+            (TypedData::Int8(data), TypedData::Int8(mut other)) => data.append(&mut other),
+            (TypedData::Uint8(data), TypedData::Uint8(mut other))
+            | (TypedData::Uint8Clamped(data), TypedData::Uint8Clamped(mut other)) => {
+                data.append(&mut other);
+            }
+            (TypedData::Int16(data), TypedData::Int16(mut other)) => data.append(&mut other),
+            (TypedData::Uint16(data), TypedData::Uint16(mut other)) => data.append(&mut other),
+            (TypedData::Int32(data), TypedData::Int32(mut other)) => data.append(&mut other),
+            (TypedData::Uint32(data), TypedData::Uint32(mut other)) => data.append(&mut other),
+            (TypedData::Int64(data), TypedData::Int64(mut other)) => data.append(&mut other),
+            (TypedData::Uint64(data), TypedData::Uint64(mut other)) => data.append(&mut other),
+            (TypedData::Float32(data), TypedData::Float32(mut other)) => data.append(&mut other),
+            (TypedData::Float64(data), TypedData::Float64(mut other)) => data.append(&mut other),
+            (TypedData::Int32x4(data), TypedData::Int32x4(mut other)) => data.append(&mut other),
+            (TypedData::Float32x4(data), TypedData::Float32x4(mut other)) => {
+                data.append(&mut other);
+            }
+            (TypedData::Float64x2(data), TypedData::Float64x2(mut other)) => {
+                data.append(&mut other);
+            }
+            (_, other) => return Err(other),
+        }
+        Ok(())
+    }
+
+    /// Returns the raw bytes, the number of elements and the alignment of the data.
+    #[cfg(feature = "allocator-api")]
+    pub(super) fn as_raw_parts(&self) -> (&[u8], usize, usize) {
+        fn bytes<T>(data: &[T]) -> (&[u8], usize, usize) {
+            use std::{
+                mem::{align_of, size_of_val},
+                slice::from_raw_parts,
+            };
+
+            // Safe: all element types are plain numbers or arrays of them
+            let bytes = unsafe { from_raw_parts(data.as_ptr().cast::<u8>(), size_of_val(data)) };
+            (bytes, data.len(), align_of::<T>())
+        }
+
+        fn simd((bytes, length, align): (&[u8], usize, usize)) -> (&[u8], usize, usize) {
+            (bytes, length, align.max(SIMD_ALIGNMENT))
+        }
+
+        match self {
+            TypedData::ByteData(data) => bytes(data),
+            TypedData::Int8(data) => bytes(data),
+            TypedData::Uint8(data) | TypedData::Uint8Clamped(data) => bytes(data),
+            TypedData::Int16(data) => bytes(data),
+            TypedData::Uint16(data) => bytes(data),
+            TypedData::Int32(data) => bytes(data),
+            TypedData::Uint32(data) => bytes(data),
+            TypedData::Int64(data) => bytes(data),
+            TypedData::Uint64(data) => bytes(data),
+            TypedData::Float32(data) => bytes(data),
+            TypedData::Float64(data) => bytes(data),
+            TypedData::Int32x4(data) => simd(bytes(data)),
+            TypedData::Float32x4(data) => simd(bytes(data)),
+            TypedData::Float64x2(data) => simd(bytes(data)),
+        }
+    }
+
+    /// Expands the data into an array of scalar [`CObject`]'s.
+    ///
+    /// Integers become `int64` objects and floats become `double` objects.
+    /// `Uint64` values are reinterpreted as `i64`, like dart's `Uint64List` does.
+    /// The elements of `Int32x4`, `Float32x4` and `Float64x2` are flattened
+    /// into their lanes.
+    ///
+    /// See [`CObjectMut::collect_scalar_array_as()`] for the reverse.
+    #[allow(clippy::cast_possible_wrap, clippy::vec_box)]
+    pub fn into_scalar_array(self) -> CObject {
+        fn ints<T: Copy + Into<i64>>(data: &[T]) -> Vec<Box<CObject>> {
+            data.iter()
+                .map(|int| Box::new(CObject::int64((*int).into())))
+                .collect()
+        }
+
+        fn doubles<T: Copy + Into<f64>>(data: &[T]) -> Vec<Box<CObject>> {
+            data.iter()
+                .map(|double| Box::new(CObject::double((*double).into())))
+                .collect()
+        }
+
+        CObject::array(match self {
+            TypedData::ByteData(data) => ints(&data),
+            TypedData::Int8(data) => ints(&data),
+            TypedData::Uint8(data) | TypedData::Uint8Clamped(data) => ints(&data),
+            TypedData::Int16(data) => ints(&data),
+            TypedData::Uint16(data) => ints(&data),
+            TypedData::Int32(data) => ints(&data),
+            TypedData::Uint32(data) => ints(&data),
+            TypedData::Int64(data) => ints(&data),
+            TypedData::Uint64(data) => data
+                .into_iter()
+                .map(|int| Box::new(CObject::int64(int as i64)))
+                .collect(),
+            TypedData::Float32(data) => doubles(&data),
+            TypedData::Float64(data) => doubles(&data),
+            TypedData::Int32x4(data) => ints(&data.concat()),
+            TypedData::Float32x4(data) => doubles(&data.concat()),
+            TypedData::Float64x2(data) => doubles(&data.concat()),
+        })
+    }
 }
 
 /// Hook to allow using custom external typed data.
@@ -219,18 +684,8 @@ unsafe impl CustomExternalTyped for TypedData {
             }
             TypedData::Int8(data) => data.into_external_typed_data(),
             TypedData::Uint8(data) => data.into_external_typed_data(),
-            TypedData::Uint8Clamped(mut data) => {
-                let ptr = data.as_mut_ptr().cast::<u8>();
-                let length = data.len().try_into().unwrap();
-                let peer = Box::into_raw(Box::new(data)).cast::<c_void>();
-
-                ExternalTypedData {
-                    type_: TypedDataType::ByteData.into(),
-                    length,
-                    data: ptr,
-                    peer,
-                    callback: Some(drop_boxed_peer::<Box<[u8]>>),
-                }
+            TypedData::Uint8Clamped(data) => {
+                vec_into_external_typed_data(data, TypedDataType::Uint8Clamped)
             }
             TypedData::Int16(data) => data.into_external_typed_data(),
             TypedData::Uint16(data) => data.into_external_typed_data(),
@@ -252,18 +707,14 @@ macro_rules! impl_custom_external_typed_data_for_vec {
         $($st:ty = $typed_data_variant:ident),* $(,)?
     }) => ($(
         unsafe impl CustomExternalTyped for Vec<$st> {
-            fn into_external_typed_data(mut self) -> ExternalTypedData {
-                let data = self.as_mut_ptr().cast::<u8>();
-                let length = self.len().try_into().unwrap();
-                let peer = Box::into_raw(Box::new(self)).cast::<c_void>();
+            fn into_external_typed_data(self) -> ExternalTypedData {
+                vec_into_external_typed_data(self, TypedDataType::$typed_data_variant)
+            }
+        }
 
-                ExternalTypedData {
-                    type_:  TypedDataType::$typed_data_variant.into(),
-                    length,
-                    data,
-                    peer,
-                    callback: Some(drop_boxed_peer::<Vec<$st>>),
-                }
+        unsafe impl CustomExternalTyped for SharedTypedData<Arc<Vec<$st>>> {
+            fn into_external_typed_data(self) -> ExternalTypedData {
+                shared_into_external_typed_data(self.0, TypedDataType::$typed_data_variant)
             }
         }
     )*);
@@ -281,12 +732,228 @@ impl_custom_external_typed_data_for_vec!(
         u64 = Uint64,
         f32 = Float32,
         f64 = Float64,
+    }
+);
+
+/// A reference counted buffer which is shared with dart instead of being copied.
+///
+/// It's sent as external typed data, see [`CObject::external_typed_data()`].
+/// The reference is dropped once dart finalized the received list, or when the
+/// object is dropped without being sent.
+///
+/// Implemented for `Arc<[u8]>` and `Arc<Vec<T>>` of the primitive element types.
+pub struct SharedTypedData<S>(S);
+
+impl<S> SharedTypedData<S> {
+    /// Wraps a buffer to share it with dart.
+    ///
+    /// # Safety
+    ///
+    /// Dart must treat the received list as read-only, e.g. by only using an
+    /// `asUnmodifiableView()` of it. Writing to it is a data race with reads
+    /// through other clones of the `Arc`.
+    pub unsafe fn new(shared: S) -> Self {
+        Self(shared)
+    }
+}
+
+unsafe impl CustomExternalTyped for SharedTypedData<Arc<[u8]>> {
+    fn into_external_typed_data(self) -> ExternalTypedData {
+        shared_into_external_typed_data(self.0, TypedDataType::Uint8)
+    }
+}
+
+/// The alignment dart expects for the elements of SIMD typed data.
+const SIMD_ALIGNMENT: usize = 16;
+
+/// A SIMD element with the alignment dart expects.
+///
+/// It has the same size as the wrapped `[_; N]` element, so a slice of it has the
+/// same layout as a slice of the elements.
+#[repr(C, align(16))]
+struct SimdAligned<T>(T);
+
+macro_rules! impl_custom_external_typed_data_for_simd_vec {
+    (unsafe impl for {
+        $($st:ty = $typed_data_variant:ident),* $(,)?
+    }) => ($(
+        unsafe impl CustomExternalTyped for Vec<$st> {
+            /// The data is copied into a new buffer if it isn't aligned to 16 bytes.
+            fn into_external_typed_data(self) -> ExternalTypedData {
+                let type_ = TypedDataType::$typed_data_variant;
+                if self.as_ptr() as usize % SIMD_ALIGNMENT == 0 {
+                    vec_into_external_typed_data(self, type_)
+                } else {
+                    let aligned = self.into_iter().map(SimdAligned).collect::<Vec<_>>();
+                    vec_into_external_typed_data(aligned, type_)
+                }
+            }
+        }
+    )*);
+}
+
+impl_custom_external_typed_data_for_simd_vec!(
+    unsafe impl for {
         [i32; 4] = Int32x4,
         [f32; 4] = Float32x4,
         [f64; 2] = Float64x2,
     }
 );
 
+fn vec_into_external_typed_data<T>(mut data: Vec<T>, type_: TypedDataType) -> ExternalTypedData {
+    let ptr = data.as_mut_ptr().cast::<u8>();
+    let length = data.len().try_into().unwrap();
+    let peer = Box::into_raw(Box::new(data)).cast::<c_void>();
+
+    ExternalTypedData {
+        type_: type_.into(),
+        length,
+        data: ptr,
+        peer,
+        callback: Some(drop_boxed_peer::<Vec<T>>),
+    }
+}
+
+/// Shares the data of a reference counted buffer, the reference is dropped by the finalizer.
+fn shared_into_external_typed_data<S, T>(shared: S, type_: TypedDataType) -> ExternalTypedData
+where
+    S: Deref,
+    S::Target: AsRef<[T]>,
+{
+    let data = (*shared).as_ref();
+    let length = data.len().try_into().unwrap();
+    // empty data must use a null pointer, see `prepare_dart_array_parts()`
+    let ptr = if data.is_empty() {
+        ptr::null_mut()
+    } else {
+        NonNull::from(data).as_ptr().cast::<u8>()
+    };
+    let peer = Box::into_raw(Box::new(shared)).cast::<c_void>();
+
+    ExternalTypedData {
+        type_: type_.into(),
+        length,
+        data: ptr,
+        peer,
+        callback: Some(drop_boxed_peer::<S>),
+    }
+}
+
 unsafe extern "C" fn drop_boxed_peer<T>(_data: *mut c_void, peer: *mut c_void) {
     drop(unsafe { Box::from_raw(peer.cast::<T>()) });
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::posted_messages, DartRuntime};
+
+    use super::*;
+
+    #[test]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn test_simd_external_typed_data_is_aligned() {
+        let lanes = [1.0, 2.0, 3.0, 4.0];
+        for data in [vec![lanes; 3], Vec::new()] {
+            let etd = data.clone().into_external_typed_data();
+            assert_eq!(etd.data as usize % SIMD_ALIGNMENT, 0);
+            let length = etd.length.try_into().unwrap();
+            let written =
+                unsafe { std::slice::from_raw_parts(etd.data.cast::<[f32; 4]>(), length) };
+            assert_eq!(written, data);
+            unsafe { etd.callback.unwrap()(etd.data.cast(), etd.peer) };
+        }
+    }
+
+    #[test]
+    fn test_shared_buffer_is_released_by_finalizer() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let bytes = Arc::<[u8]>::from(vec![1, 2, 3]);
+        let floats = Arc::new(vec![0.5_f64, -1.0]);
+
+        let mut received = posted_messages(|port| {
+            // Safe: the received lists are only read
+            let mut obj =
+                CObject::external_typed_data(unsafe { SharedTypedData::new(bytes.clone()) });
+            assert_eq!(obj.as_mut().raw_typed_data_bytes(rt), Some(&bytes[..]));
+            assert_eq!(Arc::strong_count(&bytes), 2);
+            port.post_cobject(obj).unwrap();
+            port.post_cobject(CObject::external_typed_data(unsafe {
+                SharedTypedData::new(floats.clone())
+            }))
+            .unwrap();
+        });
+        assert_eq!(Arc::strong_count(&bytes), 1);
+        assert_eq!(Arc::strong_count(&floats), 1);
+
+        assert_eq!(received.len(), 2);
+        assert!(matches!(
+            received[0].as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8([1, 2, 3])), _))
+        ));
+        match received[1].as_mut().as_typed_data(rt) {
+            Some((Ok(TypedDataRef::Float64(data)), _)) => assert_eq!(data, &floats[..]),
+            _ => panic!("expected float64 typed data"),
+        }
+    }
+
+    #[test]
+    fn test_copy_into() {
+        let data = [1.5_f32, -2.0, 0.25];
+        let data = TypedDataRef::Float32(&data);
+        let mut buffer = [0_u8; 16];
+        assert_eq!(data.copy_into(&mut buffer).unwrap(), 12);
+        assert_eq!(buffer[..12], *data.as_bytes());
+        assert_eq!(buffer[12..], [0; 4]);
+
+        let mut buffer = [0_u8; 11];
+        let err = data.copy_into(&mut buffer).unwrap_err();
+        assert_eq!((err.required, err.available), (12, 11));
+        assert_eq!(buffer, [0; 11]);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let data = [[1.5, -2.0], [f64::MAX, 0.0]];
+        let bytes = TypedDataRef::Float64x2(&data).as_bytes();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[..8], 1.5_f64.to_ne_bytes());
+
+        let typed = TypedData::from_bytes(TypedDataType::Float64x2, bytes.to_vec()).unwrap();
+        assert!(matches!(typed, TypedData::Float64x2(ref elements) if elements[..] == data));
+        assert!(TypedData::from_bytes(TypedDataType::Int32, vec![0; 6]).is_err());
+    }
+
+    #[test]
+    fn test_owned_and_ref_round_trip() {
+        let data = TypedData::Float64(vec![1.0, -0.5]);
+        let owned = data.as_ref().into_owned();
+        assert!(matches!(owned, TypedData::Float64(ref values) if values[..] == [1.0, -0.5]));
+        assert!(matches!(owned.as_ref(), TypedDataRef::Float64(&[_, _])));
+    }
+
+    #[test]
+    fn test_chunk_typed_data() {
+        let data = (0..10_u16).map(f32::from).collect::<Vec<_>>();
+        let chunks = TypedDataRef::Float32(&data).chunks(4).collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        for (chunk, expected) in chunks.into_iter().zip(data.chunks(4)) {
+            assert!(matches!(chunk, TypedDataRef::Float32(chunk) if chunk == expected));
+        }
+        assert_eq!(TypedDataRef::Float32(&[]).chunks(4).count(), 0);
+    }
+
+    #[test]
+    fn test_uint8_clamped_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = CObject::typed_data(TypedData::Uint8Clamped(vec![0, 128, 255]));
+        for _ in 0..2 {
+            let view = obj.as_mut();
+            let data = view.as_typed_data(rt).unwrap().0.unwrap();
+            assert_eq!(data.data_type(), TypedDataType::Uint8Clamped);
+            assert!(matches!(data, TypedDataRef::Uint8Clamped(&[0, 128, 255])));
+            let copy = data.into_owned();
+            assert!(matches!(copy, TypedData::Uint8Clamped(_)));
+            obj = CObject::typed_data(copy);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{ser, Serialize, Serializer};
+
+use crate::ReadOnlyRuntime;
+
+use super::{CObjectMut, CObjectValuesRef, TypedDataRef};
+
+/// How typed data is serialized, see [`SerializationConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedDataFormat {
+    /// As sequence of the elements, e.g. a JSON array of numbers.
+    Elements,
+    /// As string of the standard base64 encoding (with padding) of the native endian bytes.
+    Base64,
+}
+
+/// Configures how [`CObjectMut`], [`CObjectValuesRef`] and [`TypedDataRef`] are serialized.
+///
+/// Their [`Serialize`] implementations use the default config, use
+/// [`SerializationConfig::wrap()`] to serialize them with a different one:
+///
+/// - null is serialized as unit
+/// - bools, ints, doubles and strings are serialized as scalars
+/// - arrays are serialized as sequences
+/// - typed data is serialized as configured by [`SerializationConfig::typed_data`]
+///
+/// Send ports, capabilities and unknown types can't be serialized. An owned
+/// [`CObject`](super::CObject) is serialized through [`CObject::as_mut()`](super::CObject::as_mut()).
+///
+/// ```
+/// # use xayn_dart_api_dl::cobject::{CObjectMut, SerializationConfig, TypedDataFormat};
+/// fn to_json(msg: &CObjectMut<'_>) -> serde_json::Result<String> {
+///     let config = SerializationConfig {
+///         typed_data: TypedDataFormat::Base64,
+///     };
+///     serde_json::to_string(&config.wrap(msg))
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerializationConfig {
+    /// How typed data is serialized, defaults to [`TypedDataFormat::Elements`].
+    pub typed_data: TypedDataFormat,
+}
+
+impl Default for SerializationConfig {
+    fn default() -> Self {
+        Self {
+            typed_data: TypedDataFormat::Elements,
+        }
+    }
+}
+
+impl SerializationConfig {
+    /// Wraps the value so that it's serialized with this config.
+    pub fn wrap<T>(self, value: &T) -> WithConfig<'_, T>
+    where
+        T: SerializeWithConfig + ?Sized,
+    {
+        WithConfig {
+            value,
+            config: self,
+        }
+    }
+}
+
+/// A value which is serialized with a [`SerializationConfig`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithConfig<'a, T: ?Sized> {
+    value: &'a T,
+    config: SerializationConfig,
+}
+
+impl<T> Serialize for WithConfig<'_, T>
+where
+    T: SerializeWithConfig + ?Sized,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize_with_config(self.config, serializer)
+    }
+}
+
+/// Values which can be serialized with a [`SerializationConfig`].
+pub trait SerializeWithConfig {
+    /// Serializes the value with the config.
+    ///
+    /// # Errors
+    ///
+    /// If the value (or an element of it) can't be serialized.
+    fn serialize_with_config<S>(
+        &self,
+        config: SerializationConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer;
+}
+
+impl SerializeWithConfig for CObjectMut<'_> {
+    fn serialize_with_config<S>(
+        &self,
+        config: SerializationConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let rt = ReadOnlyRuntime::new();
+        self.read_value_ref(rt)
+            .map_err(ser::Error::custom)?
+            .serialize_with_config(config, serializer)
+    }
+}
+
+impl SerializeWithConfig for CObjectValuesRef<'_> {
+    fn serialize_with_config<S>(
+        &self,
+        config: SerializationConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            CObjectValuesRef::Null => serializer.serialize_unit(),
+            CObjectValuesRef::Bool(value) => serializer.serialize_bool(*value),
+            CObjectValuesRef::Int32(value) => serializer.serialize_i32(*value),
+            CObjectValuesRef::Int64(value) => serializer.serialize_i64(*value),
+            CObjectValuesRef::Double(value) => serializer.serialize_f64(*value),
+            CObjectValuesRef::String(value) => serializer.serialize_str(value),
+            CObjectValuesRef::Array(array) => {
+                serializer.collect_seq(array.iter().map(|element| config.wrap(element)))
+            }
+            CObjectValuesRef::TypedData { data: Ok(data), .. } => {
+                data.serialize_with_config(config, serializer)
+            }
+            CObjectValuesRef::TypedData {
+                data: Err(error), ..
+            } => Err(ser::Error::custom(error)),
+            CObjectValuesRef::SendPort(_) => Err(ser::Error::custom("Can't serialize send ports.")),
+            CObjectValuesRef::Capability(_) => {
+                Err(ser::Error::custom("Can't serialize capabilities."))
+            }
+        }
+    }
+}
+
+impl SerializeWithConfig for TypedDataRef<'_> {
+    fn serialize_with_config<S>(
+        &self,
+        config: SerializationConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if config.typed_data == TypedDataFormat::Base64 {
+            return serializer.serialize_str(&base64(self.as_bytes()));
+        }
+        match self {
+            TypedDataRef::ByteData(data)
+            | TypedDataRef::Uint8(data)
+            | TypedDataRef::Uint8Clamped(data) => serializer.collect_seq(*data),
+            TypedDataRef::Int8(data) => serializer.collect_seq(*data),
+            TypedDataRef::Int16(data) => serializer.collect_seq(*data),
+            TypedDataRef::Uint16(data) => serializer.collect_seq(*data),
+            TypedDataRef::Int32(data) => serializer.collect_seq(*data),
+            TypedDataRef::Uint32(data) => serializer.collect_seq(*data),
+            TypedDataRef::Int64(data) => serializer.collect_seq(*data),
+            TypedDataRef::Uint64(data) => serializer.collect_seq(*data),
+            TypedDataRef::Float32(data) => serializer.collect_seq(*data),
+            TypedDataRef::Float64(data) => serializer.collect_seq(*data),
+            TypedDataRef::Int32x4(data) => serializer.collect_seq(*data),
+            TypedDataRef::Float32x4(data) => serializer.collect_seq(*data),
+            TypedDataRef::Float64x2(data) => serializer.collect_seq(*data),
+        }
+    }
+}
+
+macro_rules! impl_serialize {
+    ($($t:ty),*) => {$(
+        impl Serialize for $t {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                self.serialize_with_config(SerializationConfig::default(), serializer)
+            }
+        }
+    )*};
+}
+
+impl_serialize!(CObjectMut<'_>, CObjectValuesRef<'_>, TypedDataRef<'_>);
+
+/// Encodes the bytes as standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.chunks(3).len() * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0_u32, |group, (idx, byte)| {
+            group | u32::from(*byte) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                let sextet = (group >> (18 - 6 * idx)) & 0x3f;
+                encoded.push(char::from(ALPHABET[sextet as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use dart_api_dl_sys::ILLEGAL_PORT;
+
+    use crate::cobject::{CObject, TypedData};
+
+    use super::*;
+
+    fn message() -> CObject {
+        CObject::array(vec![
+            Box::new(CObject::null()),
+            Box::new(CObject::bool(true)),
+            Box::new(CObject::int32(-7)),
+            Box::new(CObject::int64(1 << 40)),
+            Box::new(CObject::double(1.5)),
+            Box::new(CObject::string("hy \"there\"").unwrap()),
+            Box::new(CObject::typed_data(TypedData::Uint8(vec![1, 2, 3, 4]))),
+            Box::new(CObject::external_typed_data(TypedData::Float32(vec![
+                0.5, -2.0,
+            ]))),
+        ])
+    }
+
+    #[test]
+    fn test_serialize_json() {
+        let mut msg = message();
+        assert_eq!(
+            serde_json::to_string(&msg.as_mut()).unwrap(),
+            r#"[null,true,-7,1099511627776,1.5,"hy \"there\"",[1,2,3,4],[0.5,-2.0]]"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_json_base64() {
+        let mut msg = message();
+        let config = SerializationConfig {
+            typed_data: TypedDataFormat::Base64,
+        };
+        let json = serde_json::to_value(&config.wrap(&msg.as_mut())).unwrap();
+        assert_eq!(json[6], "AQIDBA==");
+        let mut float32 = Vec::new();
+        float32.extend(0.5_f32.to_ne_bytes());
+        float32.extend((-2.0_f32).to_ne_bytes());
+        assert_eq!(json[7], base64(&float32));
+    }
+
+    #[test]
+    fn test_base64() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\xff\xfe\xfd", "//79"),
+        ] {
+            assert_eq!(base64(bytes), encoded);
+        }
+    }
+
+    #[test]
+    fn test_serialize_unsupported() {
+        let mut port = CObject::array(vec![Box::new(CObject::raw_send_port(
+            ILLEGAL_PORT,
+            ILLEGAL_PORT,
+        ))]);
+        assert!(serde_json::to_string(&port.as_mut()).is_err());
+        let mut capability = CObject::capability(1);
+        assert!(serde_json::to_string(&capability.as_mut()).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{utils::quietly, DartRuntime};
+
+use super::{CObject, CObjectMut};
+
+/// Types which are represented as tagged union in dart.
+///
+/// A tagged union is send as an array of the form `[tag, ...fields]`,
+/// which is how sealed class hierarchies are commonly mapped on the
+/// dart side.
+///
+/// Any type implementing this trait can be converted into a [`CObject`]
+/// with [`TaggedCObject::into_cobject()`].
+///
+/// # Example
+///
+/// ```
+/// use xayn_dart_api_dl::cobject::{CObject, TaggedCObject};
+///
+/// enum Shape {
+///     Circle { radius: f64 },
+///     Rect { width: f64, height: f64 },
+/// }
+///
+/// impl TaggedCObject for Shape {
+///     fn tag(&self) -> i64 {
+///         match self {
+///             Shape::Circle { .. } => 0,
+///             Shape::Rect { .. } => 1,
+///         }
+///     }
+///
+///     fn encode_fields(self) -> Vec<CObject> {
+///         match self {
+///             Shape::Circle { radius } => vec![CObject::double(radius)],
+///             Shape::Rect { width, height } => {
+///                 vec![CObject::double(width), CObject::double(height)]
+///             }
+///         }
+///     }
+/// }
+///
+/// let obj = Shape::Circle { radius: 1.0 }.into_cobject();
+/// # drop(obj);
+/// ```
+pub trait TaggedCObject {
+    /// The tag identifying the variant.
+    fn tag(&self) -> i64;
+
+    /// Encodes the fields of the variant, excluding the tag.
+    fn encode_fields(self) -> Vec<CObject>;
+
+    /// Encodes the value as `[tag, ...fields]` array.
+    fn into_cobject(self) -> CObject
+    where
+        Self: Sized,
+    {
+        let tag = self.tag();
+        let mut array = vec![Box::new(CObject::int64(tag))];
+        array.extend(self.encode_fields().into_iter().map(Box::new));
+        CObject::array(array)
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns the tag and fields if this object is a tagged union.
+    ///
+    /// I.e. if this is an array with at least one element, of which the
+    /// first is an int.
+    ///
+    /// See [`TaggedCObject`].
+    pub fn as_tagged(&self, rt: DartRuntime) -> Option<(i64, &[CObjectMut<'_>])> {
+        quietly(|| {
+            let (tag, fields) = self.as_array(rt)?.split_first()?;
+            Some((tag.as_int(rt)?, fields))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Message {
+        Ping,
+        Resize { width: i64, height: i64 },
+    }
+
+    impl TaggedCObject for Message {
+        fn tag(&self) -> i64 {
+            match self {
+                Message::Ping => 0,
+                Message::Resize { .. } => 1,
+            }
+        }
+
+        fn encode_fields(self) -> Vec<CObject> {
+            match self {
+                Message::Ping => Vec::new(),
+                Message::Resize { width, height } => {
+                    vec![CObject::int64(width), CObject::int64(height)]
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tagged_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+
+        let mut ping = Message::Ping.into_cobject();
+        let ping = ping.as_mut();
+        let (tag, fields) = ping.as_tagged(rt).unwrap();
+        assert_eq!(tag, 0);
+        assert!(fields.is_empty());
+
+        let mut resize = Message::Resize {
+            width: 640,
+            height: 480,
+        }
+        .into_cobject();
+        let resize = resize.as_mut();
+        let (tag, fields) = resize.as_tagged(rt).unwrap();
+        assert_eq!(tag, 1);
+        let fields = fields
+            .iter()
+            .map(|field| field.as_int(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, [640, 480]);
+    }
+
+    #[test]
+    fn test_as_tagged_requires_leading_int() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        assert!(CObject::array(Vec::new()).as_mut().as_tagged(rt).is_none());
+        assert!(CObject::array(vec![Box::new(CObject::null())])
+            .as_mut()
+            .as_tagged(rt)
+            .is_none());
+        assert!(CObject::int64(0).as_mut().as_tagged(rt).is_none());
+    }
+}
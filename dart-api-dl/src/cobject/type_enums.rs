@@ -100,6 +100,22 @@ impl_from_for_pseudo_enums! {
     }
 }
 
+impl TypedDataType {
+    /// Returns the size of a single element in bytes.
+    pub fn element_size(self) -> usize {
+        match self {
+            TypedDataType::ByteData
+            | TypedDataType::Int8
+            | TypedDataType::Uint8
+            | TypedDataType::Uint8Clamped => 1,
+            TypedDataType::Int16 | TypedDataType::Uint16 => 2,
+            TypedDataType::Int32 | TypedDataType::Uint32 | TypedDataType::Float32 => 4,
+            TypedDataType::Int64 | TypedDataType::Uint64 | TypedDataType::Float64 => 8,
+            TypedDataType::Int32x4 | TypedDataType::Float32x4 | TypedDataType::Float64x2 => 16,
+        }
+    }
+}
+
 /// The [`CObjectType`] isn't known/supported by this library.
 ///
 /// There are a few cases where a type is not supported:
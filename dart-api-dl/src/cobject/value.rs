@@ -0,0 +1,181 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::NulError;
+
+use dart_api_dl_sys::ILLEGAL_PORT;
+
+use crate::{ports::SendPort, DartRuntime};
+
+use super::{CObject, CObjectMut, CObjectValuesRef, Capability, TypedData, UnknownCObjectType};
+
+/// An owned copy of a [`CObjectMut`] which is independent of dart's memory.
+///
+/// Unlike [`CObject`] it's `Send` and can be pattern matched.
+#[derive(Debug, Clone)]
+pub enum DartValue {
+    /// Null.
+    Null,
+    /// A bool.
+    Bool(bool),
+    /// An int, both 32bit and 64bit ints are read as this.
+    Int(i64),
+    /// A double.
+    Double(f64),
+    /// A string.
+    Str(String),
+    /// An array.
+    List(Vec<DartValue>),
+    /// The raw (native endian) bytes of typed data of any type.
+    Bytes(Vec<u8>),
+    /// A send port, `None` if it's the `ILLEGAL_PORT`.
+    SendPort(Option<SendPort>),
+    /// A capability.
+    Capability(Capability),
+}
+
+impl PartialEq for DartValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DartValue::Null, DartValue::Null) => true,
+            (DartValue::Bool(a), DartValue::Bool(b)) => a == b,
+            (DartValue::Int(a), DartValue::Int(b))
+            | (DartValue::Capability(a), DartValue::Capability(b)) => a == b,
+            (DartValue::Double(a), DartValue::Double(b)) => a == b,
+            (DartValue::Str(a), DartValue::Str(b)) => a == b,
+            (DartValue::List(a), DartValue::List(b)) => a == b,
+            (DartValue::Bytes(a), DartValue::Bytes(b)) => a == b,
+            (DartValue::SendPort(a), DartValue::SendPort(b)) => {
+                a.map(|port| port.as_raw()) == b.map(|port| port.as_raw())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl DartValue {
+    /// Creates a [`CObject`] representing this value.
+    ///
+    /// Ints become `Int64` objects and bytes become `Uint8` typed data.
+    ///
+    /// # Errors
+    ///
+    /// If a string contains a nul byte.
+    pub fn into_cobject(self) -> Result<CObject, NulError> {
+        Ok(match self {
+            DartValue::Null => CObject::null(),
+            DartValue::Bool(value) => CObject::bool(value),
+            DartValue::Int(value) => CObject::int64(value),
+            DartValue::Double(value) => CObject::double(value),
+            DartValue::Str(value) => CObject::string(value)?,
+            DartValue::List(values) => CObject::array(
+                values
+                    .into_iter()
+                    .map(|value| value.into_cobject().map(Box::new))
+                    .collect::<Result<_, _>>()?,
+            ),
+            DartValue::Bytes(bytes) => CObject::typed_data(TypedData::Uint8(bytes)),
+            DartValue::SendPort(Some(port)) => CObject::send_port(port),
+            DartValue::SendPort(None) => CObject::raw_send_port(ILLEGAL_PORT, ILLEGAL_PORT),
+            DartValue::Capability(id) => CObject::capability(id),
+        })
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Copies the object into an owned [`DartValue`].
+    ///
+    /// # Errors
+    ///
+    /// If the object is (or contains objects) of an unknown type, including
+    /// typed data with an unknown element type.
+    pub fn to_value(&self, rt: DartRuntime) -> Result<DartValue, UnknownCObjectType> {
+        Ok(match self.value_ref(rt)? {
+            CObjectValuesRef::Null => DartValue::Null,
+            CObjectValuesRef::Bool(value) => DartValue::Bool(value),
+            CObjectValuesRef::Int32(value) => DartValue::Int(value.into()),
+            CObjectValuesRef::Int64(value) => DartValue::Int(value),
+            CObjectValuesRef::Double(value) => DartValue::Double(value),
+            CObjectValuesRef::String(value) => DartValue::Str(value.to_owned()),
+            CObjectValuesRef::Array(array) => DartValue::List(
+                array
+                    .iter()
+                    .map(|element| element.to_value(rt))
+                    .collect::<Result<_, _>>()?,
+            ),
+            CObjectValuesRef::TypedData { data, .. } => DartValue::Bytes(
+                data.map_err(|_| UnknownCObjectType(self.partial_mut.type_))?
+                    .as_bytes()
+                    .to_vec(),
+            ),
+            CObjectValuesRef::SendPort(port) => DartValue::SendPort(port),
+            CObjectValuesRef::Capability(id) => DartValue::Capability(id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use static_assertions::assert_impl_all;
+
+    use super::*;
+
+    fn round_trip(value: DartValue) {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut obj = value.clone().into_cobject().unwrap();
+        assert_eq!(obj.as_mut().to_value(rt), Ok(value));
+    }
+
+    #[test]
+    fn test_value_round_trips() {
+        assert_impl_all!(DartValue: Send);
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        round_trip(DartValue::Null);
+        round_trip(DartValue::Bool(true));
+        round_trip(DartValue::Int(-7));
+        round_trip(DartValue::Double(0.5));
+        round_trip(DartValue::Str("hy".into()));
+        round_trip(DartValue::Bytes(vec![1, 2, 3]));
+        round_trip(DartValue::SendPort(Some(
+            rt.send_port_from_raw(12).unwrap(),
+        )));
+        round_trip(DartValue::SendPort(None));
+        round_trip(DartValue::Capability(42));
+        round_trip(DartValue::List(vec![
+            DartValue::Int(1),
+            DartValue::List(vec![DartValue::Str("nested".into()), DartValue::Null]),
+            DartValue::List(Vec::new()),
+        ]));
+    }
+
+    #[test]
+    fn test_strings_with_nul_bytes_are_rejected() {
+        assert!(DartValue::Str("h\0y".into()).into_cobject().is_err());
+        assert!(
+            DartValue::List(vec![DartValue::Null, DartValue::Str("\0".into())])
+                .into_cobject()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_to_value_widens_ints() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        assert_eq!(
+            CObject::int32(3).as_mut().to_value(rt),
+            Ok(DartValue::Int(3))
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Messages carrying the version of their schema.
+
+use crate::{utils::quietly, DartRuntime};
+
+use super::{CObject, CObjectMut};
+
+impl CObject {
+    /// Create a [`CObject`] of the form `[version, payload]`.
+    ///
+    /// This allows the receiver to dispatch on the version of the payload's schema,
+    /// e.g. to adapt or reject messages of an older or newer version of the protocol.
+    ///
+    /// See [`CObjectMut::as_versioned()`].
+    pub fn versioned(version: u32, payload: CObject) -> Self {
+        CObject::array(vec![
+            Box::new(CObject::int64(version.into())),
+            Box::new(payload),
+        ])
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns the version and payload if the object is a versioned message.
+    ///
+    /// I.e. if this is an array of two elements, of which the first is an
+    /// int in the `u32` range.
+    ///
+    /// See [`CObject::versioned()`].
+    pub fn as_versioned(&self, rt: DartRuntime) -> Option<(u32, &CObjectMut<'_>)> {
+        quietly(|| match self.as_array(rt)? {
+            [version, payload] => Some((version.as_int(rt)?.try_into().ok()?, payload)),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versioned_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut message = CObject::versioned(2, CObject::string("payload").unwrap());
+        let message = message.as_mut();
+        let (version, payload) = message.as_versioned(rt).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(payload.as_string(rt), Some("payload"));
+    }
+
+    #[test]
+    fn test_as_versioned_requires_version() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for mut message in [
+            CObject::int64(2),
+            CObject::array(vec![Box::new(CObject::int64(2))]),
+            CObject::array(vec![
+                Box::new(CObject::int64(-1)),
+                Box::new(CObject::null()),
+            ]),
+            CObject::array(vec![
+                Box::new(CObject::string("2").unwrap()),
+                Box::new(CObject::null()),
+            ]),
+        ] {
+            assert!(message.as_mut().as_versioned(rt).is_none());
+        }
+    }
+}
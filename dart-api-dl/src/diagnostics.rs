@@ -0,0 +1,38 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Diagnostics to surface protocol bugs during development.
+//!
+//! All external typed data created by this library is tracked in debug builds,
+//! see [`set_external_typed_data_sink()`], and violations of the documented
+//! behavior of the dart VM are reported, see [`set_contract_violation_sink()`].
+//! Messages which are dropped without being handled, e.g. by a
+//! [`RateLimitedHandler`](crate::ports::RateLimitedHandler), are reported too,
+//! see [`set_dropped_message_sink()`].
+//!
+//! With the `trace-coercions` feature the `as_*` readers of
+//! [`CObjectMut`](crate::cobject::CObjectMut) additionally report each read
+//! which fails due to a type mismatch, see `set_diagnostic_sink()`.
+//!
+//! By default all sinks log through the [`log`] facade.
+
+#[cfg(feature = "trace-coercions")]
+pub(crate) mod coercion;
+mod contract_violation;
+mod dropped_message;
+mod external_typed_data;
+
+#[cfg(feature = "trace-coercions")]
+pub use self::coercion::{set_diagnostic_sink, CoercionTrace, DiagnosticSink};
+pub use self::{contract_violation::*, dropped_message::*, external_typed_data::*};
@@ -0,0 +1,136 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracing of failed coercions, see [`set_diagnostic_sink()`].
+
+use std::{
+    cell::Cell,
+    fmt::{self, Display},
+    panic::Location,
+    sync::{PoisonError, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::cobject::CObjectType;
+
+/// Receives the diagnostic traces, see [`set_diagnostic_sink()`].
+pub type DiagnosticSink = fn(&CoercionTrace);
+
+static SINK: Lazy<RwLock<DiagnosticSink>> = Lazy::new(|| RwLock::new(log_trace));
+
+thread_local! {
+    /// Number of active [`quietly()`] calls on this thread.
+    static QUIET: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A read of a [`CObjectMut`](crate::cobject::CObjectMut) failed due to a type mismatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoercionTrace {
+    /// The type the reader expected.
+    pub expected: &'static str,
+    /// The found type, `None` if it's not known by this library.
+    pub found: Option<CObjectType>,
+    /// The location of the call to the reader.
+    pub location: &'static Location<'static>,
+}
+
+impl Display for CoercionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got ", self.expected)?;
+        if let Some(found) = self.found {
+            write!(f, "{found:?}")?;
+        } else {
+            write!(f, "an unknown type")?;
+        }
+        write!(f, " at {}", self.location)
+    }
+}
+
+/// Replaces the sink which receives all traces.
+///
+/// By default traces are logged as warnings.
+pub fn set_diagnostic_sink(sink: DiagnosticSink) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+fn log_trace(trace: &CoercionTrace) {
+    log::warn!("failed coercion: {trace}");
+}
+
+/// Reports a failed coercion to the sink, unless it happens inside of [`quietly()`].
+pub(crate) fn trace_coercion(
+    expected: &'static str,
+    found: Option<CObjectType>,
+    location: &'static Location<'static>,
+) {
+    if QUIET.with(Cell::get) == 0 {
+        let sink = *SINK.read().unwrap_or_else(PoisonError::into_inner);
+        sink(&CoercionTrace {
+            expected,
+            found,
+            location,
+        });
+    }
+}
+
+/// Runs `f` without tracing failed coercions.
+///
+/// Used where the library only probes the type of an object.
+pub(crate) fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    QUIET.with(|quiet| quiet.set(quiet.get() + 1));
+    let res = f();
+    QUIET.with(|quiet| quiet.set(quiet.get() - 1));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::{cobject::CObject, DartRuntime};
+
+    use super::*;
+
+    thread_local! {
+        static TRACES: RefCell<Vec<CoercionTrace>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(trace: &CoercionTrace) {
+        TRACES.with(|traces| traces.borrow_mut().push(*trace));
+    }
+
+    #[test]
+    fn test_failed_coercion_emits_trace() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        set_diagnostic_sink(record);
+
+        let mut obj = CObject::string("5").unwrap();
+        let obj = obj.as_mut();
+        assert_eq!(obj.as_string(rt), Some("5"));
+        assert_eq!(obj.as_int64(rt), None);
+        let line = line!() - 1;
+        assert_eq!(quietly(|| obj.as_bool(rt)), None);
+
+        let traces = TRACES.with(RefCell::take);
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].expected, "Int64");
+        assert_eq!(traces[0].found, Some(CObjectType::String));
+        assert_eq!(traces[0].location.file(), file!());
+        assert_eq!(traces[0].location.line(), line);
+        assert!(traces[0]
+            .to_string()
+            .starts_with("expected Int64, got String at "));
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fmt::{self, Display},
+    sync::{PoisonError, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::ports::DartPortId;
+
+/// Receives the contract violations, see [`set_contract_violation_sink()`].
+pub type ContractViolationSink = fn(ContractViolation);
+
+static SINK: Lazy<RwLock<ContractViolationSink>> = Lazy::new(|| RwLock::new(log_violation));
+
+/// The dart VM didn't behave as documented.
+///
+/// The library handles such cases defensively, e.g. by ignoring the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContractViolation {
+    /// A native message handler was called with a null pointer as message.
+    NullMessage {
+        /// The port which received the message.
+        port: DartPortId,
+    },
+}
+
+impl Display for ContractViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContractViolation::NullMessage { port } => {
+                write!(f, "port {port} received a null pointer as message")
+            }
+        }
+    }
+}
+
+/// Replaces the sink which receives all contract violations.
+///
+/// By default violations are logged as warnings.
+pub fn set_contract_violation_sink(sink: ContractViolationSink) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+fn log_violation(violation: ContractViolation) {
+    log::warn!("dart api contract violated: {violation}");
+}
+
+/// Reports a contract violation to the sink.
+pub(crate) fn report_contract_violation(violation: ContractViolation) {
+    let sink = *SINK.read().unwrap_or_else(PoisonError::into_inner);
+    sink(violation);
+}
@@ -0,0 +1,81 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fmt::{self, Display},
+    sync::{PoisonError, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::ports::DartPortId;
+
+/// Receives the dropped messages, see [`set_dropped_message_sink()`].
+pub type DroppedMessageSink = fn(DroppedMessage);
+
+static SINK: Lazy<RwLock<DroppedMessageSink>> = Lazy::new(|| RwLock::new(log_dropped));
+
+/// A message was dropped without being handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DroppedMessage {
+    /// The port which received the message.
+    pub port: DartPortId,
+    /// The name of the handler of the port.
+    pub handler: &'static str,
+    /// Why the message was dropped.
+    pub reason: DropReason,
+}
+
+/// The reason a message was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// The message exceeded the [`RateLimit`](crate::ports::RateLimit) of the handler.
+    RateLimited,
+}
+
+impl Display for DroppedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            DropReason::RateLimited => "the rate limit was exceeded",
+        };
+        write!(
+            f,
+            "port {} ({}) dropped a message, {reason}",
+            self.port, self.handler
+        )
+    }
+}
+
+/// Replaces the sink which receives all dropped messages.
+///
+/// By default dropped messages are logged as warnings.
+pub fn set_dropped_message_sink(sink: DroppedMessageSink) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+fn log_dropped(dropped: DroppedMessage) {
+    log::warn!("{dropped}");
+}
+
+/// Reports a dropped message to the sink.
+pub(crate) fn report_dropped_message(port: DartPortId, handler: &'static str, reason: DropReason) {
+    let sink = *SINK.read().unwrap_or_else(PoisonError::into_inner);
+    sink(DroppedMessage {
+        port,
+        handler,
+        reason,
+    });
+}
@@ -0,0 +1,223 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    panic::catch_unwind,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+        PoisonError,
+        RwLock,
+    },
+};
+
+use dart_api_dl_sys::Dart_HandleFinalizer;
+use once_cell::sync::Lazy;
+
+use crate::cobject::ExternalTypedData;
+
+/// Receives the external typed data events, see [`set_external_typed_data_sink()`].
+pub type ExternalTypedDataSink = fn(&ExternalTypedDataEvent);
+
+static SINK: Lazy<RwLock<ExternalTypedDataSink>> = Lazy::new(|| RwLock::new(log_double_finalize));
+
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(1);
+
+/// Number of shards of [`LIVE`].
+const SHARDS: usize = 32;
+
+/// The original finalizer and peer of all not yet finalized external typed data, by token.
+///
+/// The table is sharded by token so that finalizers running on different threads
+/// rarely contend on the same lock. Tokens are never reused, so a token missing
+/// from the table was finalized before.
+static LIVE: Lazy<[Mutex<HashMap<usize, Original>>; SHARDS]> = Lazy::new(Default::default);
+
+/// The original finalizer and peer of tracked external typed data.
+struct Original {
+    callback: Dart_HandleFinalizer,
+    peer: *mut c_void,
+}
+
+// Safe: the peer is only passed to the finalizer, which might run on any thread anyway
+unsafe impl Send for Original {}
+
+fn live_shard(token: usize) -> &'static Mutex<HashMap<usize, Original>> {
+    &LIVE[token % SHARDS]
+}
+
+/// Something happened to an external typed data created by this library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalTypedDataEvent {
+    /// The unique token of the external typed data.
+    pub token: usize,
+    /// What happened.
+    pub kind: ExternalTypedDataEventKind,
+}
+
+/// See [`ExternalTypedDataEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalTypedDataEventKind {
+    /// The external typed data was created.
+    Created,
+    /// The finalizer of the external typed data ran.
+    Finalized,
+    /// The finalizer ran again, which would have been a double free.
+    ///
+    /// The original finalizer isn't called again.
+    DoubleFinalized,
+}
+
+/// Replaces the sink which receives all external typed data events.
+///
+/// By default only [`ExternalTypedDataEventKind::DoubleFinalized`] is logged as error.
+///
+/// External typed data is only tracked in debug builds, in release builds the
+/// sink doesn't receive any events.
+pub fn set_external_typed_data_sink(sink: ExternalTypedDataSink) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+fn log_double_finalize(event: &ExternalTypedDataEvent) {
+    if event.kind == ExternalTypedDataEventKind::DoubleFinalized {
+        log::error!("external typed data {} finalized twice", event.token);
+    }
+}
+
+fn report(token: usize, kind: ExternalTypedDataEventKind) {
+    let sink = *SINK.read().unwrap_or_else(PoisonError::into_inner);
+    sink(&ExternalTypedDataEvent { token, kind });
+}
+
+/// Replaces the peer of the external typed data with a unique token.
+///
+/// The original finalizer is called through a wrapper which reports each run
+/// and doesn't run it a second time.
+///
+/// In release builds the data is returned unchanged.
+pub(crate) fn track_external_typed_data(mut etd: ExternalTypedData) -> ExternalTypedData {
+    if cfg!(not(debug_assertions)) {
+        return etd;
+    }
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    live_shard(token)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            token,
+            Original {
+                callback: etd.callback,
+                peer: etd.peer,
+            },
+        );
+    etd.peer = token as *mut c_void;
+    etd.callback = Some(finalize_tracked);
+    report(token, ExternalTypedDataEventKind::Created);
+    etd
+}
+
+unsafe extern "C" fn finalize_tracked(data: *mut c_void, peer: *mut c_void) {
+    let token = peer as usize;
+    let original = live_shard(token)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&token);
+    let kind = if original.is_some() {
+        ExternalTypedDataEventKind::Finalized
+    } else {
+        ExternalTypedDataEventKind::DoubleFinalized
+    };
+    // a panicking sink must not unwind into dart
+    let _ = catch_unwind(|| report(token, kind));
+    if let Some(Original {
+        callback: Some(callback),
+        peer,
+    }) = original
+    {
+        // Safe: it's the original finalizer of the data, which didn't run yet
+        unsafe { callback(data, peer) };
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::{
+        cobject::{CObject, CustomExternalTyped},
+        test_utils::posted_messages,
+    };
+
+    use super::*;
+
+    thread_local! {
+        static EVENTS: RefCell<Vec<ExternalTypedDataEvent>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(event: &ExternalTypedDataEvent) {
+        EVENTS.with(|events| events.borrow_mut().push(*event));
+    }
+
+    fn events_of(token: usize) -> Vec<ExternalTypedDataEventKind> {
+        EVENTS.with(|events| {
+            events
+                .borrow()
+                .iter()
+                .filter(|event| event.token == token)
+                .map(|event| event.kind)
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_send_and_finalize_is_tracked_once() {
+        set_external_typed_data_sink(record);
+
+        let obj = CObject::external_typed_data(vec![1_u8, 2, 3]);
+        let token = EVENTS.with(|events| events.borrow().last().unwrap().token);
+        posted_messages(|port| port.post_cobject(obj).unwrap());
+
+        assert_eq!(
+            events_of(token),
+            [
+                ExternalTypedDataEventKind::Created,
+                ExternalTypedDataEventKind::Finalized,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_finalize_is_detected() {
+        set_external_typed_data_sink(record);
+
+        let etd = track_external_typed_data(vec![1_u16].into_external_typed_data());
+        let token = EVENTS.with(|events| events.borrow().last().unwrap().token);
+        assert_eq!(etd.peer as usize, token);
+        for _ in 0..2 {
+            unsafe { etd.callback.unwrap()(etd.data.cast(), etd.peer) };
+        }
+
+        assert_eq!(
+            events_of(token),
+            [
+                ExternalTypedDataEventKind::Created,
+                ExternalTypedDataEventKind::Finalized,
+                ExternalTypedDataEventKind::DoubleFinalized,
+            ]
+        );
+        assert!(!live_shard(token).lock().unwrap().contains_key(&token));
+    }
+}
@@ -34,12 +34,19 @@
 // so most of it's functions which have `self` don't use self.
 #![allow(clippy::unused_self)]
 
+// Lets the derive macros refer to this crate in its own tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as xayn_dart_api_dl;
+
 pub mod cobject;
+pub mod diagnostics;
 mod lifecycle;
 mod panic;
 pub mod ports;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
 pub use lifecycle::*;
 
-pub use dart_api_dl_sys::ILLEGAL_PORT;
+pub use dart_api_dl_sys::{DART_API_DL_MAJOR_VERSION, DART_API_DL_MINOR_VERSION, ILLEGAL_PORT};
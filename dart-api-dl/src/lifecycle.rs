@@ -12,16 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ffi::c_void;
+use std::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use dart_api_dl_sys::Dart_InitializeApiDL;
+use dart_api_dl_sys::{Dart_InitializeApiDL, DART_API_DL_MAJOR_VERSION, DART_API_DL_MINOR_VERSION};
 
 use displaydoc::Display;
 use once_cell::sync::OnceCell;
 use thiserror::Error;
 
+use crate::{
+    cobject::{CObject, CustomExternalTyped, ExternalTypedData, TypedDataType},
+    ports::{PostingMessageFailed, SendPort},
+};
+
 static INIT_ONCE: OnceCell<Result<DartRuntime, InitializationFailed>> = OnceCell::new();
 
+/// Cleared once a posted [`ShutdownGuard`] got finalized, it's never set again.
+static LIKELY_ALIVE: AtomicBool = AtomicBool::new(true);
+
 /// Alias for the void pointer passed to [`Dart_InitializeApiDL`].
 pub type InitData = *mut c_void;
 
@@ -37,7 +49,8 @@ pub type InitData = *mut c_void;
 /// This can produce an [`InitializationFailed::InitFailed`] error if initialization
 /// fails. Dart doesn't tell us why initialization failed, but the only likely reason
 /// is that the major version associated with `dart_api_dl.h` of the Dart VM doesn't
-/// match the major version of the `dart_api_dl.h` we build against.
+/// match the major version of the `dart_api_dl.h` we build against. The error
+/// contains the major version of the bindings and a note pointing this out.
 ///
 /// # Safety
 ///
@@ -51,10 +64,8 @@ pub type InitData = *mut c_void;
 /// Luckily even after the Dart VM stops all of the functionality exposed here
 /// should be rust-safe to call (but might abort the process), though there
 /// are no guarantees.
-//FIXME: we could have a Dart VM shutdown guard by returning an external typed data
-// "with magic" destructor the user has to place in a static variable. But besides
-// it being un-ergonomic it's also very confusing/error prone with the current external
-// typed data and if blocking finalizers doesn't block the shutdown also doesn't work.
+//FIXME: [`DartRuntime::shutdown_guard()`] provides a best-effort check, but it's
+// un-ergonomic and as finalizers don't block the shutdown it can't prevent races.
 // Maybe with external native pointers (like added in dart 2.15) this will get a bit
 // better.
 pub unsafe fn initialize_dart_api_dl(
@@ -65,12 +76,31 @@ pub unsafe fn initialize_dart_api_dl(
             if unsafe { Dart_InitializeApiDL(initialize_api_dl_data) } == 0 {
                 Ok(DartRuntime { _priv: () })
             } else {
-                Err(InitializationFailed::InitFailed)
+                Err(InitializationFailed::init_failed())
             }
         })
         .clone()
 }
 
+/// Version of the `dart_api_dl.h` API the bindings were generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DlApiVersion {
+    /// The major version, must match the major version of the Dart VM.
+    pub major: u32,
+    /// The minor version, if the Dart VM has a lower minor version some function slots stay uninitialized.
+    pub minor: u32,
+}
+
+/// Returns the version of the `dart_api_dl.h` API the bindings were generated for.
+///
+/// This is the same as [`DART_API_DL_MAJOR_VERSION`] and [`DART_API_DL_MINOR_VERSION`].
+pub fn dl_api_version() -> DlApiVersion {
+    DlApiVersion {
+        major: DART_API_DL_MAJOR_VERSION,
+        minor: DART_API_DL_MINOR_VERSION,
+    }
+}
+
 /// Marker to prove the Dart VM started.
 ///
 /// Acts as an interface for accessing various dart api dl calls.
@@ -108,6 +138,134 @@ impl DartRuntime {
     pub(crate) unsafe fn instance_unchecked() -> Self {
         DartRuntime { _priv: () }
     }
+
+    /// Creates a guard which notices when the Dart VM likely stopped.
+    ///
+    /// The guard has to be posted to dart with [`ShutdownGuard::post()`], where it's
+    /// received as `Uint8List` which should be stored in a static variable. Once the
+    /// Dart VM stops it finalizes the list and [`DartRuntime::is_likely_alive()`] starts
+    /// returning `false`.
+    pub fn shutdown_guard(self) -> ShutdownGuard {
+        ShutdownGuard {
+            peer: Box::new(GuardPeer {
+                data: 0,
+                posted: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns `false` if any posted [`ShutdownGuard`] got finalized.
+    ///
+    /// This is only a heuristic: the Dart VM might stop before the finalizer
+    /// runs (or without running it at all). The guard is also finalized if dart
+    /// no longer references it. Dropping a guard which wasn't posted, or whose
+    /// posting failed, doesn't count.
+    ///
+    /// Once this returned `false` it never returns `true` again, even if the
+    /// library is used by a new Dart VM.
+    pub fn is_likely_alive(self) -> bool {
+        LIKELY_ALIVE.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the Dart VM is known to support the given `dart_api_dl.h` minor version.
+    ///
+    /// The Dart VM doesn't expose its minor version, it could only be determined by
+    /// checking that the function slots added in a minor version got initialized.
+    /// The bindings (see [`dl_api_version()`]) don't contain any slots added after
+    /// 2.0, so this is conservative and returns `false` for every `minor > 0`, even
+    /// if the Dart VM supports it.
+    ///
+    /// The major version always matches, as otherwise initialization would have failed.
+    pub fn dl_minor_version_at_least(self, minor: u64) -> bool {
+        minor == 0
+    }
+}
+
+/// Marker for reading objects, no matter if the dart runtime is initialized.
+///
+/// Reading and copying objects never calls a dart api dl function, so this is
+/// fine for implementations of traits like [`PartialEq`] or [`Display`](std::fmt::Display)
+/// which have no way to get a [`DartRuntime`]. Unlike a [`DartRuntime`] it isn't
+/// accepted by any function which posts messages or creates ports.
+#[derive(Clone, Copy)]
+pub(crate) struct ReadOnlyRuntime {
+    _priv: (),
+}
+
+impl ReadOnlyRuntime {
+    pub(crate) fn new() -> Self {
+        ReadOnlyRuntime { _priv: () }
+    }
+}
+
+impl From<DartRuntime> for ReadOnlyRuntime {
+    fn from(_: DartRuntime) -> Self {
+        Self::new()
+    }
+}
+
+/// A sentinel which is finalized by the Dart VM when it stops.
+///
+/// See [`DartRuntime::shutdown_guard()`].
+#[must_use]
+pub struct ShutdownGuard {
+    peer: Box<GuardPeer>,
+}
+
+impl ShutdownGuard {
+    /// Posts the guard to dart.
+    ///
+    /// Only a posted guard marks the Dart VM as stopped once it's finalized.
+    ///
+    /// # Errors
+    ///
+    /// If posting the guard failed, in which case it's dropped.
+    pub fn post(self, port: &SendPort) -> Result<(), PostingMessageFailed> {
+        let peer = Box::into_raw(self.peer);
+        let mut object = CObject::external_typed_data(GuardData(peer));
+        // Safe: the peer is only freed by the finalizer, which runs either after
+        // posting succeeded or when the object is dropped below.
+        let posted = unsafe { &(*peer).posted };
+        // set before posting, as dart might finalize the guard before the call returns
+        posted.store(true, Ordering::Release);
+        let result = port.post_cobject_mut(object.as_mut());
+        if result.is_err() {
+            posted.store(false, Ordering::Release);
+        }
+        result
+    }
+}
+
+/// The peer of a [`ShutdownGuard`].
+struct GuardPeer {
+    /// Dart can write to the list, so it needs its own allocation.
+    data: u8,
+    /// Set if the guard was posted to dart.
+    posted: AtomicBool,
+}
+
+/// The leaked peer of a [`ShutdownGuard`] which is about to be posted.
+struct GuardData(*mut GuardPeer);
+
+unsafe impl CustomExternalTyped for GuardData {
+    fn into_external_typed_data(self) -> ExternalTypedData {
+        unsafe extern "C" fn finalize(_data: *mut c_void, peer: *mut c_void) {
+            // Safe: the peer is the leaked box
+            let peer = unsafe { Box::from_raw(peer.cast::<GuardPeer>()) };
+            if peer.posted.load(Ordering::Acquire) {
+                LIKELY_ALIVE.store(false, Ordering::Release);
+            }
+        }
+
+        ExternalTypedData {
+            type_: TypedDataType::Uint8.into(),
+            length: 1,
+            // Safe: the peer is a valid leaked box
+            data: unsafe { ptr::addr_of_mut!((*self.0).data) },
+            peer: self.0.cast(),
+            callback: Some(finalize),
+        }
+    }
 }
 
 /// Error representing that initialization failed.
@@ -117,7 +275,22 @@ pub enum InitializationFailed {
     /// Initialization was not yet done.
     InitNotYetCalled,
     /// Initialization failed.
-    InitFailed,
+    #[displaydoc("Initialization failed, {note}; bindings target major {bindings_major}")]
+    InitFailed {
+        /// The `DART_API_DL_MAJOR_VERSION` the bindings were generated for.
+        bindings_major: u32,
+        /// A hint about the likely cause of the failure.
+        note: &'static str,
+    },
+}
+
+impl InitializationFailed {
+    fn init_failed() -> Self {
+        Self::InitFailed {
+            bindings_major: DART_API_DL_MAJOR_VERSION,
+            note: "likely a Dart DL major version mismatch",
+        }
+    }
 }
 
 /// The slot for given function pointer was not initialized.
@@ -143,14 +316,149 @@ macro_rules! fpslot {
 
 pub(crate) use fpslot;
 
+/// Runs the body with the [`DartRuntime`], returning early if it isn't initialized.
+///
+/// This is meant to be used in `extern "C"` entry points, which all need to
+/// check for the runtime first.
+///
+/// - `with_runtime!(|rt| { ... })` returns `Err(From::from(err))` with the
+///   [`InitializationFailed`] error if the runtime isn't initialized.
+/// - `with_runtime!(|rt| { ... } else fallback)` returns `fallback` instead.
+///
+/// Otherwise it evaluates to the value of the body.
+///
+/// # Example
+///
+/// ```
+/// use xayn_dart_api_dl::with_runtime;
+///
+/// #[no_mangle]
+/// pub extern "C" fn is_ready() -> bool {
+///     with_runtime!(|_rt| { true } else false)
+/// }
+///
+/// // we never called `initialize_dart_api_dl()`
+/// assert!(!is_ready());
+/// ```
+#[macro_export]
+macro_rules! with_runtime {
+    (|$rt:ident| $body:block) => {
+        match $crate::DartRuntime::instance() {
+            ::core::result::Result::Ok($rt) => $body,
+            ::core::result::Result::Err(err) => {
+                return ::core::result::Result::Err(::core::convert::From::from(err));
+            }
+        }
+    };
+    (|$rt:ident| $body:block else $fallback:expr) => {
+        match $crate::DartRuntime::instance() {
+            ::core::result::Result::Ok($rt) => $body,
+            ::core::result::Result::Err(_) => return $fallback,
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use static_assertions::assert_impl_all;
 
+    use crate::test_utils::{posted_messages, unique_port_id};
+
     use super::*;
 
     #[test]
     fn test_static_constraints() {
         assert_impl_all!(DartRuntime: Send, Sync);
     }
+
+    #[test]
+    fn test_with_runtime_returns_early_if_uninitialized() {
+        fn run(reached: &mut bool) -> Result<(), InitializationFailed> {
+            with_runtime!(|_rt| {
+                *reached = true;
+            });
+            Ok(())
+        }
+
+        fn run_with_fallback(reached: &mut bool) -> i32 {
+            with_runtime!(|_rt| {
+                *reached = true;
+                1
+            } else -1)
+        }
+
+        let mut reached = false;
+        assert!(matches!(
+            run(&mut reached),
+            Err(InitializationFailed::InitNotYetCalled)
+        ));
+        assert_eq!(run_with_fallback(&mut reached), -1);
+        assert!(!reached);
+    }
+
+    #[test]
+    fn test_dl_api_version_matches_sys_crate() {
+        use crate::{
+            DART_API_DL_MAJOR_VERSION as REEXPORTED_MAJOR,
+            DART_API_DL_MINOR_VERSION as REEXPORTED_MINOR,
+        };
+
+        assert_eq!(REEXPORTED_MAJOR, DART_API_DL_MAJOR_VERSION);
+        assert_eq!(REEXPORTED_MINOR, DART_API_DL_MINOR_VERSION);
+        assert_eq!(
+            dl_api_version(),
+            DlApiVersion {
+                major: DART_API_DL_MAJOR_VERSION,
+                minor: DART_API_DL_MINOR_VERSION,
+            },
+        );
+    }
+
+    #[test]
+    fn test_dl_minor_version_at_least() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        assert!(rt.dl_minor_version_at_least(0));
+        assert!(!rt.dl_minor_version_at_least(1));
+        assert!(!rt.dl_minor_version_at_least(u64::MAX));
+    }
+
+    #[test]
+    fn test_finalized_shutdown_guard_marks_vm_stopped() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        drop(rt.shutdown_guard());
+        let closed = rt.send_port_from_raw(unique_port_id()).unwrap();
+        assert!(rt.shutdown_guard().post(&closed).is_err());
+        assert!(rt.is_likely_alive());
+
+        // finalizing a guard which wasn't posted doesn't count
+        let peer = Box::into_raw(rt.shutdown_guard().peer);
+        let guard = GuardData(peer).into_external_typed_data();
+        unsafe { (guard.callback.unwrap())(ptr::null_mut(), guard.peer) };
+        assert!(rt.is_likely_alive());
+
+        // the loopback finalizes posted external typed data right away
+        let mut posted = posted_messages(|port| {
+            rt.shutdown_guard().post(&port).unwrap();
+            assert!(!rt.is_likely_alive());
+        });
+        assert_eq!(posted.len(), 1);
+        assert!(posted[0].as_mut().as_typed_data(rt).is_some());
+    }
+
+    #[test]
+    fn test_init_failed_carries_bindings_major_version() {
+        let error = InitializationFailed::init_failed();
+        assert!(matches!(
+            error,
+            InitializationFailed::InitFailed { bindings_major, .. }
+                if bindings_major == DART_API_DL_MAJOR_VERSION
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Initialization failed, likely a Dart DL major version mismatch; \
+                bindings target major {DART_API_DL_MAJOR_VERSION}"
+            ),
+        );
+    }
 }
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::panic::{AssertUnwindSafe, UnwindSafe};
+use std::{
+    any::Any,
+    panic::{AssertUnwindSafe, UnwindSafe},
+};
 
 use crate::cobject::{CObject, CObjectMut};
 
@@ -33,13 +36,7 @@ where
         Err(err) => err,
     };
 
-    let err = if let Some(err) = err.downcast_ref::<String>() {
-        CObject::string_lossy(err)
-    } else if let Some(err) = err.downcast_ref::<&'static str>() {
-        CObject::string_lossy(err)
-    } else {
-        CObject::string_lossy("panic of unsupported type")
-    };
+    let err = CObject::string_lossy(panic_message(&*err));
 
     let a_obj = AssertUnwindSafe(obj);
     if std::panic::catch_unwind(AssertUnwindSafe(|| on_panic(fix(a_obj), err))).is_err() {
@@ -47,6 +44,17 @@ where
     }
 }
 
+/// Returns the message of a caught panic.
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = panic.downcast_ref::<String>() {
+        msg
+    } else if let Some(msg) = panic.downcast_ref::<&'static str>() {
+        msg
+    } else {
+        "panic of unsupported type"
+    }
+}
+
 // Rust2021 is too clever
 fn fix<T>(v: AssertUnwindSafe<T>) -> T {
     v.0
@@ -13,23 +13,41 @@
 // limitations under the License.
 
 //! This module contains types and implementations for interacting with send/receive ports.
+#[cfg(feature = "drain")]
+use std::time::Duration;
 use std::{
     ffi::{CString, NulError},
     mem::forget,
     ops::Deref,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
 };
 
 use dart_api_dl_sys::{
+    _Dart_CObject__bindgen_ty_1,
+    _Dart_CObject__bindgen_ty_1__bindgen_ty_3,
     Dart_CObject,
+    Dart_CObject_Type,
+    ILLEGAL_PORT,
+};
+#[cfg(not(test))]
+use dart_api_dl_sys::{
     Dart_CloseNativePort_DL,
     Dart_NewNativePort_DL,
     Dart_PostCObject_DL,
     Dart_PostInteger_DL,
-    ILLEGAL_PORT,
 };
 
 use thiserror::Error;
 
+#[cfg(test)]
+use crate::test_utils::{
+    Dart_CloseNativePort_DL,
+    Dart_NewNativePort_DL,
+    Dart_PostCObject_DL,
+    Dart_PostInteger_DL,
+};
 use crate::{
     cobject::{CObject, CObjectMut},
     lifecycle::{fpslot, DartRuntime},
@@ -37,6 +55,59 @@ use crate::{
     UninitializedFunctionSlot,
 };
 
+mod closure;
+#[cfg(feature = "metrics")]
+mod concurrency;
+mod context;
+mod control;
+mod correlation;
+#[cfg(feature = "drain")]
+mod drain;
+mod error;
+mod failure;
+#[cfg(feature = "handler-thread")]
+mod handler_thread;
+mod init_scope;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod ping;
+mod progress;
+mod queue;
+mod rate_limit;
+#[cfg(feature = "reentrancy-guard")]
+mod reentrant;
+#[cfg(feature = "port-registry")]
+mod registry;
+mod reply;
+mod state;
+#[cfg(feature = "futures")]
+mod stream;
+#[cfg(feature = "tokio")]
+mod tokio_handler;
+mod typed;
+mod typed_send_port;
+
+#[cfg(feature = "metrics")]
+pub use self::metrics::LatencySnapshot;
+#[cfg(feature = "reentrancy-guard")]
+pub use self::reentrant::{PortReentered, ReentrantGuard};
+#[cfg(feature = "futures")]
+pub use self::stream::PortStream;
+#[cfg(feature = "tokio")]
+pub use self::tokio_handler::{HandlerFuture, Tokio, TokioNativeMessageHandler};
+pub use self::{
+    context::ContextMessageHandler,
+    control::ControlMessage,
+    correlation::{CorrelatingSender, PendingAck},
+    failure::{FailurePolicy, PostFailed},
+    init_scope::InitScope,
+    queue::QueueOverflow,
+    rate_limit::{RateLimit, RateLimitedHandler},
+    reply::{ReplyBuilder, ReplyHandle},
+    typed::{Responder, TypedPort, DECODE_FAILED_ERROR_CODE},
+    typed_send_port::Port,
+};
+
 /// Raw Id of a dart Port.
 ///
 /// Same as `Dart_Port_DL`.
@@ -49,6 +120,15 @@ use crate::{
 // the non-clear separation.
 pub type DartPortId = i64;
 
+/// Returns `true` if `port` is the `ILLEGAL_PORT`.
+///
+/// All constructors of [`SendPort`] and [`NativeRecvPort`] reject a port
+/// through this check, which makes it the only place to adapt for a dart
+/// build with a different illegal port.
+pub fn is_illegal_port(port: DartPortId) -> bool {
+    port == ILLEGAL_PORT
+}
+
 // like `Dart_NativeMessageHandler_DL` but not wrapped in an `Option`
 type DartNativeMessageHandler =
     unsafe extern "C" fn(dest_port_id: DartPortId, message: *mut Dart_CObject);
@@ -83,7 +163,19 @@ impl DartRuntime {
         port: DartPortId,
         origin: DartPortId,
     ) -> Option<SendPort> {
-        (port != ILLEGAL_PORT).then(|| SendPort { port, origin })
+        SendPort::from_raw_parts(port, origin)
+    }
+
+    /// Parses a port id formatted with [`SendPort::id_string()`].
+    ///
+    /// Returns `None` if the string is malformed or encodes `ILLEGAL_PORT`.
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn send_port_from_id_string(&self, id: &str) -> Option<SendPort> {
+        if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        let port = u64::from_str_radix(id, 16).ok()? as DartPortId;
+        self.send_port_from_raw(port)
     }
 
     /// Wrap a raw port id as `NativeRecvPort`.
@@ -91,14 +183,42 @@ impl DartRuntime {
     /// The returned type will close the port when it's dropped and can
     /// be used as a guard.
     pub fn native_recv_port_from_raw(&self, port: DartPortId) -> Option<NativeRecvPort> {
-        (port != ILLEGAL_PORT).then(|| {
-            NativeRecvPort(SendPort {
+        (!is_illegal_port(port)).then(|| NativeRecvPort {
+            port: SendPort {
                 port,
                 origin: ILLEGAL_PORT,
-            })
+            },
+            #[cfg(feature = "drain")]
+            drain_on_drop: None,
         })
     }
 
+    /// Takes over the ownership of a native port created by other code.
+    ///
+    /// This is for ports whose id was received out-of-band, e.g. from another
+    /// plugin which created the port. The returned [`NativeRecvPort`] closes the
+    /// port when it's dropped, no new port is created.
+    ///
+    /// Unlike [`DartRuntime::native_recv_port_from_raw()`], which is mainly used
+    /// to temporarily wrap our own port (and then [`NativeRecvPort::leak()`] it),
+    /// this is meant for permanently adopting the responsibility to close it.
+    ///
+    /// Returns `None` if `port == ILLEGAL_PORT`.
+    ///
+    /// # Safety
+    ///
+    /// Closing a port is only correct if nobody else will close it:
+    ///
+    /// - The port must be a native port, not e.g. a dart `ReceivePort`.
+    /// - The creator of the port must have handed over its ownership,
+    ///   i.e. it must not close the port itself. Otherwise the port might be
+    ///   closed twice, and as port ids can be reused the second close could
+    ///   close an unrelated port.
+    /// - It must not be adopted more than once.
+    pub unsafe fn adopt_recv_port(&self, port: DartPortId) -> Option<NativeRecvPort> {
+        self.native_recv_port_from_raw(port)
+    }
+
     /// Creates a new [`NativeRecvPort`].
     ///
     /// If possible use [`DartRuntime::native_recv_port()`] instead.
@@ -156,32 +276,98 @@ impl DartRuntime {
         N: NativeMessageHandler,
     {
         //SAFE: The handle_message wrapper provides a safe abstraction
-        return unsafe {
+        let port = unsafe {
             self.unsafe_native_recv_port(N::NAME, handle_message::<N>, N::CONCURRENT_HANDLING)
-        };
+        }?;
+        #[cfg(feature = "metrics")]
+        {
+            metrics::init(port.as_raw().0);
+            concurrency::init(port.as_raw().0);
+        }
+        return Ok(port);
 
         unsafe extern "C" fn handle_message<N>(ourself: DartPortId, data_mut: *mut Dart_CObject)
         where
             N: NativeMessageHandler,
         {
             if let Ok(rt) = DartRuntime::instance() {
-                if let Some(port) = rt.native_recv_port_from_raw(ourself) {
-                    unsafe {
-                        CObjectMut::with_pointer(data_mut, |data| {
-                            catch_unwind_panic_as_cobject(
-                                data,
-                                |data| N::handle_message(rt, &port, data),
-                                |data, panic_obj| N::handle_panic(rt, &port, data, panic_obj),
-                            );
-                        });
-                    };
-                    port.leak();
-                }
+                // Safe: dart passes a valid message, or at worst a null pointer
+                unsafe { handle_raw_message::<N>(rt, ourself, data_mut) };
             }
         }
     }
 }
 
+/// Dispatches a raw message received on the port `ourself` to the handler `N`.
+///
+/// Dart should never call the handler with a null pointer, but if it does the
+/// message is ignored instead of being dereferenced and reported as
+/// [`ContractViolation::NullMessage`](crate::diagnostics::ContractViolation::NullMessage).
+///
+/// # Safety
+///
+/// The pointer must be null or valid for [`CObjectMut::with_pointer()`].
+unsafe fn handle_raw_message<N>(rt: DartRuntime, ourself: DartPortId, data_mut: *mut Dart_CObject)
+where
+    N: NativeMessageHandler,
+{
+    if data_mut.is_null() {
+        crate::diagnostics::report_contract_violation(
+            crate::diagnostics::ContractViolation::NullMessage { port: ourself },
+        );
+        return;
+    }
+    unsafe {
+        CObjectMut::with_pointer(data_mut, |data| {
+            dispatch_message::<N>(rt, ourself, data);
+        });
+    };
+}
+
+/// Dispatches a message received on the port `ourself` to the handler `N`.
+///
+/// This is the part of the message handler trampoline which doesn't
+/// deal with raw pointers.
+fn dispatch_message<N>(rt: DartRuntime, ourself: DartPortId, data: CObjectMut<'_>)
+where
+    N: NativeMessageHandler,
+{
+    if let Some(port) = rt.native_recv_port_from_raw(ourself) {
+        #[cfg(feature = "drain")]
+        let is_sentinel = drain::consume_sentinel(rt, ourself, &data);
+        #[cfg(not(feature = "drain"))]
+        let is_sentinel = false;
+        if !is_sentinel {
+            // unbounded ports don't do any bookkeeping
+            if let Some(slot) = queue::enter(ourself, N::QUEUE_DEPTH, N::QUEUE_OVERFLOW) {
+                #[cfg(feature = "metrics")]
+                let start = Instant::now();
+                #[cfg(feature = "metrics")]
+                let invocation = concurrency::enter(ourself);
+                #[cfg(feature = "handler-thread")]
+                let guard = handler_thread::HandlerThreadGuard::enter();
+                #[cfg(feature = "reentrancy-guard")]
+                let entered = ReentrantGuard::enter(ourself);
+                catch_unwind_panic_as_cobject(
+                    data,
+                    |data| N::handle_message(rt, &port, data),
+                    |data, panic_obj| N::handle_panic(rt, &port, data, panic_obj),
+                );
+                #[cfg(feature = "reentrancy-guard")]
+                drop(entered);
+                #[cfg(feature = "handler-thread")]
+                drop(guard);
+                #[cfg(feature = "metrics")]
+                drop(invocation);
+                drop(slot);
+                #[cfg(feature = "metrics")]
+                metrics::record(ourself, start.elapsed());
+            }
+        }
+        port.leak();
+    }
+}
+
 /// The creating of a native receiver port failed.
 #[derive(Debug, Error)]
 pub enum PortCreationFailed {
@@ -213,15 +399,36 @@ pub trait NativeMessageHandler {
     /// If `false` dart will only call the handler from one thread at a time.
     ///
     /// It still will call it from different threads over time, just not at the same time.
+    ///
+    /// As a consequence a handler must not block until a message it posted to its own
+    /// port is handled, as this will deadlock. With the `reentrancy-guard` feature
+    /// `SendPort::reentrant_guard()` turns such a deadlock into an error.
     const CONCURRENT_HANDLING: bool;
 
     /// A Name used to setup the port.
     ///
-    /// This must not contain a `0` byte.
+    /// This must not contain a `0` byte, use [`assert_no_nul()`] to check this
+    /// at compile time.
     ///
     /// The name is mainly used for debugging purpose.
     const NAME: &'static str;
 
+    /// The maximal number of messages which are handled at the same time.
+    ///
+    /// Dart queues the messages of a port itself, without any bound. This bound is
+    /// enforced in rust when dart hands a message to the handler, messages exceeding
+    /// it are handled according to [`NativeMessageHandler::QUEUE_OVERFLOW`]. This is
+    /// mainly useful with [`NativeMessageHandler::CONCURRENT_HANDLING`], as otherwise
+    /// dart never hands more than one message at a time to the handler.
+    ///
+    /// Defaults to `usize::MAX`, which is unbounded. A depth of `0` is treated as `1`.
+    const QUEUE_DEPTH: usize = usize::MAX;
+
+    /// What happens with messages exceeding [`NativeMessageHandler::QUEUE_DEPTH`].
+    ///
+    /// Defaults to [`QueueOverflow::Block`].
+    const QUEUE_OVERFLOW: QueueOverflow = QueueOverflow::Block;
+
     /// Called when handling a message.
     ///
     /// `ourself` can be used to close the port (though you should not rely on
@@ -239,12 +446,115 @@ pub trait NativeMessageHandler {
     /// This is not called while `panicking`, as such this will not trigger a
     /// "double-panic" induced abort. Though it also can't do anything with the
     /// panic so it will simply do nothing.
+    ///
+    /// By default the panic is intentionally dropped silently. Override it to
+    /// forward the panic as error reply, e.g. if the message contains the port
+    /// to respond to:
+    ///
+    /// ```
+    /// # use xayn_dart_api_dl::{
+    /// #     cobject::{CObject, CObjectMut},
+    /// #     ports::{NativeMessageHandler, NativeRecvPort},
+    /// #     DartRuntime,
+    /// # };
+    /// struct Handler;
+    ///
+    /// impl NativeMessageHandler for Handler {
+    ///     const CONCURRENT_HANDLING: bool = true;
+    ///     const NAME: &'static str = "handler";
+    ///
+    ///     fn handle_message(rt: DartRuntime, _: &NativeRecvPort, data: CObjectMut<'_>) {
+    ///         // ...
+    ///     }
+    ///
+    ///     fn handle_panic(rt: DartRuntime, _: &NativeRecvPort, data: CObjectMut<'_>, panic: CObject) {
+    ///         if let Some(Some(respond_to)) = data.as_send_port(rt) {
+    ///             let _ = respond_to.post_cobject(panic);
+    ///         }
+    ///     }
+    /// }
+    /// ```
     fn handle_panic(
         rt: DartRuntime,
         ourself: &NativeRecvPort,
         data: CObjectMut<'_>,
         panic: CObject,
-    );
+    ) {
+        let _ = (rt, ourself, data, panic);
+    }
+}
+
+/// Returns the `name` and panics if it contains a nul byte.
+///
+/// Used in a `const` context this turns a nul byte in a
+/// [`NativeMessageHandler::NAME`] into a compile time error instead of a
+/// [`PortCreationFailed::NulInName`] error when creating the port.
+///
+/// ```
+/// # use xayn_dart_api_dl::ports::assert_no_nul;
+/// const NAME: &str = assert_no_nul("adder");
+/// ```
+///
+/// ```compile_fail,E0080
+/// # use xayn_dart_api_dl::ports::assert_no_nul;
+/// const NAME: &str = assert_no_nul("add\0er");
+/// ```
+///
+/// # Panics
+///
+/// If the `name` contains a nul byte.
+pub const fn assert_no_nul(name: &'static str) -> &'static str {
+    let bytes = name.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        assert!(bytes[idx] != 0, "the name must not contain a nul byte");
+        idx += 1;
+    }
+    name
+}
+
+/// The default of [`max_message_size()`], i.e. no limit.
+///
+/// The limit is compared with the estimated number of bytes of a message. Dart
+/// can't allocate strings, lists or typed data with more than `2^30` elements
+/// on 32bit platforms, a limit in bytes can only approximate this.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = usize::MAX;
+
+static MAX_MESSAGE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_MESSAGE_SIZE);
+
+/// Returns the maximal estimated size in bytes of messages posted by [`SendPort::post_cobject_mut()`].
+///
+/// See [`CObjectMut::estimated_size()`], by default there is no limit.
+pub fn max_message_size() -> usize {
+    MAX_MESSAGE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Sets the maximal estimated size in bytes of messages posted by [`SendPort::post_cobject_mut()`].
+///
+/// Larger messages aren't posted, as dart doesn't report why posting a message
+/// failed. [`SendPort::try_post_cobject_mut()`] reports this as
+/// [`PostingFailureKind::LikelyTooLarge`].
+/// Estimating the size walks the whole message, which is skipped while the
+/// limit is [`DEFAULT_MAX_MESSAGE_SIZE`].
+pub fn set_max_message_size(bytes: usize) {
+    MAX_MESSAGE_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+/// Fails if the [estimated size](CObjectMut::estimated_size()) of the message exceeds `limit`.
+fn check_message_size(
+    rt: DartRuntime,
+    cobject: &CObjectMut<'_>,
+    limit: usize,
+) -> Result<(), PostingFailure> {
+    if limit == usize::MAX {
+        return Ok(());
+    }
+    let size = cobject.estimated_size(rt);
+    if size > limit {
+        Err(PostingFailureKind::LikelyTooLarge { size, limit }.into())
+    } else {
+        Ok(())
+    }
 }
 
 /// Represents a send port which can be used to send messages to dart.
@@ -265,14 +575,55 @@ pub struct SendPort {
 }
 
 impl SendPort {
+    /// Wraps the port ids, see [`DartRuntime::send_port_from_raw_with_origin()`].
+    ///
+    /// This is also used for reading send ports from objects, which doesn't need a
+    /// [`DartRuntime`].
+    pub(crate) fn from_raw_parts(port: DartPortId, origin: DartPortId) -> Option<Self> {
+        (!is_illegal_port(port)).then(|| SendPort { port, origin })
+    }
+
     /// Return the underlying port ids of this `SendPort`.
     ///
     /// The first id is the port id and the second one the
     /// origin id and as such most times equals to `ILLEGAL_PORT`.
+    ///
+    /// The port id identifies the receive port this port sends to and is what
+    /// dart uses for `SendPort` equality, see [`SendPort::same_target()`].
     pub fn as_raw(&self) -> (DartPortId, DartPortId) {
         (self.port, self.origin)
     }
 
+    /// Returns a copy of this `SendPort` with the origin id set to `ILLEGAL_PORT`.
+    ///
+    /// The origin id of a port received from dart is preserved when it's
+    /// turned back into a [`CObject`] with [`CObject::send_port()`]. Use this
+    /// to deliberately not forward it.
+    #[must_use]
+    pub fn without_origin(&self) -> SendPort {
+        SendPort {
+            port: self.port,
+            origin: ILLEGAL_PORT,
+        }
+    }
+
+    /// Returns `true` if both ports send to the same receive port.
+    ///
+    /// This is how dart compares `SendPort`s, i.e. it only compares the port
+    /// ids and ignores the origin. Which can be used to e.g. deduplicate ports
+    /// received through different messages.
+    pub fn same_target(&self, other: &SendPort) -> bool {
+        self.port == other.port
+    }
+
+    /// Returns the port id as compact, stable string, e.g. for logging.
+    ///
+    /// The id is formatted as lowercase hex of its bits, the origin is not included.
+    /// It can be parsed with [`DartRuntime::send_port_from_id_string()`].
+    pub fn id_string(&self) -> String {
+        format!("{:x}", self.port)
+    }
+
     /// Sends given integer to given port.
     ///
     /// This will use `Dart_PostInteger_DL` instead of creating
@@ -291,6 +642,46 @@ impl SendPort {
         }
     }
 
+    /// Sends given bool to given port.
+    ///
+    /// This creates a bool [`CObject`] and posts it, like with
+    /// [`SendPort::post_integer()`] sending to a closed port is safe and
+    /// only results in an error.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_bool(&self, message: bool) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(CObject::bool(message))
+    }
+
+    /// Sends given double to given port.
+    ///
+    /// This creates a double [`CObject`] and posts it, like with
+    /// [`SendPort::post_integer()`] sending to a closed port is safe and
+    /// only results in an error.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_double(&self, message: f64) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(CObject::double(message))
+    }
+
+    /// Sends given string to given port.
+    ///
+    /// This creates a string [`CObject`] and posts it, like with
+    /// [`SendPort::post_integer()`] sending to a closed port is safe and
+    /// only results in an error.
+    ///
+    /// # Errors
+    ///
+    /// - If the string contains a nul byte, nothing is posted then.
+    /// - If posting the message failed.
+    pub fn post_string(&self, message: impl AsRef<str>) -> Result<(), PostingFailure> {
+        self.try_post_cobject_mut(CObject::string(message)?.as_mut())
+    }
+
     /// This will call [`SendPort.post_cobject_mut()`] and then drop the `cobject`.
     ///
     /// See [`SendPort.post_cobject_mut()`] for more details.
@@ -302,6 +693,107 @@ impl SendPort {
         self.post_cobject_mut(cobject.as_mut())
     }
 
+    /// Posts the `cobject` unless the `deadline` already passed.
+    ///
+    /// This is meant for messages which are useless once stale, e.g. sensor
+    /// frames. The deadline is checked just before posting, if it passed the
+    /// object is dropped without posting it and `Ok(false)` is returned.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_if_before(
+        &self,
+        cobject: CObject,
+        deadline: Instant,
+    ) -> Result<bool, PostingMessageFailed> {
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        self.post_cobject(cobject).map(|()| true)
+    }
+
+    /// Posts all `objects` at once as a single array message.
+    ///
+    /// Compared to posting each object on it's own this reduces the dispatch
+    /// overhead on the dart side and the objects can't be interleaved with
+    /// other messages. External typed data is moved to dart like with
+    /// [`SendPort.post_cobject_mut()`].
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_batch(&self, objects: Vec<CObject>) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(CObject::array(objects.into_iter().map(Box::new).collect()))
+    }
+
+    /// Posts each of the `messages` as separate message, in order.
+    ///
+    /// Each successfully posted message is handled like with
+    /// [`SendPort.post_cobject_mut()`], i.e. its external typed data is moved
+    /// to dart and set to null. Posting stops at the first message which fails.
+    /// Use [`SendPort::post_as_array()`] to post all objects as single array message.
+    ///
+    /// Returns the number of posted messages, which is always `messages.len()`.
+    ///
+    /// # Errors
+    ///
+    /// If posting a message failed, the error contains the number of messages
+    /// which were posted before it.
+    pub fn post_cobject_slice(
+        &self,
+        messages: &mut [CObjectMut<'_>],
+    ) -> Result<usize, PostingSliceFailed> {
+        for (posted, message) in messages.iter_mut().enumerate() {
+            self.try_post_cobject_mut(message.reborrow())
+                .map_err(|reason| PostingSliceFailed { posted, reason })?;
+        }
+        Ok(messages.len())
+    }
+
+    /// Posts all `messages` at once as a single array message.
+    ///
+    /// Like [`SendPort::post_batch()`] but for borrowed objects. If posting
+    /// succeeded the external typed data of the messages is moved to dart and
+    /// set to null, like with [`SendPort.post_cobject_mut()`]. Otherwise the
+    /// messages stay unchanged.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_as_array(
+        &self,
+        messages: &mut [CObjectMut<'_>],
+    ) -> Result<(), PostingMessageFailed> {
+        let mut values = messages
+            .iter_mut()
+            .map(CObjectMut::as_mut_ptr)
+            .collect::<Vec<_>>();
+        // empty arrays must use a null pointer, see `prepare_dart_array_parts()`
+        let values_ptr = if values.is_empty() {
+            ptr::null_mut()
+        } else {
+            values.as_mut_ptr()
+        };
+        let mut array = Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kArray,
+            value: _Dart_CObject__bindgen_ty_1 {
+                as_array: _Dart_CObject__bindgen_ty_1__bindgen_ty_3 {
+                    // a slice can't be longer than isize::MAX
+                    length: values.len().try_into().unwrap_or(isize::MAX),
+                    values: values_ptr,
+                },
+            },
+        };
+        // Safe: the array only points to the sound messages, which are
+        // borrowed for the duration of the call
+        unsafe {
+            CObjectMut::with_pointer(ptr::addr_of_mut!(array), |array| {
+                self.post_cobject_mut(array)
+            })
+        }
+    }
+
     /// Sends given [`CObject`] to given port.
     ///
     /// Like in dart, for data which is not externally typed, a copy of the data is sent
@@ -318,22 +810,32 @@ impl SendPort {
     ///
     /// # Errors
     ///
-    /// If posting the message failed this will error.
-    pub fn post_cobject_mut(
-        &self,
-        mut cobject: CObjectMut<'_>,
-    ) -> Result<(), PostingMessageFailed> {
+    /// If posting the message failed this will error. If the
+    /// [estimated size](CObjectMut::estimated_size()) of the message exceeds the
+    /// [`max_message_size()`] it isn't posted at all.
+    pub fn post_cobject_mut(&self, cobject: CObjectMut<'_>) -> Result<(), PostingMessageFailed> {
+        self.try_post_cobject_mut(cobject)
+            .map_err(PostingMessageFailed::from)
+    }
+
+    /// Like [`SendPort::post_cobject_mut()`] but the error tells why posting failed.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed, see [`PostingFailure::kind()`].
+    pub fn try_post_cobject_mut(&self, mut cobject: CObjectMut<'_>) -> Result<(), PostingFailure> {
+        // SAFE: If we have a `SendPort` the runtime must have been initialized.
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        check_message_size(rt, &cobject, max_message_size())?;
         // SAFE: As long as `CObject` was properly constructed and is kept in a sound
         //       state (which is a requirement of it's unsafe interfaces).
         if unsafe { fpslot!(@call Dart_PostCObject_DL(self.port, cobject.as_mut_ptr()))? } {
-            // SAFE: If we have a `SendPort` the runtime must have been initialized.
-            let rt = unsafe { DartRuntime::instance_unchecked() };
             // null everything which has been moved out semantically
             // or else we will get double free or even use-after free problems
             cobject.null_external_typed_objects(rt);
             Ok(())
         } else {
-            Err(PostingMessageFailed)
+            Err(PostingFailureKind::Rejected.into())
         }
     }
 }
@@ -342,7 +844,11 @@ impl SendPort {
 ///
 /// If this handler is dropped the port is closed.
 #[derive(Debug)]
-pub struct NativeRecvPort(SendPort);
+pub struct NativeRecvPort {
+    port: SendPort,
+    #[cfg(feature = "drain")]
+    drain_on_drop: Option<Duration>,
+}
 
 impl NativeRecvPort {
     /// Prevent drop form closing this port.
@@ -351,16 +857,91 @@ impl NativeRecvPort {
         forget(self);
         port
     }
+
+    /// Upper bound for the timeout of [`NativeRecvPort::with_drain_on_drop()`].
+    #[cfg(feature = "drain")]
+    pub const MAX_DRAIN_ON_DROP_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Makes dropping this port first [drain](NativeRecvPort::drain()) it.
+    ///
+    /// **Dropping the port will then block the dropping thread** until the drain
+    /// completed or the timeout elapsed. The timeout is capped at
+    /// [`NativeRecvPort::MAX_DRAIN_ON_DROP_TIMEOUT`], so that a port which is no
+    /// longer serviced can't block a drop for long.
+    ///
+    /// Dropping the port from within the handler of a port which doesn't handle
+    /// messages concurrently always blocks for the whole timeout, see
+    /// [`NativeRecvPort::drain()`].
+    #[cfg(feature = "drain")]
+    #[must_use]
+    pub fn with_drain_on_drop(mut self, timeout: Duration) -> Self {
+        self.drain_on_drop = Some(timeout.min(Self::MAX_DRAIN_ON_DROP_TIMEOUT));
+        self
+    }
+
+    /// Best-effort waits until the messages enqueued on this port have been handled.
+    ///
+    /// This posts a sentinel message to the port and blocks until the message
+    /// handler trampoline received it or `timeout` elapsed. The sentinel is not
+    /// passed to the message handler.
+    ///
+    /// Returns `true` if the sentinel was received before the timeout.
+    ///
+    /// This is racy in multiple ways:
+    ///
+    /// - Dart doesn't guarantee that messages are handled in the order they were
+    ///   enqueued, though in practice they are.
+    /// - With `CONCURRENT_HANDLING` messages enqueued before the sentinel might still
+    ///   be handled concurrently when this returns.
+    /// - Messages enqueued after the sentinel are not waited for.
+    ///
+    /// If this is called from within the handler of a port which doesn't handle
+    /// messages concurrently this will always time out, as the sentinel can only
+    /// be handled after the current handler returns.
+    #[cfg(feature = "drain")]
+    pub fn drain(&self, timeout: Duration) -> bool {
+        drain::drain(self, timeout)
+    }
+
+    /// Returns the latencies of the message handler of this port.
+    ///
+    /// The latencies of ports created by [`DartRuntime::native_recv_port()`] are
+    /// recorded until the port is dropped.
+    #[cfg(feature = "metrics")]
+    pub fn latency_snapshot(&self) -> LatencySnapshot {
+        metrics::snapshot(self.as_raw().0)
+    }
+
+    /// Returns the highest number of messages which were handled at the same time.
+    ///
+    /// Dart decides how many messages of a port with
+    /// [`NativeMessageHandler::CONCURRENT_HANDLING`] are handled in parallel, this
+    /// allows sizing other resources, e.g. a connection pool, to match it. Messages
+    /// are counted while they are handed to the handler, `0` if none was handled yet.
+    ///
+    /// The concurrency of ports created by [`DartRuntime::native_recv_port()`] is
+    /// recorded until the port is dropped.
+    #[cfg(feature = "metrics")]
+    pub fn peak_concurrency(&self) -> usize {
+        concurrency::peak(self.as_raw().0)
+    }
 }
 
 impl Drop for NativeRecvPort {
     fn drop(&mut self) {
+        #[cfg(feature = "drain")]
+        if let Some(timeout) = self.drain_on_drop {
+            self.drain(timeout);
+        }
         // SAFE:
         // - Is save if calling dart functions is safe
         // - and if calling it with a bad port id is safe
         //
         // Both should be the case
         let _ = unsafe { fpslot!(@call Dart_CloseNativePort_DL(self.as_raw().0)) };
+        #[cfg(feature = "port-registry")]
+        registry::remove(self.as_raw().0);
+        state::remove(self.as_raw().0);
     }
 }
 
@@ -368,7 +949,7 @@ impl Deref for NativeRecvPort {
     type Target = SendPort;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.port
     }
 }
 
@@ -377,6 +958,79 @@ impl Deref for NativeRecvPort {
 #[error("Posting message failed.")]
 pub struct PostingMessageFailed;
 
+/// Posting a message on a port failed, with the reason why.
+///
+/// See [`PostingFailure::kind()`] for why it failed.
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct PostingFailure {
+    kind: PostingFailureKind,
+}
+
+impl PostingFailure {
+    /// Returns why posting the message failed.
+    pub fn kind(&self) -> &PostingFailureKind {
+        &self.kind
+    }
+}
+
+/// Why posting a message failed, see [`PostingFailure::kind()`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum PostingFailureKind {
+    /// Dart rejected the message, e.g. because the port is closed.
+    #[error("Posting message failed.")]
+    Rejected,
+    /// The message wasn't posted as it's likely too large, see [`set_max_message_size()`].
+    #[error("Posting message failed, its estimated size of {size} bytes exceeds {limit} bytes.")]
+    LikelyTooLarge {
+        /// The estimated size of the message.
+        size: usize,
+        /// The maximal message size at the time of posting.
+        limit: usize,
+    },
+    /// The message wasn't posted as the string contains a nul byte.
+    #[error("Posting message failed, the string contains a nul byte.")]
+    InteriorNul(NulError),
+}
+
+/// Posting a slice of messages stopped at a message which failed.
+///
+/// See [`SendPort::post_cobject_slice()`].
+#[derive(Debug, Error)]
+#[error("Posting message {posted} of the slice failed.")]
+pub struct PostingSliceFailed {
+    /// The number of messages which were posted before the failure.
+    pub posted: usize,
+    /// Why posting the message failed.
+    #[source]
+    pub reason: PostingFailure,
+}
+
+impl From<PostingFailureKind> for PostingFailure {
+    fn from(kind: PostingFailureKind) -> Self {
+        Self { kind }
+    }
+}
+
+impl From<NulError> for PostingFailure {
+    fn from(err: NulError) -> Self {
+        PostingFailureKind::InteriorNul(err).into()
+    }
+}
+
+impl From<UninitializedFunctionSlot> for PostingFailure {
+    fn from(_: UninitializedFunctionSlot) -> Self {
+        PostingFailureKind::Rejected.into()
+    }
+}
+
+impl From<PostingFailure> for PostingMessageFailed {
+    fn from(_: PostingFailure) -> Self {
+        Self
+    }
+}
+
 impl From<UninitializedFunctionSlot> for PostingMessageFailed {
     fn from(_: UninitializedFunctionSlot) -> Self {
         Self
@@ -385,9 +1039,25 @@ impl From<UninitializedFunctionSlot> for PostingMessageFailed {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        cell::RefCell,
+        ptr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
     use dart_api_dl_sys::{Dart_NativeMessageHandler_DL, Dart_Port_DL};
     use static_assertions::{assert_impl_all, assert_type_eq_all};
 
+    use crate::{
+        cobject::{SharedTypedData, TypedData, TypedDataRef},
+        diagnostics::{set_contract_violation_sink, ContractViolation},
+        test_utils::{posted_messages, unique_port_id, was_closed},
+    };
+
     use super::*;
 
     #[test]
@@ -401,4 +1071,320 @@ mod tests {
             Dart_NativeMessageHandler_DL
         );
     }
+
+    #[test]
+    fn test_origin_round_trips_through_cobject() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw_with_origin(10, 20).unwrap();
+        let mut cobject = CObject::send_port(port);
+        let received = cobject.as_mut().as_send_port(rt).unwrap().unwrap();
+        assert_eq!(received.as_raw(), (10, 20));
+
+        let mut cobject = CObject::send_port(received);
+        let resent = cobject.as_mut().as_send_port(rt).unwrap().unwrap();
+        assert_eq!(resent.as_raw(), (10, 20));
+    }
+
+    #[test]
+    fn test_oversized_message_is_not_posted() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut message = CObject::string("a".repeat(1025)).unwrap();
+        assert_eq!(
+            check_message_size(rt, &message.as_mut(), 1024)
+                .unwrap_err()
+                .kind(),
+            &PostingFailureKind::LikelyTooLarge {
+                size: 1025,
+                limit: 1024
+            },
+        );
+        assert!(check_message_size(rt, &message.as_mut(), 1025).is_ok());
+        // external typed data is moved, not copied
+        let mut external = CObject::typed_data(TypedData::Uint8(vec![0; 1025]));
+        assert!(check_message_size(rt, &external.as_mut(), 1024).is_ok());
+
+        let posted = posted_messages(|port| {
+            port.post_cobject(CObject::typed_data(TypedData::Uint8(vec![0; 1025])))
+                .unwrap();
+        });
+        assert_eq!(posted.len(), 1);
+    }
+
+    #[test]
+    fn test_post_scalars() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut posted = posted_messages(|port| {
+            port.post_bool(true).unwrap();
+            port.post_double(0.25).unwrap();
+            port.post_string("hy").unwrap();
+            assert!(matches!(
+                port.post_string("h\0y").unwrap_err().kind(),
+                PostingFailureKind::InteriorNul(_)
+            ));
+        });
+
+        assert_eq!(posted.len(), 3);
+        assert_eq!(posted[0].as_mut().as_bool(rt), Some(true));
+        assert_eq!(posted[1].as_mut().as_double(rt), Some(0.25));
+        assert_eq!(posted[2].as_mut().as_string(rt), Some("hy"));
+    }
+
+    #[test]
+    fn test_post_batch() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut posted = posted_messages(|port| {
+            port.post_batch(vec![
+                CObject::int64(1),
+                CObject::string("two").unwrap(),
+                CObject::external_typed_data(TypedData::Uint8(vec![3, 3, 3])),
+                CObject::null(),
+            ])
+            .unwrap();
+        });
+
+        assert_eq!(posted.len(), 1);
+        let batch = posted[0].as_mut();
+        let batch = batch.as_array(rt).unwrap();
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch[0].as_int(rt), Some(1));
+        assert_eq!(batch[1].as_string(rt), Some("two"));
+        assert!(matches!(
+            batch[2].as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8(&[3, 3, 3])), _))
+        ));
+        assert!(batch[3].as_null(rt).is_some());
+    }
+
+    #[test]
+    fn test_post_if_before() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let frame = Arc::<[u8]>::from(vec![1, 2, 3]);
+        let mut posted = posted_messages(|port| {
+            let deadline = Instant::now();
+            // Safe: the object is never received
+            let stale =
+                CObject::external_typed_data(unsafe { SharedTypedData::new(frame.clone()) });
+            assert!(!port.post_if_before(stale, deadline).unwrap());
+            assert_eq!(Arc::strong_count(&frame), 1);
+
+            let deadline = Instant::now() + Duration::from_secs(10);
+            assert!(port.post_if_before(CObject::int64(1), deadline).unwrap());
+        });
+
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].as_mut().as_int(rt), Some(1));
+    }
+
+    #[test]
+    fn test_post_cobject_slice() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut first = CObject::external_typed_data(TypedData::Uint8(vec![1, 2]));
+        let mut second = CObject::int64(3);
+        let mut third = CObject::external_typed_data(TypedData::Int16(vec![4]));
+        let mut messages = [first.as_mut(), second.as_mut(), third.as_mut()];
+        let mut posted = posted_messages(|port| {
+            assert_eq!(port.post_cobject_slice(&mut messages).unwrap(), 3);
+        });
+        assert!(messages[0].as_null(rt).is_some());
+        assert_eq!(messages[1].as_int(rt), Some(3));
+        assert!(messages[2].as_null(rt).is_some());
+
+        assert_eq!(posted.len(), 3);
+        assert!(matches!(
+            posted[0].as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8(&[1, 2])), _))
+        ));
+        assert_eq!(posted[1].as_mut().as_int(rt), Some(3));
+        assert!(matches!(
+            posted[2].as_mut().as_typed_data(rt),
+            Some((Ok(TypedDataRef::Int16(&[4])), _))
+        ));
+
+        let port = rt.send_port_from_raw(unique_port_id()).unwrap();
+        let mut unsent = CObject::external_typed_data(TypedData::Uint8(vec![5]));
+        let err = port.post_cobject_slice(&mut [unsent.as_mut()]).unwrap_err();
+        assert_eq!(err.posted, 0);
+        assert_eq!(err.reason.kind(), &PostingFailureKind::Rejected);
+        assert!(unsent.as_mut().as_typed_data(rt).is_some());
+    }
+
+    #[test]
+    fn test_post_cobject_slice_reports_posted_count() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut first = CObject::int64(1);
+        // the loopback rejects objects it can't copy
+        let mut unknown = Dart_CObject {
+            type_: Dart_CObject_Type::Dart_CObject_kUnsupported,
+            value: _Dart_CObject__bindgen_ty_1 { as_int64: 0 },
+        };
+        let mut last = CObject::external_typed_data(TypedData::Uint8(vec![3]));
+        let mut posted = posted_messages(|port| {
+            // Safe: the unknown object has no data which could be read
+            unsafe {
+                CObjectMut::with_pointer(&mut unknown, |unknown| {
+                    let mut messages = [first.as_mut(), unknown, last.as_mut()];
+                    let err = port.post_cobject_slice(&mut messages).unwrap_err();
+                    assert_eq!(err.posted, 1);
+                    assert_eq!(err.reason.kind(), &PostingFailureKind::Rejected);
+                })
+            };
+        });
+
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].as_mut().as_int(rt), Some(1));
+        // posting stopped at the first failure
+        assert!(last.as_mut().as_typed_data(rt).is_some());
+    }
+
+    #[test]
+    fn test_post_as_array() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut first = CObject::int64(1);
+        let mut second = CObject::external_typed_data(TypedData::Uint8(vec![2, 2]));
+        let mut messages = [first.as_mut(), second.as_mut()];
+        let mut posted = posted_messages(|port| {
+            port.post_as_array(&mut messages).unwrap();
+            port.post_as_array(&mut []).unwrap();
+        });
+        assert_eq!(messages[0].as_int(rt), Some(1));
+        assert!(messages[1].as_null(rt).is_some());
+
+        assert_eq!(posted.len(), 2);
+        let received = posted[0].as_mut();
+        let array = received.as_array(rt).unwrap();
+        assert_eq!(array.len(), 2);
+        assert_eq!(array[0].as_int(rt), Some(1));
+        assert!(matches!(
+            array[1].as_typed_data(rt),
+            Some((Ok(TypedDataRef::Uint8(&[2, 2])), _))
+        ));
+        assert_eq!(posted[1].as_mut().as_array(rt).map(<[_]>::len), Some(0));
+
+        let port = rt.send_port_from_raw(unique_port_id()).unwrap();
+        let mut unsent = CObject::external_typed_data(TypedData::Uint8(vec![3]));
+        assert!(port.post_as_array(&mut [unsent.as_mut()]).is_err());
+        assert!(unsent.as_mut().as_typed_data(rt).is_some());
+    }
+
+    #[test]
+    fn test_id_string_round_trips() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for id in [1, 0x1234_abcd, -1, DartPortId::MIN, DartPortId::MAX] {
+            let port = rt.send_port_from_raw_with_origin(id, 20).unwrap();
+            let parsed = rt.send_port_from_id_string(&port.id_string()).unwrap();
+            assert_eq!(parsed.as_raw(), (id, ILLEGAL_PORT));
+        }
+        assert_eq!(
+            rt.send_port_from_raw(-1).unwrap().id_string(),
+            "ffffffffffffffff"
+        );
+    }
+
+    #[test]
+    fn test_malformed_id_string_is_rejected() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for id in ["", "0", "+1", "-1", "xyz", " 1", "1ffffffffffffffff"] {
+            assert!(rt.send_port_from_id_string(id).is_none(), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn test_port_constructors_use_is_illegal_port() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for id in [ILLEGAL_PORT, unique_port_id()] {
+            let legal = !is_illegal_port(id);
+            assert_eq!(rt.send_port_from_raw(id).is_some(), legal);
+            assert_eq!(rt.send_port_from_raw_with_origin(id, 20).is_some(), legal);
+            assert_eq!(
+                rt.native_recv_port_from_raw(id)
+                    .map(NativeRecvPort::leak)
+                    .is_some(),
+                legal
+            );
+            assert_eq!(
+                unsafe { rt.adopt_recv_port(id) }
+                    .map(NativeRecvPort::leak)
+                    .is_some(),
+                legal
+            );
+            let mut cobject = CObject::raw_send_port(id, ILLEGAL_PORT);
+            assert_eq!(cobject.as_mut().as_send_port(rt).unwrap().is_some(), legal);
+        }
+        assert!(is_illegal_port(ILLEGAL_PORT));
+    }
+
+    #[test]
+    fn test_adopted_port_is_closed_on_drop() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let id = unique_port_id();
+        assert!(unsafe { rt.adopt_recv_port(ILLEGAL_PORT) }.is_none());
+        let port = unsafe { rt.adopt_recv_port(id) }.unwrap();
+        assert_eq!(port.as_raw().0, id);
+        assert!(!was_closed(id));
+        drop(port);
+        assert!(was_closed(id));
+    }
+
+    #[test]
+    fn test_without_origin_drops_origin() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw_with_origin(10, 20).unwrap();
+        assert_eq!(port.without_origin().as_raw(), (10, ILLEGAL_PORT));
+
+        let mut cobject = CObject::send_port(port.without_origin());
+        let received = cobject.as_mut().as_send_port(rt).unwrap().unwrap();
+        assert_eq!(received.as_raw(), (10, ILLEGAL_PORT));
+        assert_eq!(
+            rt.send_port_from_raw(10).unwrap().as_raw(),
+            (10, ILLEGAL_PORT)
+        );
+    }
+
+    #[test]
+    fn test_same_target_ignores_origin() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw(10).unwrap();
+        let with_origin = rt.send_port_from_raw_with_origin(10, 20).unwrap();
+        let other_origin = rt.send_port_from_raw_with_origin(10, 30).unwrap();
+        assert!(port.same_target(&with_origin));
+        assert!(with_origin.same_target(&other_origin));
+        assert_eq!(port.as_raw().0, with_origin.as_raw().0);
+
+        let other = rt.send_port_from_raw_with_origin(11, 20).unwrap();
+        assert!(!with_origin.same_target(&other));
+    }
+
+    static NULL_HANDLED: AtomicBool = AtomicBool::new(false);
+
+    struct NullChecker;
+
+    impl NativeMessageHandler for NullChecker {
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "null checker";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {
+            NULL_HANDLED.store(true, Ordering::SeqCst);
+        }
+    }
+
+    thread_local! {
+        static VIOLATIONS: RefCell<Vec<ContractViolation>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(violation: ContractViolation) {
+        VIOLATIONS.with(|violations| violations.borrow_mut().push(violation));
+    }
+
+    #[test]
+    fn test_null_message_is_ignored() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        set_contract_violation_sink(record);
+        let port = unique_port_id();
+        unsafe { handle_raw_message::<NullChecker>(rt, port, ptr::null_mut()) };
+        assert!(!NULL_HANDLED.load(Ordering::SeqCst));
+        assert_eq!(
+            VIOLATIONS.with(RefCell::take),
+            [ContractViolation::NullMessage { port }]
+        );
+    }
 }
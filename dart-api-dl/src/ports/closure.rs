@@ -0,0 +1,224 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Native ports whose messages are handled by closures.
+
+use std::{
+    marker::PhantomData,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    DartRuntime,
+};
+
+use super::{state, NativeMessageHandler, NativeRecvPort, PortCreationFailed};
+
+type MessageFn = Box<dyn Fn(DartRuntime, &NativeRecvPort, CObjectMut<'_>) + Send + Sync>;
+type PanicFn = Box<dyn Fn(DartRuntime, &NativeRecvPort, CObjectMut<'_>, CObject) + Send + Sync>;
+
+/// The closures of a port created with [`native_recv_port_closures()`].
+struct Closures {
+    handle_message: MessageFn,
+    handle_panic: PanicFn,
+}
+
+/// Configures a port whose messages are handled by closures.
+pub(super) trait ClosurePort {
+    /// See [`NativeMessageHandler::CONCURRENT_HANDLING`].
+    const CONCURRENT_HANDLING: bool;
+
+    /// See [`NativeMessageHandler::NAME`].
+    const NAME: &'static str;
+}
+
+/// Calls the closures stored with the port.
+pub(super) struct ClosureHandler<P>(PhantomData<fn() -> P>);
+
+impl<P> NativeMessageHandler for ClosureHandler<P>
+where
+    P: ClosurePort,
+{
+    const CONCURRENT_HANDLING: bool = P::CONCURRENT_HANDLING;
+    const NAME: &'static str = P::NAME;
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        if let Some(closures) = state::get::<Closures>(ourself.as_raw().0) {
+            (closures.handle_message)(rt, ourself, data);
+        }
+    }
+
+    fn handle_panic(
+        rt: DartRuntime,
+        ourself: &NativeRecvPort,
+        data: CObjectMut<'_>,
+        panic: CObject,
+    ) {
+        if let Some(closures) = state::get::<Closures>(ourself.as_raw().0) {
+            (closures.handle_panic)(rt, ourself, data, panic);
+        }
+    }
+}
+
+/// Creates a native receive port whose messages and panics are handled by closures.
+///
+/// The closures are dropped when the returned [`NativeRecvPort`] is dropped.
+pub(super) fn native_recv_port_closures<P>(
+    rt: DartRuntime,
+    handle_message: impl Fn(DartRuntime, &NativeRecvPort, CObjectMut<'_>) + Send + Sync + 'static,
+    handle_panic: impl Fn(DartRuntime, &NativeRecvPort, CObjectMut<'_>, CObject) + Send + Sync + 'static,
+) -> Result<NativeRecvPort, PortCreationFailed>
+where
+    P: ClosurePort,
+{
+    let port = rt.native_recv_port::<ClosureHandler<P>>()?;
+    state::insert(
+        port.as_raw().0,
+        Closures {
+            handle_message: Box::new(handle_message),
+            handle_panic: Box::new(handle_panic),
+        },
+    );
+    Ok(port)
+}
+
+struct Sequential;
+
+impl ClosurePort for Sequential {
+    const CONCURRENT_HANDLING: bool = false;
+    const NAME: &'static str = "closure";
+}
+
+struct Concurrent;
+
+impl ClosurePort for Concurrent {
+    const CONCURRENT_HANDLING: bool = true;
+    const NAME: &'static str = "concurrent closure";
+}
+
+impl DartRuntime {
+    /// Creates a native receive port whose messages are handled by a closure.
+    ///
+    /// The messages are handled one at a time, so the closure only needs to be
+    /// `Send` and can mutate its captured state. Use
+    /// [`DartRuntime::concurrent_native_recv_port_fn()`] for concurrent handling.
+    ///
+    /// The closure is dropped when the returned [`NativeRecvPort`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If creating the port failed.
+    pub fn native_recv_port_fn(
+        &self,
+        handler: impl FnMut(DartRuntime, CObjectMut<'_>) + Send + 'static,
+    ) -> Result<NativeRecvPort, PortCreationFailed> {
+        let handler = Mutex::new(handler);
+        native_recv_port_closures::<Sequential>(
+            *self,
+            // messages are handled one at a time, so this never blocks
+            move |rt, _, data| (handler.lock().unwrap_or_else(PoisonError::into_inner))(rt, data),
+            |_, _, _, _| {},
+        )
+    }
+
+    /// Creates a native receive port whose messages are handled concurrently by a closure.
+    ///
+    /// Like with [`NativeMessageHandler::CONCURRENT_HANDLING`] the closure is called
+    /// on dart's thread pool, possibly on multiple threads at once. Hence it must be
+    /// `Sync`, which makes data races on the captured state a compile time error:
+    ///
+    /// ```compile_fail,E0277
+    /// # use std::cell::Cell;
+    /// # use xayn_dart_api_dl::DartRuntime;
+    /// # fn create(rt: DartRuntime) {
+    /// let count = Cell::new(0);
+    /// rt.concurrent_native_recv_port_fn(move |_, _| count.set(count.get() + 1));
+    /// # }
+    /// ```
+    ///
+    /// The closure is dropped when the returned [`NativeRecvPort`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If creating the port failed.
+    pub fn concurrent_native_recv_port_fn(
+        &self,
+        handler: impl Fn(DartRuntime, CObjectMut<'_>) + Send + Sync + 'static,
+    ) -> Result<NativeRecvPort, PortCreationFailed> {
+        native_recv_port_closures::<Concurrent>(
+            *self,
+            move |rt, _, data| handler(rt, data),
+            |_, _, _, _| {},
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        sync::{
+            atomic::{AtomicI64, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{cobject::CObject, ports::dispatch_message};
+
+    use super::*;
+
+    #[test]
+    fn test_sequential_closure_mutates_state() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        // `Cell` is `Send` but not `Sync`
+        let sum = Cell::new(0);
+        let port = rt
+            .native_recv_port_fn(move |rt, data| {
+                sum.set(sum.get() + data.as_int(rt).unwrap());
+                sender.send(sum.get()).unwrap();
+            })
+            .unwrap();
+        let id = port.as_raw().0;
+        for value in 1..=3 {
+            dispatch_message::<ClosureHandler<Sequential>>(rt, id, CObject::int64(value).as_mut());
+        }
+        drop(port);
+        dispatch_message::<ClosureHandler<Sequential>>(rt, id, CObject::int64(10).as_mut());
+
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), [1, 3, 6]);
+    }
+
+    #[test]
+    fn test_concurrent_closure() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let sum = Arc::new(AtomicI64::new(0));
+        let port = rt
+            .concurrent_native_recv_port_fn({
+                let sum = sum.clone();
+                move |rt, data| {
+                    sum.fetch_add(data.as_int(rt).unwrap(), Ordering::SeqCst);
+                }
+            })
+            .unwrap();
+        let id = port.as_raw().0;
+        for value in 1..=3 {
+            dispatch_message::<ClosureHandler<Concurrent>>(rt, id, CObject::int64(value).as_mut());
+        }
+        drop(port);
+        assert_eq!(sum.load(Ordering::SeqCst), 6);
+        assert_eq!(Arc::strong_count(&sum), 1);
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Concurrency metrics of native message handlers.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::{state, DartPortId};
+
+/// The concurrency of a native port created by [`DartRuntime::native_recv_port()`].
+///
+/// [`DartRuntime::native_recv_port()`]: crate::DartRuntime::native_recv_port
+#[derive(Default)]
+struct Concurrency {
+    /// Number of messages which are being handled right now.
+    current: usize,
+    /// Highest observed number of messages handled at the same time.
+    peak: usize,
+}
+
+/// A message which is being handled, ends counting it when dropped.
+pub(super) struct Invocation(Arc<Mutex<Concurrency>>);
+
+impl Drop for Invocation {
+    fn drop(&mut self) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .current -= 1;
+    }
+}
+
+/// Starts recording the concurrency of a new port.
+pub(super) fn init(id: DartPortId) {
+    state::insert::<Mutex<Concurrency>>(id, Mutex::default());
+}
+
+/// Counts a message as being handled until the returned invocation is dropped.
+///
+/// Returns `None` if the port was already dropped, messages can still be
+/// handled after that.
+pub(super) fn enter(id: DartPortId) -> Option<Invocation> {
+    let concurrency = state::get::<Mutex<Concurrency>>(id)?;
+    let mut counts = concurrency.lock().unwrap_or_else(PoisonError::into_inner);
+    counts.current += 1;
+    counts.peak = counts.peak.max(counts.current);
+    drop(counts);
+    Some(Invocation(concurrency))
+}
+
+pub(super) fn peak(id: DartPortId) -> usize {
+    state::get::<Mutex<Concurrency>>(id).map_or(0, |concurrency| {
+        concurrency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .peak
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use once_cell::sync::Lazy;
+
+    use crate::{
+        cobject::{CObject, CObjectMut},
+        ports::{dispatch_message, NativeMessageHandler, NativeRecvPort},
+        test_utils::unique_port_id,
+        DartRuntime,
+    };
+
+    use super::*;
+
+    static OVERLAP: Lazy<Barrier> = Lazy::new(|| Barrier::new(2));
+
+    struct Overlapping;
+
+    impl NativeMessageHandler for Overlapping {
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "overlapping";
+
+        fn handle_message(rt: DartRuntime, _: &NativeRecvPort, data: CObjectMut<'_>) {
+            if data.as_bool(rt) == Some(true) {
+                OVERLAP.wait();
+            }
+        }
+
+        fn handle_panic(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>, _: CObject) {}
+    }
+
+    #[test]
+    fn test_peak_concurrency_reflects_overlap() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.native_recv_port::<Overlapping>().unwrap();
+        let id = port.as_raw().0;
+        assert_eq!(port.peak_concurrency(), 0);
+
+        dispatch_message::<Overlapping>(rt, id, CObject::bool(false).as_mut());
+        assert_eq!(port.peak_concurrency(), 1);
+
+        let handlers = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    dispatch_message::<Overlapping>(rt, id, CObject::bool(true).as_mut());
+                })
+            })
+            .collect::<Vec<_>>();
+        for handler in handlers {
+            handler.join().unwrap();
+        }
+        assert_eq!(port.peak_concurrency(), 2);
+
+        dispatch_message::<Overlapping>(rt, id, CObject::bool(false).as_mut());
+        assert_eq!(port.peak_concurrency(), 2);
+
+        drop(port);
+        assert_eq!(peak(id), 0);
+    }
+
+    #[test]
+    fn test_dropped_port_is_not_counted() {
+        let id = unique_port_id();
+        assert!(enter(id).is_none());
+        assert!(state::get::<Mutex<Concurrency>>(id).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Native ports whose handler has access to per-port context.
+
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    DartRuntime,
+};
+
+use super::{
+    closure::{native_recv_port_closures, ClosurePort},
+    NativeRecvPort,
+    PortCreationFailed,
+};
+
+/// Like [`NativeMessageHandler`] but with a context stored with each port.
+///
+/// The context is passed to the handler in addition to the message, which
+/// allows e.g. holding per-port configuration without global statics.
+///
+/// Ports are created with [`DartRuntime::native_recv_port_with_context()`].
+///
+/// [`NativeMessageHandler`]: super::NativeMessageHandler
+pub trait ContextMessageHandler {
+    /// The context stored with each port.
+    ///
+    /// It's dropped when the [`NativeRecvPort`] is dropped.
+    type Context: Send + Sync + 'static;
+
+    /// See [`NativeMessageHandler::CONCURRENT_HANDLING`].
+    ///
+    /// [`NativeMessageHandler::CONCURRENT_HANDLING`]: super::NativeMessageHandler::CONCURRENT_HANDLING
+    const CONCURRENT_HANDLING: bool;
+
+    /// See [`NativeMessageHandler::NAME`].
+    ///
+    /// [`NativeMessageHandler::NAME`]: super::NativeMessageHandler::NAME
+    const NAME: &'static str;
+
+    /// Called when handling a message with the context of the port.
+    ///
+    /// See [`NativeMessageHandler::handle_message()`].
+    ///
+    /// [`NativeMessageHandler::handle_message()`]: super::NativeMessageHandler::handle_message
+    fn handle_message(
+        ctx: &Self::Context,
+        rt: DartRuntime,
+        ourself: &NativeRecvPort,
+        data: CObjectMut<'_>,
+    );
+
+    /// Called with the context of the port if handling a message failed.
+    ///
+    /// See [`NativeMessageHandler::handle_panic()`], like there the panic is
+    /// dropped silently by default.
+    ///
+    /// [`NativeMessageHandler::handle_panic()`]: super::NativeMessageHandler::handle_panic
+    fn handle_panic(
+        ctx: &Self::Context,
+        rt: DartRuntime,
+        ourself: &NativeRecvPort,
+        data: CObjectMut<'_>,
+        panic: CObject,
+    ) {
+        let _ = (ctx, rt, ourself, data, panic);
+    }
+}
+
+/// Configures the closure port of `C`.
+struct WithContext<C>(PhantomData<fn() -> C>);
+
+impl<C> ClosurePort for WithContext<C>
+where
+    C: ContextMessageHandler,
+{
+    const CONCURRENT_HANDLING: bool = C::CONCURRENT_HANDLING;
+    const NAME: &'static str = C::NAME;
+}
+
+impl DartRuntime {
+    /// Creates a native receive port whose handler is called with `ctx`.
+    ///
+    /// Each port has its own context, so multiple ports with the same
+    /// handler can e.g. use different configurations. The port is a closure
+    /// port, see [`DartRuntime::native_recv_port_fn()`], whose closures own
+    /// the context.
+    ///
+    /// # Errors
+    ///
+    /// If creating the port failed.
+    pub fn native_recv_port_with_context<C>(
+        &self,
+        ctx: C::Context,
+    ) -> Result<NativeRecvPort, PortCreationFailed>
+    where
+        C: ContextMessageHandler + 'static,
+    {
+        let ctx = Arc::new(ctx);
+        let panic_ctx = ctx.clone();
+        native_recv_port_closures::<WithContext<C>>(
+            *self,
+            move |rt, ourself, data| C::handle_message(&ctx, rt, ourself, data),
+            move |rt, ourself, data, panic| C::handle_panic(&panic_ctx, rt, ourself, data, panic),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::ports::{closure::ClosureHandler, dispatch_message};
+
+    use super::*;
+
+    struct Scaled;
+
+    struct Scale {
+        factor: i64,
+        seen: Arc<Mutex<Vec<i64>>>,
+    }
+
+    impl ContextMessageHandler for Scaled {
+        type Context = Scale;
+
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "scaled";
+
+        fn handle_message(
+            ctx: &Scale,
+            rt: DartRuntime,
+            _ourself: &NativeRecvPort,
+            data: CObjectMut<'_>,
+        ) {
+            let value = data.as_int(rt).unwrap() * ctx.factor;
+            ctx.seen.lock().unwrap().push(value);
+        }
+    }
+
+    #[test]
+    fn test_each_port_sees_its_own_context() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let ports = [2, 10].map(|factor| {
+            rt.native_recv_port_with_context::<Scaled>(Scale {
+                factor,
+                seen: seen.clone(),
+            })
+            .unwrap()
+        });
+        for (port, value) in ports.iter().zip([1, 3]) {
+            let id = port.as_raw().0;
+            dispatch_message::<ClosureHandler<WithContext<Scaled>>>(
+                rt,
+                id,
+                CObject::int64(value).as_mut(),
+            );
+        }
+        assert_eq!(*seen.lock().unwrap(), [2, 30]);
+
+        let id = ports[0].as_raw().0;
+        drop(ports);
+        assert_eq!(Arc::strong_count(&seen), 1);
+        dispatch_message::<ClosureHandler<WithContext<Scaled>>>(rt, id, CObject::int64(5).as_mut());
+        assert_eq!(*seen.lock().unwrap(), [2, 30]);
+    }
+}
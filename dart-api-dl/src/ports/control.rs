@@ -0,0 +1,142 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conventional control messages.
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    utils::quietly,
+    DartRuntime,
+};
+
+use super::{PostingMessageFailed, SendPort};
+
+/// Marker used as first element of a control message.
+const CONTROL_MARKER: &str = "__control__";
+
+/// A control message, like a request to shut down.
+///
+/// Control messages are sent as an array of the form `["__control__", name]`,
+/// which makes them distinguishable from app messages as long as those don't
+/// use the same marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ControlMessage {
+    /// Requests the receiver to shut down.
+    Shutdown,
+    /// Checks if the receiver is alive.
+    Ping,
+}
+
+impl ControlMessage {
+    fn name(self) -> &'static str {
+        match self {
+            ControlMessage::Shutdown => "shutdown",
+            ControlMessage::Ping => "ping",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shutdown" => Some(ControlMessage::Shutdown),
+            "ping" => Some(ControlMessage::Ping),
+            _ => None,
+        }
+    }
+}
+
+impl From<ControlMessage> for CObject {
+    fn from(message: ControlMessage) -> Self {
+        CObject::array(vec![
+            Box::new(CObject::string_lossy(CONTROL_MARKER)),
+            Box::new(CObject::string_lossy(message.name())),
+        ])
+    }
+}
+
+impl SendPort {
+    /// Posts a [`ControlMessage`] to this port.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_control(&self, message: ControlMessage) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(message.into())
+    }
+
+    /// Posts a [`ControlMessage::Shutdown`] to this port.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_shutdown(&self) -> Result<(), PostingMessageFailed> {
+        self.post_control(ControlMessage::Shutdown)
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns `Some` if the object is a [`ControlMessage`].
+    pub fn as_control(&self, rt: DartRuntime) -> Option<ControlMessage> {
+        quietly(|| match self.as_array(rt)? {
+            [marker, name] if marker.as_string(rt) == Some(CONTROL_MARKER) => {
+                ControlMessage::from_name(name.as_string(rt)?)
+            }
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[test]
+    fn test_post_and_recognize_shutdown() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut messages = posted_messages(|port| {
+            port.post_shutdown().unwrap();
+            port.post_control(ControlMessage::Ping).unwrap();
+        });
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].as_mut().as_control(rt),
+            Some(ControlMessage::Shutdown)
+        );
+        assert_eq!(
+            messages[1].as_mut().as_control(rt),
+            Some(ControlMessage::Ping)
+        );
+    }
+
+    #[test]
+    fn test_app_messages_are_not_control_messages() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        for mut message in [
+            CObject::string("shutdown").unwrap(),
+            CObject::array(vec![
+                Box::new(CObject::string("app").unwrap()),
+                Box::new(CObject::string("shutdown").unwrap()),
+            ]),
+            CObject::array(vec![
+                Box::new(CObject::string(CONTROL_MARKER).unwrap()),
+                Box::new(CObject::string("unknown").unwrap()),
+            ]),
+        ] {
+            assert_eq!(message.as_mut().as_control(rt), None);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Request/response messaging multiplexed over a single reply port.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+        MutexGuard,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    cobject::{CObject, CObjectMut, SendableCopy},
+    DartRuntime,
+};
+
+use super::{
+    DartPortId,
+    NativeMessageHandler,
+    NativeRecvPort,
+    PortCreationFailed,
+    PostingMessageFailed,
+    SendPort,
+};
+
+/// Senders for the acks which are awaited, by reply port and correlation id.
+type Pending = HashMap<(DartPortId, i64), Sender<SendableCopy>>;
+
+static PENDING: Lazy<Mutex<Pending>> = Lazy::new(Default::default);
+
+/// Sends requests tagged with a correlation id and matches the acks to them.
+///
+/// Each request is posted as `[reply_port, id, payload]`, the receiver is
+/// expected to ack it by posting `[id, payload]` to the `reply_port`. Acks can
+/// arrive in any order. Acks with an unknown or malformed id are ignored.
+#[derive(Debug)]
+pub struct CorrelatingSender {
+    target: SendPort,
+    reply: NativeRecvPort,
+    next_id: AtomicI64,
+}
+
+impl CorrelatingSender {
+    /// Creates a new sender for requests to the `target` port.
+    ///
+    /// # Errors
+    ///
+    /// If creating the reply port failed.
+    pub fn new(rt: DartRuntime, target: SendPort) -> Result<Self, PortCreationFailed> {
+        Ok(Self {
+            target,
+            reply: rt.native_recv_port::<AckHandler>()?,
+            next_id: AtomicI64::new(0),
+        })
+    }
+
+    /// Returns the port to which acks are posted.
+    pub fn reply_port(&self) -> SendPort {
+        *self.reply
+    }
+
+    /// Posts a request with a new correlation id.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn send(&self, payload: CObject) -> Result<PendingAck, PostingMessageFailed> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let key = (self.reply.as_raw().0, id);
+        let (sender, receiver) = channel();
+        // register before posting, the ack might arrive before posting returns
+        pending().insert(key, sender);
+
+        let request = CObject::array(vec![
+            Box::new(CObject::send_port(self.reply_port())),
+            Box::new(CObject::int64(id)),
+            Box::new(payload),
+        ]);
+        if let Err(err) = self.target.post_cobject(request) {
+            pending().remove(&key);
+            return Err(err);
+        }
+        Ok(PendingAck { key, receiver })
+    }
+}
+
+impl Drop for CorrelatingSender {
+    fn drop(&mut self) {
+        let reply = self.reply.as_raw().0;
+        pending().retain(|(port, _), _| *port != reply);
+    }
+}
+
+/// An ack which is awaited, see [`CorrelatingSender::send()`].
+#[derive(Debug)]
+pub struct PendingAck {
+    key: (DartPortId, i64),
+    receiver: Receiver<SendableCopy>,
+}
+
+impl PendingAck {
+    /// The correlation id of the request.
+    pub fn id(&self) -> i64 {
+        self.key.1
+    }
+
+    /// Blocks until the ack is received or `timeout` elapsed.
+    ///
+    /// Returns `None` on timeout or if the [`CorrelatingSender`] was dropped.
+    pub fn wait(self, timeout: Duration) -> Option<CObject> {
+        self.receiver
+            .recv_timeout(timeout)
+            .ok()
+            .map(SendableCopy::into_inner)
+    }
+}
+
+impl Drop for PendingAck {
+    fn drop(&mut self) {
+        pending().remove(&self.key);
+    }
+}
+
+fn pending() -> MutexGuard<'static, Pending> {
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct AckHandler;
+
+impl NativeMessageHandler for AckHandler {
+    const CONCURRENT_HANDLING: bool = true;
+    const NAME: &'static str = "correlating-sender-acks";
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        if let Some([id, payload]) = data.as_array(rt) {
+            let sender = id
+                .as_int(rt)
+                .and_then(|id| pending().remove(&(ourself.as_raw().0, id)));
+            if let (Some(sender), Ok(payload)) = (sender, payload.sendable_copy(rt.into())) {
+                let _ = sender.send(payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ports::dispatch_message, test_utils::loopback};
+
+    use super::*;
+
+    #[test]
+    fn test_acks_are_matched_out_of_order() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (loopback, inbox) = loopback();
+        let sender =
+            CorrelatingSender::new(rt, rt.send_port_from_raw(loopback.id()).unwrap()).unwrap();
+        let first = sender.send(CObject::string("first").unwrap()).unwrap();
+        let second = sender.send(CObject::string("second").unwrap()).unwrap();
+        assert_ne!(first.id(), second.id());
+        drop(loopback);
+
+        let mut requests = Vec::new();
+        while let Some(mut request) = inbox.recv() {
+            let request = request.as_mut();
+            let request = request.as_array(rt).unwrap();
+            let reply = request[0].as_send_port(rt).unwrap().unwrap();
+            assert!(reply.same_target(&sender.reply_port()));
+            let id = request[1].as_int(rt).unwrap();
+            let payload = request[2].as_string(rt).unwrap().to_owned();
+            requests.push((id, payload));
+        }
+        assert_eq!(requests.len(), 2);
+
+        for (id, payload) in requests.into_iter().rev() {
+            let mut ack = CObject::array(vec![
+                Box::new(CObject::int64(id)),
+                Box::new(CObject::string(format!("ack {payload}")).unwrap()),
+            ]);
+            dispatch_message::<AckHandler>(rt, sender.reply.as_raw().0, ack.as_mut());
+        }
+
+        let timeout = Duration::from_secs(1);
+        let mut first = first.wait(timeout).unwrap();
+        assert_eq!(first.as_mut().as_string(rt), Some("ack first"));
+        let mut second = second.wait(timeout).unwrap();
+        assert_eq!(second.as_mut().as_string(rt), Some("ack second"));
+    }
+}
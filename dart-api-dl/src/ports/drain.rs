@@ -0,0 +1,227 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Best-effort draining of native receive ports.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    cobject::{CObject, CObjectMut, Capability},
+    utils::quietly,
+    DartRuntime,
+};
+
+use super::{DartPortId, NativeRecvPort};
+
+/// Sentinels of all ports which currently wait to be drained, by token.
+///
+/// Keying by token allows multiple threads to drain the same port at the same time.
+static SENTINELS: Lazy<Mutex<HashMap<Capability, Arc<Sentinel>>>> = Lazy::new(Default::default);
+
+/// Number of entries in [`SENTINELS`], used to keep the trampoline lock free in the common case.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Source for sentinel tokens, the start value is arbitrary but makes accidental
+/// collisions with capabilities of app messages less likely.
+static NEXT_TOKEN: AtomicI64 = AtomicI64::new(0x5e47_1e1d_0000_0000);
+
+struct Sentinel {
+    port: DartPortId,
+    token: Capability,
+    received: Mutex<bool>,
+    condvar: Condvar,
+}
+
+pub(super) fn drain(port: &NativeRecvPort, timeout: Duration) -> bool {
+    let sentinel = Arc::new(Sentinel {
+        port: port.as_raw().0,
+        token: NEXT_TOKEN.fetch_add(1, Ordering::Relaxed),
+        received: Mutex::new(false),
+        condvar: Condvar::new(),
+    });
+    register(sentinel.token, Some(sentinel.clone()));
+
+    let received = port
+        .post_cobject(CObject::capability(sentinel.token))
+        .is_ok()
+        && {
+            let received = sentinel
+                .received
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let (received, _) = sentinel
+                .condvar
+                .wait_timeout_while(received, timeout, |received| !*received)
+                .unwrap_or_else(PoisonError::into_inner);
+            *received
+        };
+
+    register(sentinel.token, None);
+    received
+}
+
+fn register(token: Capability, sentinel: Option<Arc<Sentinel>>) {
+    let mut sentinels = SENTINELS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(sentinel) = sentinel {
+        sentinels.insert(token, sentinel);
+    } else {
+        sentinels.remove(&token);
+    }
+    PENDING.store(sentinels.len(), Ordering::Release);
+}
+
+/// Returns `true` if `data` is the drain sentinel of given port.
+///
+/// In which case the draining thread is woken up.
+pub(super) fn consume_sentinel(rt: DartRuntime, id: DartPortId, data: &CObjectMut<'_>) -> bool {
+    if PENDING.load(Ordering::Acquire) == 0 {
+        return false;
+    }
+    let token = match quietly(|| data.as_capability(rt)) {
+        Some(token) => token,
+        None => return false,
+    };
+    let sentinel = SENTINELS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&token)
+        .filter(|sentinel| sentinel.port == id)
+        .cloned();
+    if let Some(sentinel) = sentinel {
+        *sentinel
+            .received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = true;
+        sentinel.condvar.notify_all();
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{
+        ports::{dispatch_message, NativeMessageHandler},
+        test_utils::{loopback, unique_port_id, was_closed},
+    };
+
+    use super::*;
+
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counter;
+
+    impl NativeMessageHandler for Counter {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "counter";
+
+        fn handle_message(rt: DartRuntime, _ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+            assert!(data.as_int(rt).is_some());
+            HANDLED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn handle_panic(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>, _: CObject) {
+            panic!("unexpected message");
+        }
+    }
+
+    #[test]
+    fn test_drain_on_drop_handles_enqueued_messages() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (loopback, inbox) = loopback();
+        let id = loopback.id();
+        let dart = thread::spawn(move || {
+            while let Some(mut message) = inbox.recv() {
+                dispatch_message::<Counter>(rt, id, message.as_mut());
+            }
+        });
+
+        let port = rt
+            .native_recv_port_from_raw(id)
+            .unwrap()
+            .with_drain_on_drop(Duration::from_secs(10));
+        for message in 0..3 {
+            port.post_integer(message).unwrap();
+        }
+        drop(port);
+
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 3);
+        assert!(was_closed(id));
+        drop(loopback);
+        dart.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_drains_of_the_same_port() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (loopback, inbox) = loopback();
+        let id = loopback.id();
+        let drains = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    let port = rt.native_recv_port_from_raw(id).unwrap();
+                    let drained = port.drain(Duration::from_secs(10));
+                    port.leak();
+                    drained
+                })
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..2 {
+            let mut message = inbox.recv().unwrap();
+            assert!(consume_sentinel(rt, id, &message.as_mut()));
+        }
+
+        for drain in drains {
+            assert!(drain.join().unwrap());
+        }
+        drop(loopback);
+    }
+
+    #[test]
+    fn test_drain_on_drop_timeout_is_capped() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt
+            .native_recv_port_from_raw(unique_port_id())
+            .unwrap()
+            .with_drain_on_drop(Duration::from_secs(30));
+        assert_eq!(
+            port.drain_on_drop,
+            Some(NativeRecvPort::MAX_DRAIN_ON_DROP_TIMEOUT)
+        );
+        port.leak();
+    }
+
+    #[test]
+    fn test_drain_times_out_if_sentinel_is_not_handled() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (loopback, _inbox) = loopback();
+        let port = rt.native_recv_port_from_raw(loopback.id()).unwrap();
+        assert!(!port.drain(Duration::from_millis(10)));
+    }
+}
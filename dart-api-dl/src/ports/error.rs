@@ -0,0 +1,160 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conventional error messages with a machine-readable code.
+
+use std::error::Error;
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    utils::quietly,
+    DartRuntime,
+};
+
+use super::{PostingMessageFailed, SendPort};
+
+/// Marker used as first element of an error message.
+const ERROR_MARKER: &str = "__error__";
+
+impl SendPort {
+    /// Posts an error with a machine-readable `code` to this port.
+    ///
+    /// The error is sent as an array of the form `["__error__", code, message]`,
+    /// which allows the dart side to switch on the code instead of parsing the
+    /// message. The message is cut off at the first nul byte.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_error(&self, code: i64, message: &str) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(CObject::array(vec![
+            Box::new(CObject::string_lossy(ERROR_MARKER)),
+            Box::new(CObject::int64(code)),
+            Box::new(CObject::string_lossy(message)),
+        ]))
+    }
+}
+
+impl CObject {
+    /// Create a [`CObject`] describing an error together with its chain of sources.
+    ///
+    /// The error is encoded as an array of the form
+    /// `["__error__", message, [cause1, cause2, ...]]`, where the causes are the
+    /// messages of the [`Error::source()`] chain, starting with the direct source.
+    /// Messages are cut off at the first nul byte.
+    ///
+    /// See [`CObjectMut::as_error_chain()`].
+    pub fn from_error(err: &dyn Error) -> Self {
+        let causes = std::iter::successors(err.source(), |&cause| cause.source())
+            .map(|cause| Box::new(CObject::string_lossy(cause.to_string())))
+            .collect();
+        CObject::array(vec![
+            Box::new(CObject::string_lossy(ERROR_MARKER)),
+            Box::new(CObject::string_lossy(err.to_string())),
+            Box::new(CObject::array(causes)),
+        ])
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns the code and message if the object is an error.
+    ///
+    /// See [`SendPort::post_error()`].
+    pub fn as_error(&self, rt: DartRuntime) -> Option<(i64, &str)> {
+        quietly(|| match self.as_array(rt)? {
+            [marker, code, message] if marker.as_string(rt) == Some(ERROR_MARKER) => {
+                Some((code.as_int(rt)?, message.as_string(rt)?))
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the message and the messages of the causes if the object is an error chain.
+    ///
+    /// See [`CObject::from_error()`].
+    pub fn as_error_chain(&self, rt: DartRuntime) -> Option<(&str, Vec<&str>)> {
+        quietly(|| match self.as_array(rt)? {
+            [marker, message, causes] if marker.as_string(rt) == Some(ERROR_MARKER) => {
+                let causes = causes
+                    .as_array(rt)?
+                    .iter()
+                    .map(|cause| cause.as_string(rt))
+                    .collect::<Option<_>>()?;
+                Some((message.as_string(rt)?, causes))
+            }
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::{self, Display};
+
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Chained(&'static str, Option<Box<Chained>>);
+
+    impl Display for Chained {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Chained {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_deref().map(|source| source as _)
+        }
+    }
+
+    #[test]
+    fn test_post_error_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut errors = posted_messages(|port| port.post_error(404, "unknown command").unwrap());
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].as_mut().as_error(rt),
+            Some((404, "unknown command"))
+        );
+        assert_eq!(
+            CObject::string("Error: unknown command")
+                .unwrap()
+                .as_mut()
+                .as_error(rt),
+            None
+        );
+    }
+
+    #[test]
+    fn test_error_chain_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let err = Chained(
+            "loading the model failed",
+            Some(Box::new(Chained("file not found", None))),
+        );
+        let mut obj = CObject::from_error(&err);
+        assert_eq!(
+            obj.as_mut().as_error_chain(rt),
+            Some(("loading the model failed", vec!["file not found"]))
+        );
+        assert_eq!(obj.as_mut().as_error(rt), None);
+
+        let mut obj = CObject::from_error(&Chained("no cause", None));
+        assert_eq!(obj.as_mut().as_error_chain(rt), Some(("no cause", vec![])));
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Explicit handling of messages which couldn't be posted.
+
+use std::fmt::{self, Debug};
+
+use thiserror::Error;
+
+use crate::cobject::CObject;
+
+use super::{PostingFailure, SendPort};
+
+/// What happens with a message if posting it failed, see [`SendPort::post_cobject_with()`].
+pub enum FailurePolicy<'q> {
+    /// The message is dropped, like with [`SendPort::post_cobject()`].
+    Drop,
+    /// The message is returned in the error.
+    Return,
+    /// The message is pushed to the dead letter queue.
+    DeadLetter(&'q mut Vec<CObject>),
+}
+
+/// Posting a message failed, see [`SendPort::post_cobject_with()`].
+#[derive(Error)]
+#[error("{reason}")]
+pub struct PostFailed {
+    /// Why posting failed.
+    pub reason: PostingFailure,
+    /// The message if the [`FailurePolicy::Return`] was used.
+    pub returned: Option<CObject>,
+}
+
+impl Debug for PostFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostFailed")
+            .field("reason", &self.reason)
+            .field("returned", &self.returned.is_some())
+            .finish()
+    }
+}
+
+impl SendPort {
+    /// Posts the message and handles a failure according to the `policy`.
+    ///
+    /// See [`SendPort::try_post_cobject_mut()`] for details about posting.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_cobject_with(
+        &self,
+        mut cobject: CObject,
+        policy: FailurePolicy<'_>,
+    ) -> Result<(), PostFailed> {
+        let reason = match self.try_post_cobject_mut(cobject.as_mut()) {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        let returned = match policy {
+            FailurePolicy::Drop => None,
+            FailurePolicy::Return => Some(cobject),
+            FailurePolicy::DeadLetter(queue) => {
+                queue.push(cobject);
+                None
+            }
+        };
+        Err(PostFailed { reason, returned })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ports::PostingFailureKind, test_utils::unique_port_id, DartRuntime};
+
+    use super::*;
+
+    fn dead_port() -> SendPort {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        rt.send_port_from_raw(unique_port_id()).unwrap()
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let err = dead_port()
+            .post_cobject_with(CObject::int64(1), FailurePolicy::Drop)
+            .unwrap_err();
+        assert_eq!(err.reason.kind(), &PostingFailureKind::Rejected);
+        assert!(err.returned.is_none());
+    }
+
+    #[test]
+    fn test_return_policy() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let err = dead_port()
+            .post_cobject_with(CObject::int64(2), FailurePolicy::Return)
+            .unwrap_err();
+        let mut returned = err.returned.unwrap();
+        assert_eq!(returned.as_mut().as_int64(rt), Some(2));
+    }
+
+    #[test]
+    fn test_dead_letter_policy() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut dead_letters = Vec::new();
+        let port = dead_port();
+        for val in [3, 4] {
+            let err = port
+                .post_cobject_with(
+                    CObject::int64(val),
+                    FailurePolicy::DeadLetter(&mut dead_letters),
+                )
+                .unwrap_err();
+            assert!(err.returned.is_none());
+        }
+        let vals = dead_letters
+            .iter_mut()
+            .map(|letter| letter.as_mut().as_int64(rt))
+            .collect::<Vec<_>>();
+        assert_eq!(vals, [Some(3), Some(4)]);
+    }
+}
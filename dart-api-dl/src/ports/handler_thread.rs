@@ -0,0 +1,99 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of the threads on which dart runs message handlers.
+
+use std::cell::Cell;
+
+use crate::DartRuntime;
+
+thread_local! {
+    /// Number of message handlers currently running on this thread.
+    static HANDLER_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Marks the current thread as handler thread while alive.
+pub(super) struct HandlerThreadGuard;
+
+impl HandlerThreadGuard {
+    pub(super) fn enter() -> Self {
+        HANDLER_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        HandlerThreadGuard
+    }
+}
+
+impl Drop for HandlerThreadGuard {
+    fn drop(&mut self) {
+        HANDLER_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+impl DartRuntime {
+    /// Returns `true` if the current thread is running a [`NativeMessageHandler`].
+    ///
+    /// This is the case inside of both [`NativeMessageHandler::handle_message()`]
+    /// and [`NativeMessageHandler::handle_panic()`], but not on any threads spawned
+    /// from there.
+    ///
+    /// [`NativeMessageHandler`]: super::NativeMessageHandler
+    /// [`NativeMessageHandler::handle_message()`]: super::NativeMessageHandler::handle_message()
+    /// [`NativeMessageHandler::handle_panic()`]: super::NativeMessageHandler::handle_panic()
+    pub fn is_on_handler_thread() -> bool {
+        HANDLER_DEPTH.with(|depth| depth.get() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use crate::{
+        cobject::{CObject, CObjectMut},
+        ports::{dispatch_message, NativeMessageHandler, NativeRecvPort},
+        test_utils::unique_port_id,
+    };
+
+    use super::*;
+
+    static ON_HANDLER_THREAD: AtomicBool = AtomicBool::new(false);
+    static ON_SPAWNED_THREAD: AtomicBool = AtomicBool::new(true);
+
+    struct ThreadChecker;
+
+    impl NativeMessageHandler for ThreadChecker {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "thread-checker";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {
+            ON_HANDLER_THREAD.store(DartRuntime::is_on_handler_thread(), Ordering::SeqCst);
+            let spawned = thread::spawn(DartRuntime::is_on_handler_thread)
+                .join()
+                .unwrap();
+            ON_SPAWNED_THREAD.store(spawned, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_is_on_handler_thread() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        assert!(!DartRuntime::is_on_handler_thread());
+        dispatch_message::<ThreadChecker>(rt, unique_port_id(), CObject::null().as_mut());
+        assert!(ON_HANDLER_THREAD.load(Ordering::SeqCst));
+        assert!(!ON_SPAWNED_THREAD.load(Ordering::SeqCst));
+        assert!(!DartRuntime::is_on_handler_thread());
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transactional creation of native receive ports.
+
+use std::mem;
+
+use super::{NativeRecvPort, SendPort};
+
+/// Closes all ports created during an initialization if it doesn't complete.
+///
+/// Ports are [tracked](InitScope::track()) while they are created. If the scope is
+/// dropped without calling [`InitScope::commit()`], e.g. because the initialization
+/// returned early with an error or panicked, all tracked ports are closed in reverse
+/// order of creation. On commit all ports are [leaked](NativeRecvPort::leak()).
+#[derive(Debug, Default)]
+#[must_use]
+pub struct InitScope {
+    ports: Vec<NativeRecvPort>,
+}
+
+impl InitScope {
+    /// Creates an empty scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks the port, returning the [`SendPort`] to it.
+    pub fn track(&mut self, port: NativeRecvPort) -> SendPort {
+        let send_port = *port;
+        self.ports.push(port);
+        send_port
+    }
+
+    /// Completes the initialization, leaking all tracked ports.
+    ///
+    /// Returns the leaked ports in order of tracking.
+    pub fn commit(mut self) -> Vec<SendPort> {
+        mem::take(&mut self.ports)
+            .into_iter()
+            .map(NativeRecvPort::leak)
+            .collect()
+    }
+}
+
+impl Drop for InitScope {
+    fn drop(&mut self) {
+        while let Some(port) = self.ports.pop() {
+            drop(port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_utils::{unique_port_id, was_closed},
+        DartRuntime,
+    };
+
+    use super::*;
+
+    fn init(rt: DartRuntime, scope: &mut InitScope) -> Result<SendPort, &'static str> {
+        scope.track(rt.native_recv_port_from_raw(unique_port_id()).unwrap());
+        scope.track(rt.native_recv_port_from_raw(unique_port_id()).unwrap());
+        Err("failed after creating two ports")
+    }
+
+    #[test]
+    fn test_failed_init_closes_ports() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut scope = InitScope::new();
+        assert!(init(rt, &mut scope).is_err());
+        let ids = scope
+            .ports
+            .iter()
+            .map(|port| port.as_raw().0)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 2);
+        drop(scope);
+        assert!(ids.into_iter().all(was_closed));
+    }
+
+    #[test]
+    fn test_committed_ports_stay_open() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut scope = InitScope::new();
+        let id = unique_port_id();
+        let port = scope.track(rt.native_recv_port_from_raw(id).unwrap());
+        assert_eq!(port.as_raw().0, id);
+        let ports = scope.commit();
+        assert_eq!(
+            ports.iter().map(|port| port.as_raw().0).collect::<Vec<_>>(),
+            [id]
+        );
+        assert!(!was_closed(id));
+    }
+}
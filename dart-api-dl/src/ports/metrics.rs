@@ -0,0 +1,197 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Latency metrics of native message handlers.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use super::{state, DartPortId};
+
+/// Number of histogram buckets, the last one has no upper bound.
+const BUCKETS: usize = 24;
+
+/// Snapshot of the handler latencies of a [`NativeRecvPort`].
+///
+/// Latencies are sorted into buckets with exponentially growing upper
+/// bounds, starting at 1µs and doubling with each bucket.
+///
+/// [`NativeRecvPort`]: super::NativeRecvPort
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencySnapshot {
+    count: u64,
+    total: Duration,
+    max: Duration,
+    buckets: [u64; BUCKETS],
+}
+
+impl LatencySnapshot {
+    /// Number of handled messages.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Total time spent in the handler.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Longest time spent handling a single message.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Average time spent handling a message, `None` if no message was handled.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| {
+            let nanos = self.total.as_nanos() / u128::from(self.count);
+            Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+        })
+    }
+
+    /// Iterates over the `(upper_bound, count)` of all histogram buckets.
+    ///
+    /// The upper bound is inclusive, the last bucket is unbounded and
+    /// reports [`Duration::MAX`].
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().enumerate().map(|(idx, count)| {
+            let upper_bound = if idx + 1 == BUCKETS {
+                Duration::MAX
+            } else {
+                Duration::from_micros(1 << idx)
+            };
+            (upper_bound, *count)
+        })
+    }
+
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+        self.buckets[bucket_index(latency)] += 1;
+    }
+}
+
+/// Index of the smallest bucket whose upper bound is `>= latency`.
+fn bucket_index(latency: Duration) -> usize {
+    let micros = latency.as_micros();
+    let idx = if micros <= 1 {
+        0
+    } else {
+        128 - (micros - 1).leading_zeros() as usize
+    };
+    idx.min(BUCKETS - 1)
+}
+
+/// Latencies of a native port created by [`DartRuntime::native_recv_port()`].
+///
+/// [`DartRuntime::native_recv_port()`]: crate::DartRuntime::native_recv_port
+type Latencies = Mutex<LatencySnapshot>;
+
+/// Starts recording the latencies of a new port.
+pub(super) fn init(id: DartPortId) {
+    state::insert::<Latencies>(id, Mutex::default());
+}
+
+/// Records the latency of a handled message.
+///
+/// Nothing is recorded if the port was already dropped, messages can still be
+/// handled after that.
+pub(super) fn record(id: DartPortId, latency: Duration) {
+    if let Some(latencies) = state::get::<Latencies>(id) {
+        latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(latency);
+    }
+}
+
+pub(super) fn snapshot(id: DartPortId) -> LatencySnapshot {
+    state::get::<Latencies>(id)
+        .map(|latencies| {
+            latencies
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use crate::{
+        cobject::{CObject, CObjectMut},
+        ports::{dispatch_message, NativeMessageHandler, NativeRecvPort},
+        test_utils::unique_port_id,
+        DartRuntime,
+    };
+
+    use super::*;
+
+    struct Sleeper;
+
+    impl NativeMessageHandler for Sleeper {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "sleeper";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        assert_eq!(bucket_index(Duration::ZERO), 0);
+        assert_eq!(bucket_index(Duration::from_micros(1)), 0);
+        assert_eq!(bucket_index(Duration::from_micros(2)), 1);
+        assert_eq!(bucket_index(Duration::from_micros(3)), 2);
+        assert_eq!(bucket_index(Duration::from_micros(1024)), 10);
+        assert_eq!(bucket_index(Duration::from_secs(100)), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_handler_latency_is_recorded() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.native_recv_port::<Sleeper>().unwrap();
+        assert_eq!(port.latency_snapshot(), LatencySnapshot::default());
+
+        dispatch_message::<Sleeper>(rt, port.as_raw().0, CObject::null().as_mut());
+        dispatch_message::<Sleeper>(rt, port.as_raw().0, CObject::null().as_mut());
+
+        let snapshot = port.latency_snapshot();
+        assert_eq!(snapshot.count(), 2);
+        assert!(snapshot.max() >= Duration::from_millis(20));
+        assert!(snapshot.max() < Duration::from_secs(1));
+        let mean = snapshot.mean().unwrap();
+        assert!(mean >= Duration::from_millis(20) && mean < Duration::from_secs(1));
+        let (upper_bound, count) = snapshot.buckets().find(|(_, count)| *count > 0).unwrap();
+        assert!(upper_bound >= Duration::from_millis(20));
+        assert_eq!(count, 2);
+
+        let id = port.as_raw().0;
+        drop(port);
+        assert_eq!(super::snapshot(id), LatencySnapshot::default());
+    }
+
+    #[test]
+    fn test_latency_of_dropped_port_is_not_recorded() {
+        let id = unique_port_id();
+        record(id, Duration::from_millis(1));
+        assert!(state::get::<Latencies>(id).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conventional heartbeat messages carrying a timestamp.
+
+use std::time::SystemTime;
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    utils::quietly,
+    DartRuntime,
+};
+
+use super::{PostingMessageFailed, SendPort};
+
+/// Marker used as first element of a ping message.
+const PING_MARKER: &str = "__ping__";
+
+impl SendPort {
+    /// Posts a ping with the current time to this port.
+    ///
+    /// The ping is sent as an array of the form `["__ping__", millis]`, with
+    /// the time encoded like [`CObject::from_system_time()`], i.e. it can be
+    /// read with dart's `DateTime.fromMillisecondsSinceEpoch`.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_ping(&self) -> Result<(), PostingMessageFailed> {
+        self.post_cobject(CObject::array(vec![
+            Box::new(CObject::string_lossy(PING_MARKER)),
+            Box::new(CObject::from_system_time(SystemTime::now())),
+        ]))
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns the timestamp in milliseconds since the unix epoch if the object is a ping.
+    ///
+    /// See [`SendPort::post_ping()`].
+    pub fn as_ping(&self, rt: DartRuntime) -> Option<i64> {
+        quietly(|| match self.as_array(rt)? {
+            [marker, millis] if marker.as_string(rt) == Some(PING_MARKER) => millis.as_int(rt),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[test]
+    fn test_post_ping_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let mut pings = posted_messages(|port| port.post_ping().unwrap());
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        assert_eq!(pings.len(), 1);
+        let millis = pings[0].as_mut().as_ping(rt).unwrap();
+        assert!((before.as_millis()..=after.as_millis()).contains(&u128::try_from(millis).unwrap()));
+        assert_eq!(CObject::int64(millis).as_mut().as_ping(rt), None);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conventional progress updates of long running operations.
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    utils::quietly,
+    DartRuntime,
+};
+
+use super::{PostingMessageFailed, SendPort};
+
+/// Marker used as first element of a progress message.
+const PROGRESS_MARKER: &str = "__progress__";
+
+impl SendPort {
+    /// Posts a progress update to this port.
+    ///
+    /// The update is sent as an array of the form
+    /// `["__progress__", current, total, message]`, where the message is `null`
+    /// if there is none. Counts which don't fit into an `int` saturate.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post_progress(
+        &self,
+        current: u64,
+        total: u64,
+        message: Option<&str>,
+    ) -> Result<(), PostingMessageFailed> {
+        let count = |count| CObject::int64(i64::try_from(count).unwrap_or(i64::MAX));
+        self.post_cobject(CObject::array(vec![
+            Box::new(CObject::string_lossy(PROGRESS_MARKER)),
+            Box::new(count(current)),
+            Box::new(count(total)),
+            Box::new(message.map_or_else(CObject::null, CObject::string_lossy)),
+        ]))
+    }
+}
+
+impl CObjectMut<'_> {
+    /// Returns `(current, total, message)` if the object is a progress update.
+    ///
+    /// See [`SendPort::post_progress()`].
+    pub fn as_progress(&self, rt: DartRuntime) -> Option<(u64, u64, Option<&str>)> {
+        quietly(|| match self.as_array(rt)? {
+            [marker, current, total, message] if marker.as_string(rt) == Some(PROGRESS_MARKER) => {
+                let current = u64::try_from(current.as_int(rt)?).ok()?;
+                let total = u64::try_from(total.as_int(rt)?).ok()?;
+                let message = match message.as_null(rt) {
+                    Some(()) => None,
+                    None => Some(message.as_string(rt)?),
+                };
+                Some((current, total, message))
+            }
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[test]
+    fn test_post_progress_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut progress = posted_messages(|port| {
+            port.post_progress(3, 10, Some("indexing")).unwrap();
+            port.post_progress(10, 10, None).unwrap();
+        });
+
+        assert_eq!(progress.len(), 2);
+        assert_eq!(
+            progress[0].as_mut().as_progress(rt),
+            Some((3, 10, Some("indexing")))
+        );
+        assert_eq!(progress[1].as_mut().as_progress(rt), Some((10, 10, None)));
+        assert_eq!(CObject::int64(3).as_mut().as_progress(rt), None);
+    }
+}
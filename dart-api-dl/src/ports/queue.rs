@@ -0,0 +1,157 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bounding the number of messages handed to a handler at the same time.
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use super::{state, DartPortId};
+
+/// What happens with a message if [`NativeMessageHandler::QUEUE_DEPTH`] is reached.
+///
+/// [`NativeMessageHandler::QUEUE_DEPTH`]: super::NativeMessageHandler::QUEUE_DEPTH
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueOverflow {
+    /// Blocks the dart thread delivering the message until a slot is free.
+    ///
+    /// This throttles the delivery of messages to the port, but keeps the thread
+    /// from handling messages of other ports.
+    Block,
+    /// Drops the message without handling it.
+    DropNewest,
+}
+
+/// The queue of a bounded port which handled a message.
+#[derive(Default)]
+struct Queue {
+    len: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// A message which is being handled, frees its slot in the queue when dropped.
+pub(super) struct QueueSlot(Option<Arc<Queue>>);
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        if let Some(queue) = &self.0 {
+            *queue.len.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+            queue.freed.notify_one();
+        }
+    }
+}
+
+/// Takes a slot in the queue of the port for a message.
+///
+/// Returns `None` if the message should be dropped. Ports with a `depth` of
+/// `usize::MAX` are unbounded and don't need any bookkeeping.
+pub(super) fn enter(id: DartPortId, depth: usize, overflow: QueueOverflow) -> Option<QueueSlot> {
+    if depth == usize::MAX {
+        return Some(QueueSlot(None));
+    }
+    let queue = state::get_or_insert_with(id, Queue::default);
+    let mut len = queue.len.lock().unwrap_or_else(PoisonError::into_inner);
+    while *len >= depth.max(1) {
+        if overflow == QueueOverflow::DropNewest {
+            return None;
+        }
+        len = queue
+            .freed
+            .wait(len)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    *len += 1;
+    drop(len);
+    Some(QueueSlot(Some(queue)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::{channel, Receiver, Sender},
+        },
+        thread,
+    };
+
+    use once_cell::sync::Lazy;
+
+    use crate::{
+        cobject::{CObject, CObjectMut},
+        ports::{dispatch_message, NativeMessageHandler, NativeRecvPort},
+        test_utils::unique_port_id,
+        DartRuntime,
+    };
+
+    use super::*;
+
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    static RELEASE: Lazy<Mutex<Option<Receiver<()>>>> = Lazy::new(Default::default);
+
+    struct Bounded;
+
+    impl NativeMessageHandler for Bounded {
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "bounded";
+        const QUEUE_DEPTH: usize = 1;
+        const QUEUE_OVERFLOW: QueueOverflow = QueueOverflow::DropNewest;
+
+        fn handle_message(rt: DartRuntime, _: &NativeRecvPort, data: CObjectMut<'_>) {
+            if data.as_bool(rt) == Some(true) {
+                let release = RELEASE.lock().unwrap().take().unwrap();
+                release.recv().unwrap();
+            }
+            HANDLED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn block_handler() -> Sender<()> {
+        let (sender, receiver) = channel();
+        *RELEASE.lock().unwrap() = Some(receiver);
+        sender
+    }
+
+    #[test]
+    fn test_full_queue_drops_newest() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let id = unique_port_id();
+        let release = block_handler();
+        let blocked = thread::spawn(move || {
+            dispatch_message::<Bounded>(rt, id, CObject::bool(true).as_mut());
+        });
+        while RELEASE.lock().unwrap().is_some() {
+            thread::yield_now();
+        }
+
+        dispatch_message::<Bounded>(rt, id, CObject::bool(false).as_mut());
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 0);
+        release.send(()).unwrap();
+        blocked.join().unwrap();
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+
+        dispatch_message::<Bounded>(rt, id, CObject::bool(false).as_mut());
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_full_queue_blocks() {
+        let id = unique_port_id();
+        let slot = enter(id, 1, QueueOverflow::Block).unwrap();
+        let waiting = thread::spawn(move || drop(enter(id, 1, QueueOverflow::Block)));
+        assert!(enter(id, 1, QueueOverflow::DropNewest).is_none());
+        drop(slot);
+        waiting.join().unwrap();
+        assert!(enter(id, 1, QueueOverflow::DropNewest).is_some());
+    }
+}
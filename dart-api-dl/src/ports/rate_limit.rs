@@ -0,0 +1,232 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Token bucket rate limiting of message handlers.
+
+use std::{
+    marker::PhantomData,
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+
+use crate::{
+    cobject::{CObject, CObjectMut},
+    diagnostics::{report_dropped_message, DropReason},
+    DartRuntime,
+};
+
+use super::{state, NativeMessageHandler, NativeRecvPort, QueueOverflow};
+
+/// Configures the rate limit of a [`RateLimitedHandler`].
+pub trait RateLimit: NativeMessageHandler {
+    /// The number of messages per second which are handled on average.
+    const RATE: f64;
+
+    /// The number of messages which can be handled in a burst.
+    ///
+    /// This is also the number of messages which can be handled right after
+    /// the port was created.
+    const BURST: u32;
+
+    /// Called instead of [`NativeMessageHandler::handle_message()`] for each
+    /// message which exceeds the rate limit.
+    ///
+    /// By default the message is dropped and reported as
+    /// [`DropReason::RateLimited`], see [`set_dropped_message_sink()`].
+    ///
+    /// [`set_dropped_message_sink()`]: crate::diagnostics::set_dropped_message_sink
+    fn message_dropped(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        let _ = (rt, data);
+        report_dropped_message(ourself.as_raw().0, Self::NAME, DropReason::RateLimited);
+    }
+}
+
+/// A [`NativeMessageHandler`] which drops messages exceeding the [`RateLimit`] of `H`.
+///
+/// This protects against being flooded with messages. The limit applies per port
+/// and is shared between all threads handling messages of the port.
+///
+/// ```
+/// # use xayn_dart_api_dl::{cobject::{CObject, CObjectMut}, ports::{NativeMessageHandler, NativeRecvPort, PortCreationFailed, RateLimit, RateLimitedHandler}, DartRuntime};
+/// struct Handler;
+///
+/// impl NativeMessageHandler for Handler {
+///     const CONCURRENT_HANDLING: bool = true;
+///     const NAME: &'static str = "handler";
+///
+///     fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {}
+///
+///     fn handle_panic(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>, _: CObject) {}
+/// }
+///
+/// impl RateLimit for Handler {
+///     const RATE: f64 = 100.;
+///     const BURST: u32 = 10;
+/// }
+///
+/// fn setup(rt: DartRuntime) -> Result<NativeRecvPort, PortCreationFailed> {
+///     rt.native_recv_port::<RateLimitedHandler<Handler>>()
+/// }
+/// ```
+pub struct RateLimitedHandler<H>(PhantomData<fn() -> H>);
+
+impl<H> NativeMessageHandler for RateLimitedHandler<H>
+where
+    H: RateLimit,
+{
+    const CONCURRENT_HANDLING: bool = H::CONCURRENT_HANDLING;
+    const NAME: &'static str = H::NAME;
+    const QUEUE_DEPTH: usize = H::QUEUE_DEPTH;
+    const QUEUE_OVERFLOW: QueueOverflow = H::QUEUE_OVERFLOW;
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        let admitted = state::get_or_insert_with(ourself.as_raw().0, || {
+            Mutex::new(TokenBucket::new(H::BURST))
+        })
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .try_take(H::RATE, H::BURST);
+        if admitted {
+            H::handle_message(rt, ourself, data);
+        } else {
+            H::message_dropped(rt, ourself, data);
+        }
+    }
+
+    fn handle_panic(
+        rt: DartRuntime,
+        ourself: &NativeRecvPort,
+        data: CObjectMut<'_>,
+        panic: CObject,
+    ) {
+        H::handle_panic(rt, ourself, data, panic);
+    }
+}
+
+/// The token bucket of a rate limited port which handled a message.
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: u32) -> Self {
+        Self {
+            tokens: burst.into(),
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self, rate: f64, burst: u32) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(burst.into());
+        self.last_refill = now;
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        diagnostics::{set_dropped_message_sink, DroppedMessage},
+        ports::dispatch_message,
+        test_utils::unique_port_id,
+    };
+
+    use super::*;
+
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Flooded;
+
+    impl NativeMessageHandler for Flooded {
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "flooded";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {
+            HANDLED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl RateLimit for Flooded {
+        const RATE: f64 = 0.001;
+        const BURST: u32 = 3;
+
+        fn message_dropped(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_burst_above_limit_is_dropped() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let id = unique_port_id();
+        for _ in 0..10 {
+            dispatch_message::<RateLimitedHandler<Flooded>>(rt, id, CObject::null().as_mut());
+        }
+        assert_eq!(HANDLED.load(Ordering::SeqCst), 3);
+        assert_eq!(DROPPED.load(Ordering::SeqCst), 7);
+        state::remove(id);
+    }
+
+    struct Silent;
+
+    impl NativeMessageHandler for Silent {
+        const CONCURRENT_HANDLING: bool = true;
+        const NAME: &'static str = "silent";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {}
+    }
+
+    impl RateLimit for Silent {
+        const RATE: f64 = 0.001;
+        const BURST: u32 = 1;
+    }
+
+    thread_local! {
+        static DROPS: RefCell<Vec<DroppedMessage>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(dropped: DroppedMessage) {
+        DROPS.with(|drops| drops.borrow_mut().push(dropped));
+    }
+
+    #[test]
+    fn test_dropped_message_is_reported_by_default() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        set_dropped_message_sink(record);
+        let id = unique_port_id();
+        for _ in 0..3 {
+            dispatch_message::<RateLimitedHandler<Silent>>(rt, id, CObject::null().as_mut());
+        }
+        let drops = DROPS.with(RefCell::take);
+        assert_eq!(drops.len(), 2);
+        assert!(drops.iter().all(|dropped| dropped.port == id
+            && dropped.handler == "silent"
+            && dropped.reason == DropReason::RateLimited));
+        state::remove(id);
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of handlers re-entering their own port.
+
+use std::{cell::RefCell, marker::PhantomData};
+
+use thiserror::Error;
+
+use super::{DartPortId, SendPort};
+
+thread_local! {
+    /// Ports which are currently entered on this thread, innermost last.
+    static ENTERED_PORTS: RefCell<Vec<DartPortId>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The port was re-entered on the same thread.
+#[derive(Debug, Error)]
+#[error("The port {port} was re-entered on the same thread.")]
+pub struct PortReentered {
+    /// The id of the re-entered port.
+    pub port: DartPortId,
+}
+
+/// Marks a port as entered on the current thread while alive.
+///
+/// See [`SendPort::reentrant_guard()`].
+#[derive(Debug)]
+#[must_use]
+pub struct ReentrantGuard {
+    port: DartPortId,
+    // The guard is bound to the thread-local state of the thread it was created on.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ReentrantGuard {
+    /// Enters the port without checking for re-entry.
+    pub(super) fn enter(port: DartPortId) -> Self {
+        ENTERED_PORTS.with(|ports| ports.borrow_mut().push(port));
+        ReentrantGuard {
+            port,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for ReentrantGuard {
+    fn drop(&mut self) {
+        ENTERED_PORTS.with(|ports| {
+            let mut ports = ports.borrow_mut();
+            if let Some(idx) = ports.iter().rposition(|port| *port == self.port) {
+                ports.remove(idx);
+            }
+        });
+    }
+}
+
+impl SendPort {
+    /// Marks the target of this port as entered on the current thread.
+    ///
+    /// While a [`NativeMessageHandler`] runs its port is entered on the handler
+    /// thread. Posting a message to your own port and blocking until it's handled
+    /// will deadlock if the port doesn't use [`NativeMessageHandler::CONCURRENT_HANDLING`]
+    /// (and might deadlock even if it does). The same applies to synchronously
+    /// processing a sub-message with logic which waits for the port.
+    ///
+    /// Calling this before such blocking code turns the deadlock into an error.
+    /// The returned guard keeps the port entered until it's dropped, which allows
+    /// detecting re-entry in nested code, too.
+    ///
+    /// This only detects re-entry on the same thread, it can't detect a deadlock
+    /// caused by waiting on another thread which waits on the handler.
+    ///
+    /// [`NativeMessageHandler`]: super::NativeMessageHandler
+    /// [`NativeMessageHandler::CONCURRENT_HANDLING`]: super::NativeMessageHandler::CONCURRENT_HANDLING
+    ///
+    /// # Errors
+    ///
+    /// If the target of this port is already entered on the current thread.
+    pub fn reentrant_guard(&self) -> Result<ReentrantGuard, PortReentered> {
+        let port = self.as_raw().0;
+        let entered = ENTERED_PORTS.with(|ports| ports.borrow().contains(&port));
+        if entered {
+            Err(PortReentered { port })
+        } else {
+            Ok(ReentrantGuard::enter(port))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::{
+        cobject::{CObject, CObjectMut},
+        ports::{dispatch_message, NativeMessageHandler, NativeRecvPort},
+        test_utils::unique_port_id,
+        DartRuntime,
+    };
+
+    static TRIPPED: AtomicBool = AtomicBool::new(false);
+
+    struct SelfMessaging;
+
+    impl NativeMessageHandler for SelfMessaging {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "self-messaging";
+
+        fn handle_message(_: DartRuntime, ourself: &NativeRecvPort, _: CObjectMut<'_>) {
+            TRIPPED.store(ourself.reentrant_guard().is_err(), Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_guard_trips_inside_handler() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let id = unique_port_id();
+        dispatch_message::<SelfMessaging>(rt, id, CObject::null().as_mut());
+        assert!(TRIPPED.load(Ordering::SeqCst));
+        assert!(rt.send_port_from_raw(id).unwrap().reentrant_guard().is_ok());
+    }
+
+    #[test]
+    fn test_guard_trips_on_nested_entry() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let port = rt.send_port_from_raw(unique_port_id()).unwrap();
+        let other = rt.send_port_from_raw(unique_port_id()).unwrap();
+
+        let guard = port.reentrant_guard().unwrap();
+        let err = port.reentrant_guard().unwrap_err();
+        assert_eq!(err.port, port.as_raw().0);
+        let other_guard = other.reentrant_guard().unwrap();
+        drop(guard);
+        drop(port.reentrant_guard().unwrap());
+        drop(other_guard);
+    }
+}
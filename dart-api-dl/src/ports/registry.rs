@@ -0,0 +1,135 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registry of the ports created for message handlers.
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::DartRuntime;
+
+use super::{DartPortId, NativeMessageHandler, NativeRecvPort, PortCreationFailed};
+
+/// The still open ports of each handler type, in the order they were created.
+static PORTS: Lazy<Mutex<HashMap<TypeId, Vec<DartPortId>>>> = Lazy::new(Default::default);
+
+pub(super) fn remove(id: DartPortId) {
+    PORTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .retain(|_, registered| {
+            registered.retain(|registered| *registered != id);
+            !registered.is_empty()
+        });
+}
+
+impl DartRuntime {
+    /// Like [`DartRuntime::native_recv_port()`], but registers the port by the type of its handler.
+    ///
+    /// See [`DartRuntime::port_id_for()`]. Unlike [`DartRuntime::native_recv_port()`]
+    /// this requires a `'static` handler, as the registry is keyed by its [`TypeId`].
+    ///
+    /// # Errors
+    ///
+    /// See [`DartRuntime::native_recv_port()`].
+    pub fn registered_native_recv_port<N>(&self) -> Result<NativeRecvPort, PortCreationFailed>
+    where
+        N: NativeMessageHandler + 'static,
+    {
+        let port = self.native_recv_port::<N>()?;
+        PORTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(TypeId::of::<N>())
+            .or_default()
+            .push(port.as_raw().0);
+        Ok(port)
+    }
+
+    /// Returns the id of the port created for the handler `N`.
+    ///
+    /// Ports created with [`DartRuntime::registered_native_recv_port()`] are
+    /// registered by the type of their handler until they are closed, a
+    /// [leaked](super::NativeRecvPort::leak()) port stays registered. This allows
+    /// retrieving the id without passing it through the code.
+    ///
+    /// If multiple ports were created for the handler the id of the most recently
+    /// created one which is still open is returned.
+    pub fn port_id_for<N>(&self) -> Option<DartPortId>
+    where
+        N: NativeMessageHandler + 'static,
+    {
+        PORTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&TypeId::of::<N>())
+            .and_then(|registered| registered.last())
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cobject::CObjectMut;
+
+    use super::*;
+
+    struct Adder;
+
+    impl NativeMessageHandler for Adder {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "registry-adder";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {}
+    }
+
+    #[test]
+    fn test_port_id_for_handler() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let unregistered = rt.native_recv_port::<Adder>().unwrap();
+        assert_eq!(rt.port_id_for::<Adder>(), None);
+        let port = rt.registered_native_recv_port::<Adder>().unwrap();
+        assert_eq!(rt.port_id_for::<Adder>(), Some(port.as_raw().0));
+        drop(port);
+        assert_eq!(rt.port_id_for::<Adder>(), None);
+        drop(unregistered);
+    }
+
+    struct SameName;
+
+    impl NativeMessageHandler for SameName {
+        const CONCURRENT_HANDLING: bool = false;
+        const NAME: &'static str = "registry-adder";
+
+        fn handle_message(_: DartRuntime, _: &NativeRecvPort, _: CObjectMut<'_>) {}
+    }
+
+    #[test]
+    fn test_closing_newer_port_restores_older_one() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let older = rt.registered_native_recv_port::<SameName>().unwrap();
+        let newer = rt.registered_native_recv_port::<SameName>().unwrap();
+        assert_eq!(rt.port_id_for::<SameName>(), Some(newer.as_raw().0));
+        drop(newer);
+        assert_eq!(rt.port_id_for::<SameName>(), Some(older.as_raw().0));
+        assert_ne!(rt.port_id_for::<Adder>(), Some(older.as_raw().0));
+        drop(older);
+        assert_eq!(rt.port_id_for::<SameName>(), None);
+    }
+}
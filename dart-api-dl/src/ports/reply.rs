@@ -0,0 +1,273 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Replying from outside of the handler thread.
+
+use std::{
+    ffi::CStr,
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
+
+use dart_api_dl_sys::{
+    _Dart_CObject__bindgen_ty_1,
+    _Dart_CObject__bindgen_ty_1__bindgen_ty_3,
+    _Dart_CObject__bindgen_ty_1__bindgen_ty_4,
+    Dart_CObject,
+    Dart_CObject_Type,
+};
+
+use crate::cobject::{CObject, CObjectMut, TypedDataRef};
+
+use super::{PostingMessageFailed, SendPort};
+
+/// A handle to reply to a message later, possibly from another thread.
+///
+/// The [`CObjectMut`] a handler receives is freed by dart once the handler
+/// returns, so it can't be used for asynchronous work. Instead the handler
+/// extracts what it needs from the message (including the port to reply to)
+/// and moves it together with a `ReplyHandle` to e.g. a thread pool or an
+/// async task. There the reply can be built as [`CObject`] and sent with
+/// [`ReplyHandle::reply()`].
+///
+/// Note that a [`CObject`] is not `Send`, it needs to be built on the thread
+/// which sends it.
+///
+/// [`CObjectMut`]: crate::cobject::CObjectMut
+#[derive(Debug, Clone, Copy)]
+pub struct ReplyHandle {
+    port: SendPort,
+}
+
+impl ReplyHandle {
+    /// Creates a handle to reply to the given port.
+    pub fn new(port: SendPort) -> Self {
+        Self { port }
+    }
+
+    /// Returns the port to which the reply is sent.
+    pub fn port(&self) -> SendPort {
+        self.port
+    }
+
+    /// Sends the reply.
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn reply(&self, reply: CObject) -> Result<(), PostingMessageFailed> {
+        self.port.post_cobject(reply)
+    }
+}
+
+impl From<SendPort> for ReplyHandle {
+    fn from(port: SendPort) -> Self {
+        Self::new(port)
+    }
+}
+
+/// A reply which borrows its data instead of owning it.
+///
+/// Dart copies the data of a message when it's posted (except external typed
+/// data, which is never created by this type). So a reply can be built from data
+/// borrowed from the handler without any allocations, as long as it's posted
+/// before the borrow ends. The lifetime `'h` ensures that it can't escape the
+/// scope of the borrowed data.
+///
+/// Strings must be nul terminated and as such are borrowed as [`CStr`]. Slices
+/// of numbers are sent as (non external) typed data.
+// Note: Transparent repr is important as arrays are created by casting a slice of
+// references to builders into the pointer to pointers of the raw array.
+#[repr(transparent)]
+pub struct ReplyBuilder<'h> {
+    raw: Dart_CObject,
+    _borrows: PhantomData<&'h ()>,
+}
+
+impl<'h> ReplyBuilder<'h> {
+    fn from_raw(type_: Dart_CObject_Type, value: _Dart_CObject__bindgen_ty_1) -> Self {
+        Self {
+            raw: Dart_CObject { type_, value },
+            _borrows: PhantomData,
+        }
+    }
+
+    /// Creates a null reply.
+    pub fn null() -> Self {
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kNull,
+            _Dart_CObject__bindgen_ty_1 { as_int64: 0 },
+        )
+    }
+
+    /// Creates a bool reply.
+    pub fn bool(val: bool) -> Self {
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kBool,
+            _Dart_CObject__bindgen_ty_1 { as_bool: val },
+        )
+    }
+
+    /// Creates an int64 reply.
+    pub fn int64(val: i64) -> Self {
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kInt64,
+            _Dart_CObject__bindgen_ty_1 { as_int64: val },
+        )
+    }
+
+    /// Creates a double reply.
+    pub fn double(val: f64) -> Self {
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kDouble,
+            _Dart_CObject__bindgen_ty_1 { as_double: val },
+        )
+    }
+
+    /// Creates a string reply borrowing the string.
+    ///
+    /// # Panics
+    ///
+    /// If the string is not valid utf-8.
+    pub fn string(val: &'h CStr) -> Self {
+        assert!(val.to_str().is_ok(), "dart strings must be utf-8");
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kString,
+            _Dart_CObject__bindgen_ty_1 {
+                as_string: NonNull::from(val.to_bytes_with_nul()).cast().as_ptr(),
+            },
+        )
+    }
+
+    /// Creates a typed data reply borrowing the data.
+    pub fn typed_data(data: TypedDataRef<'h>) -> Self {
+        let bytes = data.as_bytes();
+        // dart expects a null pointer for empty typed data
+        let values = if bytes.is_empty() {
+            ptr::null_mut()
+        } else {
+            NonNull::from(bytes).cast().as_ptr()
+        };
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kTypedData,
+            _Dart_CObject__bindgen_ty_1 {
+                as_typed_data: _Dart_CObject__bindgen_ty_1__bindgen_ty_4 {
+                    type_: data.data_type().into(),
+                    // We can't really have a len > isize::MAX here.
+                    length: data.len().try_into().unwrap_or(isize::MAX),
+                    values,
+                },
+            },
+        )
+    }
+
+    /// Creates an array reply borrowing the elements.
+    pub fn array(elements: &'h mut [&'h mut ReplyBuilder<'h>]) -> Self {
+        // dart expects a null pointer for empty arrays
+        let values = if elements.is_empty() {
+            ptr::null_mut()
+        } else {
+            // `&mut ReplyBuilder` has the same representation as `*mut Dart_CObject`
+            elements.as_mut_ptr().cast()
+        };
+        Self::from_raw(
+            Dart_CObject_Type::Dart_CObject_kArray,
+            _Dart_CObject__bindgen_ty_1 {
+                as_array: _Dart_CObject__bindgen_ty_1__bindgen_ty_3 {
+                    // We can't really have a len > isize::MAX here.
+                    length: elements.len().try_into().unwrap_or(isize::MAX),
+                    values,
+                },
+            },
+        )
+    }
+
+    /// Posts the reply to the port.
+    ///
+    /// See [`SendPort::post_cobject_mut()`].
+    ///
+    /// # Errors
+    ///
+    /// If posting the message failed.
+    pub fn post(&mut self, port: &SendPort) -> Result<(), PostingMessageFailed> {
+        // Safe:
+        // - the object is sound as it was built by the constructors
+        // - the borrowed data lives for `'h`
+        unsafe {
+            CObjectMut::with_pointer(ptr::addr_of_mut!(self.raw), |reply| {
+                port.post_cobject_mut(reply)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, thread};
+
+    use static_assertions::assert_impl_all;
+
+    use crate::{test_utils::posted_messages, DartRuntime};
+
+    use super::*;
+
+    #[test]
+    fn test_reply_from_spawned_thread() {
+        assert_impl_all!(ReplyHandle: Send, Sync);
+
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut replies = posted_messages(|port| {
+            let handle = ReplyHandle::new(port);
+            thread::spawn(move || {
+                let reply = CObject::array(vec![
+                    Box::new(CObject::string("done").unwrap()),
+                    Box::new(CObject::int64(42)),
+                ]);
+                handle.reply(reply).unwrap();
+            })
+            .join()
+            .unwrap();
+        });
+
+        assert_eq!(replies.len(), 1);
+        let reply = replies[0].as_mut();
+        let reply = reply.as_array(rt).unwrap();
+        assert_eq!(reply[0].as_string(rt), Some("done"));
+        assert_eq!(reply[1].as_int(rt), Some(42));
+    }
+
+    #[test]
+    fn test_post_borrowed_reply() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let values = [1_i64, -2, 3];
+        let name = CString::new("scores").unwrap();
+
+        let mut name = ReplyBuilder::string(&name);
+        let mut values = ReplyBuilder::typed_data(TypedDataRef::Int64(&values));
+        let mut total = ReplyBuilder::int64(2);
+        let mut elements = [&mut name, &mut values, &mut total];
+        let mut reply = ReplyBuilder::array(&mut elements);
+        let mut replies = posted_messages(|port| reply.post(&port).unwrap());
+
+        assert_eq!(replies.len(), 1);
+        let reply = replies[0].as_mut();
+        let reply = reply.as_array(rt).unwrap();
+        assert_eq!(reply[0].as_string(rt), Some("scores"));
+        assert!(matches!(
+            reply[1].as_typed_data(rt),
+            Some((Ok(TypedDataRef::Int64(&[1, -2, 3])), _))
+        ));
+        assert_eq!(reply[2].as_int64(rt), Some(2));
+    }
+}
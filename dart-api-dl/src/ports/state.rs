@@ -0,0 +1,151 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State stored with each native port.
+//!
+//! Each port stores at most one value per type, so every feature uses its own
+//! state type. All state of a port is dropped when its [`NativeRecvPort`] is
+//! dropped.
+//!
+//! [`NativeRecvPort`]: super::NativeRecvPort
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        PoisonError,
+        RwLock,
+    },
+};
+
+use once_cell::sync::Lazy;
+
+use super::DartPortId;
+
+type ErasedState = Arc<dyn Any + Send + Sync>;
+
+/// The state of all native ports, by port and by type.
+static STATES: Lazy<RwLock<HashMap<DartPortId, HashMap<TypeId, ErasedState>>>> =
+    Lazy::new(Default::default);
+
+/// Set once any state was stored, until then dropping a port doesn't take the lock.
+static USED: AtomicBool = AtomicBool::new(false);
+
+/// Stores the state of type `T` of the port, replacing any previous one.
+pub(super) fn insert<T>(id: DartPortId, state: T)
+where
+    T: Any + Send + Sync,
+{
+    USED.store(true, Ordering::Release);
+    STATES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(id)
+        .or_default()
+        .insert(TypeId::of::<T>(), Arc::new(state));
+}
+
+/// Returns the state of type `T` of the port.
+pub(super) fn get<T>(id: DartPortId) -> Option<Arc<T>>
+where
+    T: Any + Send + Sync,
+{
+    let state = STATES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&id)?
+        .get(&TypeId::of::<T>())?
+        .clone();
+    state.downcast().ok()
+}
+
+/// Returns the state of type `T` of the port, storing the result of `init` if there is none.
+///
+/// `init` is called without holding any lock, if the state is stored concurrently
+/// the result of `init` is dropped.
+pub(super) fn get_or_insert_with<T>(id: DartPortId, init: impl FnOnce() -> T) -> Arc<T>
+where
+    T: Any + Send + Sync,
+{
+    if let Some(state) = get(id) {
+        return state;
+    }
+    let state = Arc::new(init());
+    USED.store(true, Ordering::Release);
+    let state = STATES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(id)
+        .or_default()
+        .entry(TypeId::of::<T>())
+        .or_insert(state)
+        .clone();
+    state
+        .downcast()
+        .unwrap_or_else(|_| unreachable!("states are stored by their type id"))
+}
+
+/// Drops all state of the port.
+pub(super) fn remove(id: DartPortId) {
+    if !USED.load(Ordering::Acquire) {
+        return;
+    }
+    // the state is dropped after releasing the lock, as it might own ports
+    let state = STATES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id);
+    drop(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::test_utils::unique_port_id;
+
+    use super::*;
+
+    #[test]
+    fn test_state_is_stored_per_port_and_type() {
+        let (a, b) = (unique_port_id(), unique_port_id());
+        insert(a, 1_u8);
+        insert(a, "a");
+        insert(b, 2_u8);
+        assert_eq!(get::<u8>(a).as_deref(), Some(&1));
+        assert_eq!(get::<&str>(a).as_deref(), Some(&"a"));
+        assert_eq!(get::<u8>(b).as_deref(), Some(&2));
+        assert!(get::<&str>(b).is_none());
+
+        remove(a);
+        assert!(get::<u8>(a).is_none());
+        assert!(get::<&str>(a).is_none());
+        assert_eq!(get::<u8>(b).as_deref(), Some(&2));
+        remove(b);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_initializes_once() {
+        let id = unique_port_id();
+        let calls = AtomicUsize::new(0);
+        let init = || AtomicUsize::new(calls.fetch_add(1, Ordering::SeqCst));
+        let first = get_or_insert_with(id, init);
+        let second = get_or_insert_with(id, init);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        remove(id);
+    }
+}
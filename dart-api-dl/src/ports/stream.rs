@@ -0,0 +1,148 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Consuming the messages of a port as [`Stream`].
+
+use std::{
+    pin::Pin,
+    sync::{Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{channel, Receiver, Sender},
+    executor::block_on,
+    SinkExt,
+    Stream,
+};
+
+use crate::{
+    cobject::{CObject, CObjectMut, SendableCopy},
+    DartRuntime,
+};
+
+use super::{state, NativeMessageHandler, NativeRecvPort, PortCreationFailed};
+
+/// The sender of a port created with [`DartRuntime::native_recv_stream()`].
+///
+/// It's dropped with the port, which ends the stream once the buffered
+/// messages are yielded.
+type SharedSender = Mutex<Sender<SendableCopy>>;
+
+/// The messages received by a port, see [`DartRuntime::native_recv_stream()`].
+///
+/// The stream ends once the port was closed and all buffered messages were yielded.
+#[must_use]
+pub struct PortStream {
+    receiver: Receiver<SendableCopy>,
+}
+
+impl Stream for PortStream {
+    type Item = CObject;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver)
+            .poll_next(cx)
+            .map(|received| received.map(SendableCopy::into_inner))
+    }
+}
+
+struct StreamHandler;
+
+impl NativeMessageHandler for StreamHandler {
+    const CONCURRENT_HANDLING: bool = false;
+    const NAME: &'static str = "stream";
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        let sender = state::get::<SharedSender>(ourself.as_raw().0);
+        if let (Some(sender), Ok(msg)) = (sender, data.sendable_copy(rt.into())) {
+            let mut sender = sender.lock().unwrap_or_else(PoisonError::into_inner);
+            // the stream was dropped if this fails
+            let _ = block_on(sender.send(msg));
+        }
+    }
+}
+
+impl DartRuntime {
+    /// Creates a native receive port whose messages are yielded by a [`Stream`].
+    ///
+    /// The received messages are copied into owned objects. Up to `bound` messages
+    /// are buffered, if the buffer is full the message handler blocks until the
+    /// stream is polled. Messages which can't be copied, e.g. because they contain
+    /// objects of an unknown type, are ignored.
+    ///
+    /// The port is closed when the returned [`NativeRecvPort`] is dropped, after
+    /// which the stream ends.
+    ///
+    /// # Errors
+    ///
+    /// If creating the port failed.
+    pub fn native_recv_stream(
+        &self,
+        bound: usize,
+    ) -> Result<(NativeRecvPort, PortStream), PortCreationFailed> {
+        let port = self.native_recv_port::<StreamHandler>()?;
+        let (sender, receiver) = channel(bound);
+        state::insert::<SharedSender>(port.as_raw().0, Mutex::new(sender));
+        Ok((port, PortStream { receiver }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use futures::StreamExt;
+
+    use crate::ports::dispatch_message;
+
+    use super::*;
+
+    #[test]
+    fn test_stream_yields_messages_until_closed() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (port, stream) = rt.native_recv_stream(8).unwrap();
+        let id = port.as_raw().0;
+        for value in 0..3 {
+            dispatch_message::<StreamHandler>(rt, id, CObject::int64(value).as_mut());
+        }
+        drop(port);
+
+        let received = block_on(stream.collect::<Vec<_>>());
+        let received = received
+            .into_iter()
+            .map(|mut msg| msg.as_mut().as_int64(rt).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(received, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_full_buffer_blocks_handler() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (port, mut stream) = rt.native_recv_stream(1).unwrap();
+        let id = port.as_raw().0;
+        let handler = thread::spawn(move || {
+            for value in 0..10 {
+                dispatch_message::<StreamHandler>(rt, id, CObject::int64(value).as_mut());
+            }
+        });
+        for value in 0..10 {
+            let mut msg = block_on(stream.next()).unwrap();
+            assert_eq!(msg.as_mut().as_int64(rt), Some(value));
+        }
+        handler.join().unwrap();
+        drop(port);
+        assert!(block_on(stream.next()).is_none());
+    }
+}
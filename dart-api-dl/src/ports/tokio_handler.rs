@@ -0,0 +1,248 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Adapter for async message handlers running on `tokio`.
+
+use std::{
+    any::Any,
+    future::Future,
+    marker::PhantomData,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+use crate::{
+    cobject::{CObjectMut, DartValue},
+    panic::panic_message,
+    DartRuntime,
+};
+
+use super::{state, NativeMessageHandler, NativeRecvPort, SendPort};
+
+/// The future returned by a [`TokioNativeMessageHandler`].
+pub type HandlerFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The queue of a port with a [`Tokio`] handler which received messages.
+///
+/// It's dropped with the port, which stops the task of the port once it
+/// handled the already received messages.
+struct Queue(UnboundedSender<DartValue>);
+
+/// Async version of [`NativeMessageHandler`], use it through [`Tokio`].
+pub trait TokioNativeMessageHandler: 'static {
+    /// See [`NativeMessageHandler::NAME`].
+    const NAME: &'static str;
+
+    /// Returns the runtime on which the messages are handled.
+    fn runtime() -> Handle;
+
+    /// Called when handling a message.
+    ///
+    /// The message is an owned copy of the received object, as a
+    /// [`CObject`](crate::cobject::CObject) can't be sent to the runtime.
+    /// `ourself` is the port which received the message.
+    fn handle_message(rt: DartRuntime, ourself: SendPort, msg: DartValue) -> HandlerFuture;
+
+    /// Called if [`TokioNativeMessageHandler::handle_message()`] or its future panicked.
+    ///
+    /// By default the panic is ignored.
+    fn handle_panic(rt: DartRuntime, ourself: SendPort, panic: String) -> HandlerFuture {
+        let _ = (rt, ourself, panic);
+        Box::pin(async {})
+    }
+}
+
+/// A [`NativeMessageHandler`] which handles the messages with the async handler `H`.
+///
+/// Each port has a task on [`H::runtime()`](TokioNativeMessageHandler::runtime()) which
+/// handles its messages one after another, in the order they were received. As such
+/// responses are posted in order, too. Handlers which don't need this should spawn
+/// their work instead of awaiting it.
+///
+/// Messages which can't be copied, e.g. because they contain objects of unknown
+/// types, are ignored.
+///
+/// ```no_run
+/// # use xayn_dart_api_dl::{cobject::DartValue, ports::{HandlerFuture, NativeRecvPort, PortCreationFailed, SendPort, Tokio, TokioNativeMessageHandler}, DartRuntime};
+/// use once_cell::sync::Lazy;
+/// use tokio::runtime::{Handle, Runtime};
+///
+/// static RUNTIME: Lazy<Runtime> = Lazy::new(|| Runtime::new().unwrap());
+///
+/// struct Echo;
+///
+/// impl TokioNativeMessageHandler for Echo {
+///     const NAME: &'static str = "echo";
+///
+///     fn runtime() -> Handle {
+///         RUNTIME.handle().clone()
+///     }
+///
+///     fn handle_message(_: DartRuntime, _: SendPort, msg: DartValue) -> HandlerFuture {
+///         Box::pin(async move {
+///             if let DartValue::List(list) = msg {
+///                 if let [DartValue::SendPort(Some(reply)), value] = &list[..] {
+///                     if let Ok(echo) = value.clone().into_cobject() {
+///                         let _ = reply.post_cobject(echo);
+///                     }
+///                 }
+///             }
+///         })
+///     }
+/// }
+///
+/// fn setup(rt: DartRuntime) -> Result<NativeRecvPort, PortCreationFailed> {
+///     rt.native_recv_port::<Tokio<Echo>>()
+/// }
+/// ```
+pub struct Tokio<H>(PhantomData<fn() -> H>);
+
+impl<H> NativeMessageHandler for Tokio<H>
+where
+    H: TokioNativeMessageHandler,
+{
+    const CONCURRENT_HANDLING: bool = false;
+    const NAME: &'static str = H::NAME;
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        if let Ok(msg) = data.to_value(rt) {
+            enqueue::<H>(rt, **ourself, msg);
+        }
+    }
+}
+
+fn enqueue<H>(rt: DartRuntime, ourself: SendPort, msg: DartValue)
+where
+    H: TokioNativeMessageHandler,
+{
+    // messages are handled one at a time, so only one task is spawned
+    let queue = state::get_or_insert_with(ourself.as_raw().0, || {
+        let (sender, receiver) = unbounded_channel();
+        H::runtime().spawn(handle_queue::<H>(rt, ourself, receiver));
+        Queue(sender)
+    });
+    // the receiver only stops if the runtime shuts down
+    let _ = queue.0.send(msg);
+}
+
+async fn handle_queue<H>(
+    rt: DartRuntime,
+    ourself: SendPort,
+    mut queue: UnboundedReceiver<DartValue>,
+) where
+    H: TokioNativeMessageHandler,
+{
+    while let Some(msg) = queue.recv().await {
+        let handled = match catch_unwind(|| H::handle_message(rt, ourself, msg)) {
+            Ok(future) => CatchUnwind(future).await,
+            Err(panic) => Err(panic),
+        };
+        if let Err(panic) = handled {
+            let panic = panic_message(&*panic).to_owned();
+            if let Ok(future) = catch_unwind(|| H::handle_panic(rt, ourself, panic)) {
+                let _ = CatchUnwind(future).await;
+            }
+        }
+    }
+}
+
+/// Catches panics while polling the future.
+struct CatchUnwind(HandlerFuture);
+
+impl Future for CatchUnwind {
+    type Output = Result<(), Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use tokio::runtime::{Builder, Runtime};
+
+    use crate::{
+        cobject::CObject,
+        ports::dispatch_message,
+        test_utils::{loopback, unique_port_id},
+    };
+
+    use super::*;
+
+    static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+        Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap()
+    });
+
+    struct Echo;
+
+    impl TokioNativeMessageHandler for Echo {
+        const NAME: &'static str = "tokio-echo";
+
+        fn runtime() -> Handle {
+            RUNTIME.handle().clone()
+        }
+
+        fn handle_message(_: DartRuntime, _: SendPort, msg: DartValue) -> HandlerFuture {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                match msg {
+                    DartValue::List(list) => match &list[..] {
+                        [DartValue::SendPort(Some(reply)), DartValue::Int(value)] => {
+                            reply.post_integer(*value).unwrap();
+                        }
+                        _ => panic!("unexpected message"),
+                    },
+                    _ => panic!("unexpected message"),
+                }
+            })
+        }
+
+        fn handle_panic(_: DartRuntime, _: SendPort, panic: String) -> HandlerFuture {
+            Box::pin(async move { assert_eq!(panic, "unexpected message") })
+        }
+    }
+
+    #[test]
+    fn test_responses_arrive_in_order() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let (loopback, inbox) = loopback();
+        let reply = rt.send_port_from_raw(loopback.id()).unwrap();
+        let id = unique_port_id();
+        dispatch_message::<Tokio<Echo>>(rt, id, CObject::null().as_mut());
+        for value in 0..1000 {
+            let mut msg = CObject::array(vec![
+                Box::new(CObject::send_port(reply)),
+                Box::new(CObject::int64(value)),
+            ]);
+            dispatch_message::<Tokio<Echo>>(rt, id, msg.as_mut());
+        }
+        for value in 0..1000 {
+            assert_eq!(inbox.recv().unwrap().as_mut().as_int64(rt), Some(value));
+        }
+        state::remove(id);
+    }
+}
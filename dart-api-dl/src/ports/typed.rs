@@ -0,0 +1,231 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed request/response handling on top of native ports.
+
+use std::marker::PhantomData;
+
+use crate::{
+    cobject::{CObjectMut, DartMessage},
+    DartRuntime,
+};
+
+use super::{
+    state,
+    NativeMessageHandler,
+    NativeRecvPort,
+    PortCreationFailed,
+    PostingFailure,
+    ReplyHandle,
+    SendPort,
+};
+
+/// The error code posted to the reply port if a request couldn't be decoded.
+///
+/// See [`SendPort::post_error()`].
+pub const DECODE_FAILED_ERROR_CODE: i64 = -1;
+
+/// The type erased handler of a typed port.
+struct ErasedHandler(Box<dyn Fn(DartRuntime, CObjectMut<'_>) + Send + Sync>);
+
+/// A native port which decodes requests to `In` and replies with `Out`.
+///
+/// Requests are expected to be arrays of the form `[replyPort, payload]`.
+/// The payload is decoded with [`DartMessage::decode()`] and passed together
+/// with a [`Responder`] for the reply port to the handler.
+///
+/// If the payload can't be decoded an error with the code
+/// [`DECODE_FAILED_ERROR_CODE`] is posted to the reply port instead. Messages
+/// which are not of the request form are dropped.
+///
+/// Unlike a [`Port<T>`](super::Port), which only types the messages sent to a
+/// port, this is the receiving side of a request/response channel. The replies
+/// are posted to the reply port as encoded `Out` messages.
+pub struct TypedPort<In, Out> {
+    port: NativeRecvPort,
+    _types: PhantomData<fn(In) -> Out>,
+}
+
+impl<In, Out> TypedPort<In, Out>
+where
+    In: DartMessage + 'static,
+    Out: DartMessage + 'static,
+{
+    /// Creates a new port which calls `handler` for each request.
+    ///
+    /// The handler is called concurrently on dart's thread pool.
+    ///
+    /// # Errors
+    ///
+    /// If dart failed to create the port.
+    pub fn new(
+        rt: DartRuntime,
+        handler: impl Fn(DartRuntime, In, Responder<Out>) + Send + Sync + 'static,
+    ) -> Result<Self, PortCreationFailed> {
+        let port = rt.native_recv_port::<TypedPortHandler>()?;
+        let erased = move |rt, data: CObjectMut<'_>| {
+            let (reply, payload) = match data.as_array(rt) {
+                Some([reply, payload]) => (reply.as_send_port(rt).flatten(), payload),
+                _ => return,
+            };
+            let reply = match reply {
+                Some(reply) => reply,
+                None => return,
+            };
+            match In::decode(payload, rt) {
+                Ok(request) => handler(rt, request, Responder::new(reply)),
+                Err(err) => {
+                    let _ = reply.post_error(DECODE_FAILED_ERROR_CODE, &err.to_string());
+                }
+            }
+        };
+        state::insert(port.as_raw().0, ErasedHandler(Box::new(erased)));
+        Ok(Self {
+            port,
+            _types: PhantomData,
+        })
+    }
+
+    /// Returns the port to which requests are sent.
+    pub fn send_port(&self) -> SendPort {
+        *self.port
+    }
+}
+
+/// Sends the reply to a request received by a [`TypedPort`].
+///
+/// Like [`ReplyHandle`] this can be moved to another thread.
+pub struct Responder<Out> {
+    handle: ReplyHandle,
+    _type: PhantomData<fn(Out)>,
+}
+
+impl<Out> Responder<Out>
+where
+    Out: DartMessage,
+{
+    fn new(port: SendPort) -> Self {
+        Self {
+            handle: ReplyHandle::new(port),
+            _type: PhantomData,
+        }
+    }
+
+    /// Returns the port to which the reply is sent.
+    pub fn port(&self) -> SendPort {
+        self.handle.port()
+    }
+
+    /// Encodes and sends the reply.
+    ///
+    /// # Errors
+    ///
+    /// If encoding or posting the message failed.
+    pub fn reply(&self, reply: Out) -> Result<(), PostingFailure> {
+        self.port().try_post_cobject_mut(reply.encode()?.as_mut())
+    }
+}
+
+struct TypedPortHandler;
+
+impl NativeMessageHandler for TypedPortHandler {
+    const CONCURRENT_HANDLING: bool = true;
+    const NAME: &'static str = "typed port";
+
+    fn handle_message(rt: DartRuntime, ourself: &NativeRecvPort, data: CObjectMut<'_>) {
+        if let Some(handler) = state::get::<ErasedHandler>(ourself.as_raw().0) {
+            (handler.0)(rt, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::NulError;
+
+    use crate::{
+        cobject::{CObject, DecodeError},
+        ports::dispatch_message,
+        test_utils::posted_messages,
+    };
+
+    use super::*;
+
+    struct Add(i64, i64);
+
+    impl DartMessage for Add {
+        fn encode(self) -> Result<CObject, NulError> {
+            Ok(CObject::array(vec![
+                Box::new(CObject::int64(self.0)),
+                Box::new(CObject::int64(self.1)),
+            ]))
+        }
+
+        fn decode(obj: &CObjectMut<'_>, rt: DartRuntime) -> Result<Self, DecodeError> {
+            let (a, b) = obj.decode_tuple(rt)?;
+            Ok(Add(a, b))
+        }
+    }
+
+    struct Sum(i64);
+
+    impl DartMessage for Sum {
+        fn encode(self) -> Result<CObject, NulError> {
+            Ok(CObject::array(vec![Box::new(CObject::int64(self.0))]))
+        }
+
+        fn decode(obj: &CObjectMut<'_>, rt: DartRuntime) -> Result<Self, DecodeError> {
+            let (sum,) = obj.decode_tuple(rt)?;
+            Ok(Sum(sum))
+        }
+    }
+
+    #[test]
+    fn test_typed_round_trip() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let typed = TypedPort::<Add, Sum>::new(rt, |_, Add(a, b), responder| {
+            responder.reply(Sum(a + b)).unwrap();
+        })
+        .unwrap();
+        let mut replies = posted_messages(|reply_to| {
+            let mut request = CObject::array(vec![
+                Box::new(CObject::send_port(reply_to)),
+                Box::new(Add(2, 3).encode().unwrap()),
+            ]);
+            dispatch_message::<TypedPortHandler>(
+                rt,
+                typed.send_port().as_raw().0,
+                request.as_mut(),
+            );
+
+            let mut bad_request = CObject::array(vec![
+                Box::new(CObject::send_port(reply_to)),
+                Box::new(CObject::string("2 + 3").unwrap()),
+            ]);
+            dispatch_message::<TypedPortHandler>(
+                rt,
+                typed.send_port().as_raw().0,
+                bad_request.as_mut(),
+            );
+        });
+
+        assert_eq!(replies.len(), 2);
+        let Sum(sum) = Sum::decode(&replies[0].as_mut(), rt).unwrap();
+        assert_eq!(sum, 5);
+        assert!(matches!(
+            replies[1].as_mut().as_error(rt),
+            Some((DECODE_FAILED_ERROR_CODE, _))
+        ));
+    }
+}
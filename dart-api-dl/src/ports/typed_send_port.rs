@@ -0,0 +1,128 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Send ports restricted to a single message type.
+
+use std::{marker::PhantomData, ops::Deref};
+
+use crate::{
+    cobject::{CObjectMut, FromCObjectElement, IntoCObjectElement},
+    DartRuntime,
+};
+
+use super::{PostingFailure, SendPort};
+
+/// A [`SendPort`] through which only messages of type `T` are sent.
+///
+/// This makes the message type of a channel a compile time constraint:
+///
+/// ```compile_fail,E0308
+/// # use xayn_dart_api_dl::ports::{Port, SendPort};
+/// # fn send(port: SendPort) {
+/// let port = Port::<i64>::new(port);
+/// port.send("hello");
+/// # }
+/// ```
+///
+/// It derefs to the untyped [`SendPort`] for cases this doesn't cover.
+///
+/// This only types the sending side of a one-way channel, the messages are plain
+/// cobject elements. For typed requests which are answered through a reply port
+/// use a [`TypedPort`](super::TypedPort) on the receiving side instead, its
+/// requests are `[replyPort, payload]` arrays and not messages of a `Port<T>`.
+pub struct Port<T> {
+    port: SendPort,
+    _type: PhantomData<fn(T) -> T>,
+}
+
+impl<T> Port<T>
+where
+    T: IntoCObjectElement + FromCObjectElement,
+{
+    /// Wraps a port to which messages of type `T` are sent.
+    pub fn new(port: SendPort) -> Self {
+        Self {
+            port,
+            _type: PhantomData,
+        }
+    }
+
+    /// Encodes and posts the message.
+    ///
+    /// # Errors
+    ///
+    /// If encoding or posting the message failed.
+    pub fn send(&self, msg: T) -> Result<(), PostingFailure> {
+        self.port
+            .try_post_cobject_mut(msg.into_cobject_element()?.as_mut())
+    }
+
+    /// Decodes a message sent through a port of this type.
+    ///
+    /// Returns `None` if the object isn't a message of type `T`.
+    pub fn decode(obj: &CObjectMut<'_>, rt: DartRuntime) -> Option<T> {
+        T::from_cobject_element(obj, rt)
+    }
+
+    /// Returns the untyped port.
+    pub fn into_inner(self) -> SendPort {
+        self.port
+    }
+}
+
+impl<T> Clone for Port<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Port<T> {}
+
+impl<T> Deref for Port<T> {
+    type Target = SendPort;
+
+    fn deref(&self) -> &Self::Target {
+        &self.port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use static_assertions::assert_impl_all;
+
+    use crate::test_utils::posted_messages;
+
+    use super::*;
+
+    #[test]
+    fn test_static_assertions() {
+        assert_impl_all!(Port<String>: Send, Sync, Copy, Clone);
+    }
+
+    #[test]
+    fn test_send_and_decode() {
+        let rt = unsafe { DartRuntime::instance_unchecked() };
+        let mut messages = posted_messages(|port| {
+            let port = Port::<i64>::new(port);
+            let copy = port;
+            port.send(42).unwrap();
+            copy.post_string("untyped").unwrap();
+            assert_eq!(copy.into_inner().as_raw(), port.as_raw());
+        });
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(Port::<i64>::decode(&messages[0].as_mut(), rt), Some(42));
+        assert_eq!(Port::<i64>::decode(&messages[1].as_mut(), rt), None);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Test doubles for the dart port functions.
+//!
+//! In tests the `ports` module uses the function slots defined here instead
+//! of the ones from `dart-api-dl-sys`. Posting to a port only succeeds if it
+//! was registered as a [`loopback()`], in which case an owned copy of the
+//! message is delivered to the corresponding [`Inbox`].
+#![allow(non_upper_case_globals)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    os::raw::c_char,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+};
+
+use dart_api_dl_sys::{Dart_CObject, Dart_NativeMessageHandler_DL};
+use once_cell::sync::Lazy;
+
+use crate::{
+    cobject::{CObject, CObjectMut, SendableCopy},
+    ports::{DartPortId, SendPort},
+    DartRuntime,
+};
+
+pub(crate) static Dart_PostCObject_DL: Option<
+    unsafe extern "C" fn(port_id: DartPortId, message: *mut Dart_CObject) -> bool,
+> = Some(post_cobject);
+
+pub(crate) static Dart_PostInteger_DL: Option<
+    unsafe extern "C" fn(port_id: DartPortId, message: i64) -> bool,
+> = Some(post_integer);
+
+pub(crate) static Dart_NewNativePort_DL: Option<
+    unsafe extern "C" fn(
+        name: *const c_char,
+        handler: Dart_NativeMessageHandler_DL,
+        handle_concurrently: bool,
+    ) -> DartPortId,
+> = Some(new_native_port);
+
+pub(crate) static Dart_CloseNativePort_DL: Option<
+    unsafe extern "C" fn(native_port_id: DartPortId) -> bool,
+> = Some(close_native_port);
+
+static LOOPBACKS: Lazy<Mutex<HashMap<DartPortId, Sender<SendableCopy>>>> =
+    Lazy::new(Default::default);
+
+static CLOSED: Lazy<Mutex<HashSet<DartPortId>>> = Lazy::new(Default::default);
+
+// Start high to not collide with port ids hard coded in tests.
+static NEXT_PORT_ID: AtomicI64 = AtomicI64::new(1 << 32);
+
+/// Registration of a loopback port, unregisters the port on drop.
+pub(crate) struct Loopback {
+    id: DartPortId,
+}
+
+impl Loopback {
+    pub(crate) fn id(&self) -> DartPortId {
+        self.id
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        LOOPBACKS.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Receiving end of a loopback port.
+pub(crate) struct Inbox(Receiver<SendableCopy>);
+
+impl Inbox {
+    /// Returns the next message, `None` if the loopback was dropped.
+    pub(crate) fn recv(&self) -> Option<CObject> {
+        self.0.recv().ok().map(SendableCopy::into_inner)
+    }
+}
+
+/// Returns a port id which wasn't used before.
+pub(crate) fn unique_port_id() -> DartPortId {
+    NEXT_PORT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Registers a new port id to which messages can be posted.
+pub(crate) fn loopback() -> (Loopback, Inbox) {
+    let id = unique_port_id();
+    let (sender, receiver) = channel();
+    LOOPBACKS.lock().unwrap().insert(id, sender);
+    (Loopback { id }, Inbox(receiver))
+}
+
+/// Returns the messages which `post` posted to a new loopback port.
+pub(crate) fn posted_messages(post: impl FnOnce(SendPort)) -> Vec<CObject> {
+    let rt = unsafe { DartRuntime::instance_unchecked() };
+    let (loopback, inbox) = loopback();
+    post(rt.send_port_from_raw(loopback.id()).unwrap());
+    drop(loopback);
+    iter::from_fn(|| inbox.recv()).collect()
+}
+
+/// Returns `true` if the port was closed through `Dart_CloseNativePort_DL`.
+pub(crate) fn was_closed(id: DartPortId) -> bool {
+    CLOSED.lock().unwrap().contains(&id)
+}
+
+fn deliver(id: DartPortId, message: SendableCopy) -> bool {
+    let sender = LOOPBACKS.lock().unwrap().get(&id).cloned();
+    sender.map_or(false, |sender| sender.send(message).is_ok())
+}
+
+unsafe extern "C" fn post_cobject(port_id: DartPortId, message: *mut Dart_CObject) -> bool {
+    let rt = unsafe { DartRuntime::instance_unchecked() };
+    unsafe {
+        CObjectMut::with_pointer(message, |mut message| {
+            let copy = match message.sendable_copy(rt.into()) {
+                Ok(copy) => copy,
+                Err(_) => return false,
+            };
+            let delivered = deliver(port_id, copy);
+            if delivered {
+                // like dart we take ownership of the external typed data
+                message.finalize_external_typed_objects(rt);
+            }
+            delivered
+        })
+    }
+}
+
+unsafe extern "C" fn post_integer(port_id: DartPortId, message: i64) -> bool {
+    let rt = unsafe { DartRuntime::instance_unchecked() };
+    CObject::int64(message)
+        .as_mut()
+        .sendable_copy(rt.into())
+        .map_or(false, |copy| deliver(port_id, copy))
+}
+
+unsafe extern "C" fn new_native_port(
+    _name: *const c_char,
+    _handler: Dart_NativeMessageHandler_DL,
+    _handle_concurrently: bool,
+) -> DartPortId {
+    unique_port_id()
+}
+
+unsafe extern "C" fn close_native_port(native_port_id: DartPortId) -> bool {
+    CLOSED.lock().unwrap().insert(native_port_id)
+}
@@ -14,6 +14,15 @@
 
 use std::{convert::TryInto, process::abort, ptr::NonNull};
 
+#[cfg(feature = "trace-coercions")]
+pub(crate) use crate::diagnostics::coercion::quietly;
+
+/// Runs `f`, with the `trace-coercions` feature without tracing failed coercions.
+#[cfg(not(feature = "trace-coercions"))]
+pub(crate) fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
 /// Prepares a pointer and length value valid for a rust slice from a pointer and length value of a dart array.
 ///
 /// If a nullptr is passed in `NonNull::dangle()` is returned as pointer for the
@@ -0,0 +1,72 @@
+// Copyright 2022 Xayn AG
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests which count heap allocations.
+//!
+//! They are a separate test binary as they replace the global allocator.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    ffi::CString,
+};
+
+use xayn_dart_api_dl::{cobject::TypedDataRef, ports::ReplyBuilder};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// Number of allocations on this thread while counting, see [`count_allocations()`].
+    static ALLOCATIONS: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| {
+            if let Some(count) = allocations.get() {
+                allocations.set(Some(count + 1));
+            }
+        });
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Returns the number of heap allocations of the current thread while running `f`.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|allocations| allocations.set(Some(0)));
+    f();
+    ALLOCATIONS.with(Cell::take).unwrap_or_default()
+}
+
+#[test]
+fn test_borrowed_reply_does_not_allocate() {
+    let values = [1_i64, -2, 3];
+    let name = CString::new("scores").unwrap();
+
+    let allocations = count_allocations(|| {
+        let mut name = ReplyBuilder::string(&name);
+        let mut values = ReplyBuilder::typed_data(TypedDataRef::Int64(&values));
+        let mut total = ReplyBuilder::int64(2);
+        let mut elements = [&mut name, &mut values, &mut total];
+        let _reply = ReplyBuilder::array(&mut elements);
+    });
+    assert_eq!(allocations, 0);
+}
@@ -28,6 +28,7 @@ use dart_api_dl::{
     cobject::{CObject, CObjectMut, CObjectValuesRef},
     initialize_dart_api_dl,
     ports::{
+        assert_no_nul,
         DartPortId,
         NativeMessageHandler,
         NativeRecvPort,
@@ -35,9 +36,9 @@ use dart_api_dl::{
         PostingMessageFailed,
         SendPort,
     },
+    with_runtime,
     DartRuntime,
     InitData,
-    InitializationFailed,
 };
 use thiserror::Error;
 
@@ -67,12 +68,11 @@ pub extern "C" fn setup_cmd_handler(
     // We can't use Dart_Port_DL as cbindgen doesn't know about bindgen.
     respond_to: i64,
 ) -> bool {
-    setup_cmd_handler_inner(respond_to).is_ok()
+    log("setup-0");
+    with_runtime!(|rt| { setup_cmd_handler_inner(rt, respond_to).is_ok() } else false)
 }
 
-fn setup_cmd_handler_inner(respond_to: DartPortId) -> Result<(), SetupError> {
-    log("setup-0");
-    let rt = DartRuntime::instance()?;
+fn setup_cmd_handler_inner(rt: DartRuntime, respond_to: DartPortId) -> Result<(), SetupError> {
     log("setup-1");
     let send_port = rt
         .send_port_from_raw(respond_to)
@@ -104,7 +104,6 @@ static ADDER_THREAD: Lazy<Mutex<Sender<(i64, i64, SendPort)>>> = Lazy::new(|| {
 #[derive(Debug, Error)]
 #[error("setup failed")]
 enum SetupError {
-    InitFailed(#[from] InitializationFailed),
     PortCreatingFailed(#[from] PortCreationFailed),
     PortPostMessageFailed(#[from] PostingMessageFailed),
     MalformedMessage,
@@ -144,6 +143,37 @@ impl CmdHandler {
                 let msg = CObject::string("hy hy ho").map_err(|v| v.to_string())?;
                 respond_to.post_cobject(msg).map_err(|v| v.to_string())?;
             }
+            "echo float32" => {
+                let val = slice
+                    .get(1)
+                    .ok_or("missing float")?
+                    .as_float32(rt)
+                    .ok_or("argument not a double")?;
+                respond_to
+                    .post_cobject(CObject::float32(val))
+                    .map_err(|v| v.to_string())?;
+            }
+            "dl minor at least" => {
+                let minor = slice
+                    .get(1)
+                    .ok_or("missing minor version")?
+                    .as_int(rt)
+                    .ok_or("argument not a number")?;
+                let minor = minor.try_into().map_err(|_| "negative minor version")?;
+                respond_to
+                    .post_bool(rt.dl_minor_version_at_least(minor))
+                    .map_err(|v| v.to_string())?;
+            }
+            "shutdown guard" => {
+                rt.shutdown_guard()
+                    .post(&respond_to)
+                    .map_err(|v| v.to_string())?;
+            }
+            "is likely alive" => {
+                respond_to
+                    .post_bool(rt.is_likely_alive())
+                    .map_err(|v| v.to_string())?;
+            }
             "send etd" => {
                 let msg = CObject::external_typed_data(vec![1u8, 12, 33]);
                 respond_to.post_cobject(msg).map_err(|v| v.to_string())?;
@@ -161,7 +191,7 @@ impl CmdHandler {
 
 impl NativeMessageHandler for CmdHandler {
     const CONCURRENT_HANDLING: bool = true;
-    const NAME: &'static str = "adder";
+    const NAME: &'static str = assert_no_nul("adder");
 
     fn handle_message(rt: DartRuntime, _ourself: &NativeRecvPort, msg: CObjectMut<'_>) {
         log(format!("handle-msg-0: {:?}", msg));
@@ -19,6 +19,9 @@ import 'package:integration_tests/integration_tests.dart'
     show Commander, initialize;
 import 'package:test/test.dart';
 
+/// Kept alive until the VM stops, see `DartRuntime::shutdown_guard()`.
+Object? shutdownGuard;
+
 Future<void> main() async {
   setUpAll(() async {
     await initialize();
@@ -40,6 +43,24 @@ Future<void> main() async {
     expect(res, equals('Error: Adder was shutdown'));
   });
 
+  test('float32 round-trips', () async {
+    final dynamic res = await Commander.sendCmd('echo float32', [1.5]);
+    expect(res, equals(1.5));
+  });
+
+  test('dl minor version is detected conservatively', () async {
+    expect(await Commander.sendCmd('dl minor at least', [0]), isTrue);
+    expect(await Commander.sendCmd('dl minor at least', [1]), isFalse);
+    expect(await Commander.sendCmd('dl minor at least', [1000]), isFalse);
+  });
+
+  test('shutdown guard is received and kept alive', () async {
+    // it's finalized once the VM stops
+    shutdownGuard = await Commander.sendCmd('shutdown guard');
+    expect(shutdownGuard, isA<Uint8List>());
+    expect(await Commander.sendCmd('is likely alive'), isTrue);
+  });
+
   test('dart recv external typed data', () async {
     final dynamic res = await Commander.sendCmd('send etd');
     expect(res, equals([1, 12, 33]));